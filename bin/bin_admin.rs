@@ -0,0 +1,44 @@
+// This admin client sends one operator command to the server: halt, resume or cancel-only for a ticker
+
+use ses::server::stock_exchange::{
+    stock_exchange_admin_service_client::StockExchangeAdminServiceClient, RpcSetTickerStateRequest,
+    RpcTickerState,
+};
+
+pub mod admin {
+    #![allow(clippy::large_enum_variant)]
+    tonic::include_proto!("stockexchange");
+}
+
+fn parse_command(command: &str) -> Option<RpcTickerState> {
+    match command {
+        "halt" => Some(RpcTickerState::Halted),
+        "resume" => Some(RpcTickerState::Trading),
+        "cancel-only" => Some(RpcTickerState::CancelOnly),
+        _ => None,
+    }
+}
+
+#[tokio::main]
+async fn main() -> Result<(), Box<dyn std::error::Error>> {
+    let args: Vec<String> = std::env::args().collect();
+    let state = match &args[..] {
+        [_, _, command] => parse_command(command),
+        _ => None,
+    };
+    let Some(state) = state else {
+        eprintln!("Usage: {} <ticker> <halt | resume | cancel-only>", args[0]);
+        std::process::exit(1);
+    };
+    let request = RpcSetTickerStateRequest {
+        ticker: args[1].clone(),
+        state: state as i32,
+    };
+
+    let mut client = StockExchangeAdminServiceClient::connect("http://127.0.0.1:50052").await?;
+    match client.set_ticker_state(request).await {
+        Ok(response) => println!("{:?}", response.into_inner()),
+        Err(status) => println!("[Rejected] {}", status.message()),
+    }
+    Ok(())
+}
@@ -7,13 +7,19 @@ use ses::{
         rpc_order_request::{Login, NewOrder, Request},
         rpc_order_response::Response,
         stock_exchange_service_client::StockExchangeServiceClient,
-        RpcDirection, RpcLimitOrMarket, RpcOrderRequest, RpcOrderResponse, RpcTimeInForce,
+        RpcDirection, RpcLimitOrMarket, RpcOrderRequest, RpcOrderResponse, RpcPostOnly,
+        RpcSelfTradePrevention, RpcTimeInForce,
     },
-    types::common::{Direction, InvId, LimitOrMarket, Password, TimeInForce},
+    types::common::{
+        Contingency, Direction, InvId, LimitOrMarket, Password, PegReference, PostOnly, Price,
+        SelfTradePrevention, TimeInForce, Trail,
+    },
+    utils::wrap_decimal,
 };
-use tokio_stream::iter;
+use tokio_stream::{iter, pending, StreamExt};
 
 pub mod investor {
+    #![allow(clippy::large_enum_variant)]
     tonic::include_proto!("stockexchange");
 }
 
@@ -22,15 +28,31 @@ struct Instruction {
     ticker: String,
     direction: Direction,
     size: u32,
-    price: f32,
+    price: Price,
     limit_or_market: LimitOrMarket,
     time_in_force: TimeInForce,
+    #[serde(default)]
+    display_size: Option<u32>,
+    #[serde(default)]
+    hidden: bool,
+    #[serde(default)]
+    post_only: Option<PostOnly>,
+    #[serde(default)]
+    min_quantity: Option<u32>,
+    #[serde(default)]
+    self_trade_prevention: Option<SelfTradePrevention>,
+    #[serde(default)]
+    contingency: Option<Contingency>,
 }
 
 #[derive(Debug, Deserialize)]
 pub struct InvestorTest {
     id: InvId,
     password: Password,
+    #[serde(default)]
+    cancel_on_disconnect: bool,
+    #[serde(default)]
+    last_seqnum: Option<u64>, // resume after this out_seqnum, the responses since are replayed first
     instructions: Vec<Instruction>,
 }
 
@@ -44,19 +66,19 @@ impl InvestorTest {
     // read the instructions from the file and convert them to a list of rpc requests
     pub fn to_request_list(&self) -> Vec<RpcOrderRequest> {
         let mut requests: Vec<RpcOrderRequest> = Vec::new();
-        let mut seqnum = 0;
-
         let login_req = RpcOrderRequest {
             request: Some(Request::Login(Login {
-                seqnum,
+                seqnum: 0,
                 investor_id: self.id,
                 password: self.password.to_string(),
+                cancel_on_disconnect: self.cancel_on_disconnect,
+                last_seqnum: self.last_seqnum,
             })),
         };
         requests.push(login_req);
 
-        for instruction in &self.instructions {
-            seqnum += 1;
+        for (i, instruction) in self.instructions.iter().enumerate() {
+            let seqnum = i as u64 + 1;
             let new_order_req: RpcOrderRequest = RpcOrderRequest {
                 request: Some(Request::NewOrder(NewOrder {
                     seqnum,
@@ -66,14 +88,99 @@ impl InvestorTest {
                         Direction::Sell => RpcDirection::Sell.into(),
                     },
                     size: instruction.size,
-                    price: instruction.price,
+                    price: wrap_decimal(instruction.price),
                     limit_or_market: match instruction.limit_or_market {
                         LimitOrMarket::Limit => RpcLimitOrMarket::Limit.into(),
                         LimitOrMarket::Market => RpcLimitOrMarket::Market.into(),
+                        LimitOrMarket::StopMarket(_) => RpcLimitOrMarket::StopMarket.into(),
+                        LimitOrMarket::StopLimit(_) => RpcLimitOrMarket::StopLimit.into(),
+                        LimitOrMarket::Peg(PegReference::Primary, _) => {
+                            RpcLimitOrMarket::PrimaryPeg.into()
+                        }
+                        LimitOrMarket::Peg(PegReference::Midpoint, _) => {
+                            RpcLimitOrMarket::MidpointPeg.into()
+                        }
+                        LimitOrMarket::TrailingStop(_, None) => {
+                            RpcLimitOrMarket::TrailingStopMarket.into()
+                        }
+                        LimitOrMarket::TrailingStop(_, Some(_)) => {
+                            RpcLimitOrMarket::TrailingStopLimit.into()
+                        }
                     },
                     time_in_force: match instruction.time_in_force {
                         TimeInForce::Day => RpcTimeInForce::Day.into(),
                         TimeInForce::IOC => RpcTimeInForce::Ioc.into(),
+                        TimeInForce::FOK => RpcTimeInForce::Fok.into(),
+                        TimeInForce::GTC => RpcTimeInForce::Gtc.into(),
+                        TimeInForce::GTD(_) => RpcTimeInForce::Gtd.into(),
+                    },
+                    expire_time: match instruction.time_in_force {
+                        TimeInForce::GTD(expire_time) => expire_time,
+                        _ => 0,
+                    },
+                    stop_price: instruction
+                        .limit_or_market
+                        .trigger_price()
+                        .and_then(wrap_decimal),
+                    display_size: instruction.display_size.unwrap_or(0),
+                    hidden: instruction.hidden,
+                    post_only: match instruction.post_only {
+                        None => RpcPostOnly::PostOnlyNone.into(),
+                        Some(PostOnly::Reject) => RpcPostOnly::PostOnlyReject.into(),
+                        Some(PostOnly::Reprice) => RpcPostOnly::PostOnlyReprice.into(),
+                    },
+                    min_quantity: instruction.min_quantity.unwrap_or(0),
+                    self_trade_prevention: match instruction.self_trade_prevention {
+                        None => RpcSelfTradePrevention::StpNone.into(),
+                        Some(SelfTradePrevention::CancelNewest) => {
+                            RpcSelfTradePrevention::StpCancelNewest.into()
+                        }
+                        Some(SelfTradePrevention::CancelOldest) => {
+                            RpcSelfTradePrevention::StpCancelOldest.into()
+                        }
+                        Some(SelfTradePrevention::CancelBoth) => {
+                            RpcSelfTradePrevention::StpCancelBoth.into()
+                        }
+                        Some(SelfTradePrevention::DecrementAndCancel) => {
+                            RpcSelfTradePrevention::StpDecrementAndCancel.into()
+                        }
+                    },
+                    oco_order_id: match instruction.contingency {
+                        Some(Contingency::Oco(order_id)) => order_id,
+                        _ => 0,
+                    },
+                    take_profit: match instruction.contingency {
+                        Some(Contingency::Bracket { take_profit, .. }) => {
+                            take_profit.and_then(wrap_decimal)
+                        }
+                        _ => None,
+                    },
+                    stop_loss: match instruction.contingency {
+                        Some(Contingency::Bracket { stop_loss, .. }) => {
+                            stop_loss.and_then(wrap_decimal)
+                        }
+                        _ => None,
+                    },
+                    peg_offset: match instruction.limit_or_market {
+                        LimitOrMarket::Peg(_, offset) => wrap_decimal(offset),
+                        _ => None,
+                    },
+                    trail: match instruction.limit_or_market {
+                        LimitOrMarket::TrailingStop(Trail::Amount(trail), _)
+                        | LimitOrMarket::TrailingStop(Trail::Percent(trail), _) => {
+                            wrap_decimal(trail)
+                        }
+                        _ => None,
+                    },
+                    trail_percent: matches!(
+                        instruction.limit_or_market,
+                        LimitOrMarket::TrailingStop(Trail::Percent(_), _)
+                    ),
+                    limit_offset: match instruction.limit_or_market {
+                        LimitOrMarket::TrailingStop(_, limit_offset) => {
+                            limit_offset.and_then(wrap_decimal)
+                        }
+                        _ => None,
                     },
                 })),
             };
@@ -95,7 +202,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let mut client = StockExchangeServiceClient::connect("http://127.0.0.1:50051").await?;
     let mut response_stream = client
-        .send_order(iter(investor_test.to_request_list()))
+        // keep the request stream open, the server ends the session when it closes
+        .send_order(iter(investor_test.to_request_list()).chain(pending()))
         .await?
         .into_inner();
 
@@ -106,14 +214,21 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 }
 
 fn log_response(response: RpcOrderResponse) {
+    let out_seqnum = response.out_seqnum;
     let log = match response.response.unwrap() {
         Response::LoginAck(login_ack) => format!("{:?}", login_ack),
         Response::LoginRej(login_rej) => format!("{:?}", login_rej),
+        Response::LogoutAck(logout_ack) => format!("{:?}", logout_ack),
         Response::Ack(ack) => format!("{:?}", ack),
         Response::Rej(rej) => format!("{:?}", rej),
         Response::Fill(fill) => format!("{:?}", fill),
         Response::Dead(dead) => format!("{:?}", dead),
         Response::CancelRej(cancel_rej) => format!("{:?}", cancel_rej),
+        Response::AmendAck(amend_ack) => format!("{:?}", amend_ack),
+        Response::AmendRej(amend_rej) => format!("{:?}", amend_rej),
+        Response::Reduced(reduced) => format!("{:?}", reduced),
+        Response::Activated(activated) => format!("{:?}", activated),
+        Response::MarginCall(margin_call) => format!("{:?}", margin_call),
     };
-    println!("{}", log);
+    println!("[{}] {}", out_seqnum, log);
 }
@@ -1,11 +1,14 @@
-use ses::server::stock_exchange::stock_exchange_service_server;
+use ses::server::stock_exchange::{
+    stock_exchange_admin_service_server, stock_exchange_service_server,
+};
 use ses::server::StockExchangeServer;
 use tonic::transport::Server;
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let addr = "127.0.0.1:50051".parse().unwrap();
-    let mut builder = Server::builder();
+    // the admin service has no authentication: it gets its own port, for trusted operators only
+    let admin_addr = "127.0.0.1:50052".parse().unwrap();
 
     let args: Vec<String> = std::env::args().collect();
     if args.len() < 3 {
@@ -20,10 +23,20 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     let exchange_core =
         StockExchangeServer::new(investor_config.to_string(), stock_config.to_string());
+    tokio::spawn(exchange_core.clone().run_timer());
+    let admin_service =
+        stock_exchange_admin_service_server::StockExchangeAdminServiceServer::from_arc(
+            exchange_core.clone(),
+        );
     let exchange_service =
-        stock_exchange_service_server::StockExchangeServiceServer::new(exchange_core);
+        stock_exchange_service_server::StockExchangeServiceServer::from_arc(exchange_core);
 
-    builder.add_service(exchange_service).serve(addr).await?;
+    tokio::try_join!(
+        Server::builder().add_service(exchange_service).serve(addr),
+        Server::builder()
+            .add_service(admin_service)
+            .serve(admin_addr),
+    )?;
 
     Ok(())
 }
@@ -5,6 +5,7 @@ use ses::server::stock_exchange::{
 use tokio_stream::iter;
 
 pub mod subscriber {
+    #![allow(clippy::large_enum_variant)]
     tonic::include_proto!("stockexchange");
 }
 
@@ -31,6 +32,11 @@ fn log_response(response: RpcSubscribeResponse) {
         Response::Added(added) => format!("{:?}", added),
         Response::Removed(removed) => format!("{:?}", removed),
         Response::Executed(executed) => format!("{:?}", executed),
+        Response::Modified(modified) => format!("{:?}", modified),
+        Response::Replaced(replaced) => format!("{:?}", replaced),
+        Response::MarketStatus(status) => format!("{:?}", status),
+        Response::TickerStatus(status) => format!("{:?}", status),
+        Response::AuctionIndicative(indicative) => format!("{:?}", indicative),
     };
     println!("{}", log);
 }
@@ -2,32 +2,55 @@
 // -  contains the orderbook manager, event history, order info, account manager, and stock manager.
 // -  provide APIs for server to process requests and return triggered tasks for server to dispatch.
 
-use crate::types::account_manager::PotentialOrder;
-use crate::types::common::{Direction, InvId, OrderId, Password, SeqNum, Ticker};
+use crate::types::account_manager::{AccountUpdate, PotentialOrder};
+use crate::types::common::{
+    Cash, Contingency, Direction, InvId, LimitOrMarket, OrderId, Password, Price, SeqNum,
+    SessionPhase, Size, Ticker, TickerState, TimeInForce, Timestamp, Trail,
+};
+use crate::types::config::ThrottleConfig;
+use crate::types::event::{Event, MarketStatus};
 use crate::types::orderbook::{
-    CancelOrderRequest, NewOrderRequest, OrderbookLog, OrderbookRequest,
+    AmendOrderRequest, CancelOrderRequest, DeadReason, NewOrderRequest, OrderActivatedResponse,
+    OrderDeadResponse, OrderbookLog, OrderbookRequest,
+};
+use crate::types::portal::{
+    OrderResponse, PortalAmendOrderRequest, PortalNewOrderRequest, PortalRequest, PortalTask,
 };
-use crate::types::portal::{PortalNewOrderRequest, PortalRequest, PortalTask};
 use crate::utils::get_order_id;
 use std::vec;
 
 mod account;
 mod account_manager;
+mod book_side;
+mod contingent_manager;
 mod event_history;
+mod fee_manager;
+mod matching_policy;
 mod order_info;
 mod orderbook;
 mod orderbook_manager;
+mod risk_manager;
+mod session_manager;
 mod stock_manager;
+mod stop_manager;
 mod utils;
 
 use self::account::Account;
 use self::account_manager::AccountManager;
+use self::contingent_manager::{Bracket, ContingentAction, ContingentManager};
 use self::event_history::EventHistory;
+use self::fee_manager::FeeManager;
 use self::order_info::OrderInfo;
 use self::orderbook_manager::OrderbookManager;
+use self::risk_manager::{Holding, RiskManager, RiskOrder};
+use self::session_manager::SessionManager;
 use self::stock_manager::{StockManager, StockRecord};
-use self::utils::orderresponse_to_acc_update;
-use self::utils::{load_investors_from_config, load_stocks_from_config};
+use self::stop_manager::StopManager;
+use self::utils::{has_fills, orderresponse_to_acc_update, trailing_limit_price};
+use self::utils::{
+    load_fees_from_config, load_investors_from_config, load_session_from_config,
+    load_stocks_from_config,
+};
 
 pub struct Portal {
     orderbook_manager: OrderbookManager,
@@ -35,7 +58,13 @@ pub struct Portal {
     order_info: OrderInfo,
     account_manager: AccountManager,
     stock_manager: StockManager,
+    session_manager: SessionManager,
+    stop_manager: StopManager,
+    contingent_manager: ContingentManager,
+    fee_manager: FeeManager,
+    risk_manager: RiskManager,
     last_order_id: u64,
+    now: Timestamp, // latest time seen in requests, for orders the portal submits itself
 }
 
 impl Portal {
@@ -47,11 +76,24 @@ impl Portal {
         let mut account_manager = AccountManager::new();
         let mut stock_manager = StockManager::new();
 
-        // configure stocks
+        // configure stocks and trading session
+        let session_manager = SessionManager::new(load_session_from_config(stock_config.clone()));
+        let fee_manager = FeeManager::new(load_fees_from_config(stock_config.clone()));
         let stocks: Vec<(Ticker, StockRecord)> = load_stocks_from_config(stock_config);
         for (ticker, stock_rec) in stocks {
+            orderbook_manager.add_orderbook(
+                ticker.clone(),
+                stock_rec.mpf,
+                stock_rec.matching_policy,
+            );
+            if let Some(price_bands) = &stock_rec.price_bands {
+                orderbook_manager.set_price_bands(
+                    &ticker,
+                    price_bands.clone(),
+                    stock_rec.close_price,
+                );
+            }
             stock_manager.bind_stock(ticker.clone(), stock_rec);
-            orderbook_manager.add_orderbook(ticker.clone());
         }
         // configure investors
         let investors: Vec<Account> = load_investors_from_config(investor_config);
@@ -64,30 +106,87 @@ impl Portal {
             order_info,
             account_manager,
             stock_manager,
+            session_manager,
+            stop_manager: StopManager::new(),
+            contingent_manager: ContingentManager::new(),
+            fee_manager,
+            risk_manager: RiskManager::new(),
             last_order_id: 0,
+            now: 0,
         }
     }
 
     // Process a log, update portal, and return triggered tasks
     fn process_log(&mut self, log: OrderbookLog) -> PortalTask {
         match log {
-            OrderbookLog::OrderLog(order_resp) => {
+            OrderbookLog::OrderLog(mut order_resp) => {
                 let order_id = get_order_id(&order_resp);
-                let resting_size = self.order_info.get_resting(&order_id);
+                // an order that reserved nothing has nothing to release
+                let resting_size = match self.contingent_manager.is_unreserved(&order_id) {
+                    true => None,
+                    false => self.order_info.get_resting(&order_id),
+                };
                 let order_rec = self.order_info.get_order_record(&order_id).unwrap();
+                if let OrderResponse::OrderFill(fill) = &mut order_resp {
+                    fill.fee = self.fee_manager.charge(
+                        order_rec.inv_id,
+                        &fill.liquidity,
+                        fill.fill_size,
+                        fill.fill_price,
+                        self.now,
+                    );
+                }
                 // convert to PortalTask
-                let task = PortalTask::OrderResponse(order_rec.inv_id.clone(), order_resp.clone());
+                let task = PortalTask::OrderResponse(order_rec.inv_id, order_resp.clone());
                 // update portal
-                let updates = orderresponse_to_acc_update(order_resp, order_rec, resting_size);
+                let is_dead = matches!(order_resp, OrderResponse::OrderDead(_));
+                let reduced_size = match &order_resp {
+                    OrderResponse::OrderReduced(reduced) => Some(reduced.reduced_size),
+                    _ => None,
+                };
+                match &order_resp {
+                    OrderResponse::OrderFill(fill) => {
+                        self.contingent_manager.on_fill(&order_id, fill.fill_size)
+                    }
+                    OrderResponse::OrderDead(_) => self.contingent_manager.on_dead(&order_id),
+                    OrderResponse::OrderReduced(_) | OrderResponse::OrderActivated(_) => {}
+                }
+                let margin = self.account_manager.is_margin(&order_rec.inv_id);
+                // the value of an order was checked when it was accepted
+                let reserve_price = self
+                    .fee_manager
+                    .reserve_price(order_rec.limit_price)
+                    .unwrap();
+                let updates = orderresponse_to_acc_update(
+                    order_resp,
+                    order_rec,
+                    reserve_price,
+                    resting_size,
+                    margin,
+                );
                 for upd in updates {
                     self.account_manager.update(upd);
                 }
+                if is_dead {
+                    self.order_info.remove_resting(&order_id);
+                }
+                if let Some(reduced_size) = reduced_size {
+                    self.order_info.reduce_resting(&order_id, reduced_size);
+                }
                 task
             }
             OrderbookLog::EventLog(event) => {
                 // convert to PortalTask
                 let task = PortalTask::IncrementalEvent(event.clone());
                 // update portal
+                if let Event::OrderExecuted(executed) = &event {
+                    self.stock_manager
+                        .set_last_price(&executed.ticker, executed.execution_price);
+                    if let Some(tick) = self.stock_manager.get_mpf(&executed.ticker) {
+                        self.stop_manager
+                            .trail(&executed.ticker, executed.execution_price, tick);
+                    }
+                }
                 self.event_history.update_by_event(event.clone());
                 self.order_info.update_by_event(event.clone());
                 task
@@ -95,11 +194,164 @@ impl Portal {
         }
     }
 
+    // Process the logs of an orderbook request in order, then carry out what the responses triggered for
+    // contingent orders
     fn process_logs(&mut self, logs: Vec<OrderbookLog>) -> Vec<PortalTask> {
         let mut tasks = vec![];
         for log in logs {
             tasks.push(self.process_log(log));
         }
+        for action in self.contingent_manager.take_actions() {
+            tasks.extend(self.process_contingent_action(action));
+        }
+        tasks
+    }
+
+    // Kill an order that is not in the orderbook, returning its reservation
+    fn kill_order(&mut self, order_id: OrderId, reason: DeadReason) -> Vec<PortalTask> {
+        let dead = OrderResponse::OrderDead(OrderDeadResponse { order_id, reason });
+        self.process_logs(vec![OrderbookLog::OrderLog(dead)])
+    }
+
+    fn process_contingent_action(&mut self, action: ContingentAction) -> Vec<PortalTask> {
+        match action {
+            ContingentAction::Cancel(order_id) => match self.order_info.get_resting(&order_id) {
+                Some(_) => self.cancel_order(order_id),
+                None => vec![],
+            },
+            ContingentAction::Activate(entry_id, bracket) => {
+                self.activate_bracket(entry_id, bracket)
+            }
+        }
+    }
+
+    // Activate the children of a bracket entry for the size of one of its fills: a take-profit limit order
+    // and a stop-loss stop-market order in the opposite direction, linked as an OCO pair
+    // - children rest as long as the entry would have, a child that cannot be reserved is killed
+    fn activate_bracket(&mut self, entry_id: OrderId, bracket: Bracket) -> Vec<PortalTask> {
+        let entry = self.order_info.get_order_record(&entry_id).unwrap();
+        let (inv_id, ticker) = (entry.inv_id, entry.ticker.clone());
+        let child = |price: Price, limit_or_market: LimitOrMarket| PortalNewOrderRequest {
+            ticker: entry.ticker.clone(),
+            direction: entry.direction.opposite(),
+            size: bracket.size,
+            price,
+            limit_or_market,
+            time_in_force: match entry.time_in_force.rests() {
+                true => entry.time_in_force.clone(),
+                false => TimeInForce::Day,
+            },
+            timestamp: self.now,
+            display_size: None,
+            hidden: false,
+            post_only: None,
+            min_quantity: None,
+            self_trade_prevention: None,
+            contingency: None,
+        };
+        let take_profit = bracket
+            .take_profit
+            .map(|price| child(price, LimitOrMarket::Limit));
+        let stop_loss = bracket
+            .stop_loss
+            .map(|trigger| child(trigger, LimitOrMarket::StopMarket(trigger)));
+
+        let mut tasks = vec![];
+        let mut activated = |portal: &mut Portal, req: &PortalNewOrderRequest| {
+            let order_id = portal.generate_order_id();
+            portal.order_info.add_new_order(&order_id, &inv_id, req);
+            tasks.push(PortalTask::OrderResponse(
+                inv_id,
+                OrderResponse::OrderActivated(OrderActivatedResponse {
+                    order_id,
+                    parent_id: entry_id,
+                }),
+            ));
+            order_id
+        };
+        let take_profit_id = take_profit.as_ref().map(|req| activated(self, req));
+        let stop_loss_id = stop_loss.as_ref().map(|req| activated(self, req));
+
+        // the stop-loss reserves once it is triggered, the take-profit holds the reservation until then
+        if let (Some(stop_loss_id), Some(req)) = (stop_loss_id, stop_loss) {
+            self.contingent_manager.set_unreserved(stop_loss_id);
+            if let Some(take_profit_id) = take_profit_id {
+                self.contingent_manager
+                    .link_oco(take_profit_id, stop_loss_id);
+            }
+            self.stop_manager.add_stop(stop_loss_id, req.price, req);
+        }
+        if let (Some(take_profit_id), Some(req)) = (take_profit_id, take_profit) {
+            let p_order = self
+                .make_potential_order(inv_id, &req)
+                .filter(|p_order| self.valid_potential_order(&inv_id, p_order));
+            if let Some(p_order) = p_order {
+                self.account_manager
+                    .update_by_potential_order(inv_id, p_order);
+                tasks.extend(self.process_new_order(take_profit_id, req));
+            } else {
+                self.contingent_manager.set_unreserved(take_profit_id);
+                tasks.extend(self.kill_order(take_profit_id, DeadReason::InsufficientFunds));
+            }
+        }
+        tasks.extend(self.trigger_stops(&ticker));
+        tasks
+    }
+
+    // Link a new order to its contingent orders before it reaches the orderbook
+    // - a pending stop order that becomes an OCO leg releases its reservation until it is triggered
+    fn link_contingency(
+        &mut self,
+        inv_id: InvId,
+        order_id: OrderId,
+        contingency: &Option<Contingency>,
+    ) {
+        match contingency {
+            Some(Contingency::Oco(other)) => {
+                if self.stop_manager.contains(other)
+                    && !self.contingent_manager.is_unreserved(other)
+                {
+                    let resting_size = self.order_info.get_resting(other).unwrap();
+                    let limit_price = self.order_info.get_order_record(other).unwrap().limit_price;
+                    let change =
+                        self.make_reservation_change(inv_id, other, resting_size, 0, limit_price);
+                    if let Some((_, Some(release))) = change {
+                        self.account_manager.update(release);
+                    }
+                    self.contingent_manager.set_unreserved(*other);
+                }
+                self.contingent_manager.link_oco(*other, order_id);
+            }
+            Some(Contingency::Bracket {
+                take_profit,
+                stop_loss,
+            }) => self
+                .contingent_manager
+                .add_bracket(order_id, *take_profit, *stop_loss),
+            None => {}
+        }
+    }
+
+    // Cancel a live order, either a pending stop order or an order in the orderbook
+    fn cancel_order(&mut self, order_id: OrderId) -> Vec<PortalTask> {
+        if self.stop_manager.remove(&order_id).is_some() {
+            return self.kill_order(order_id, DeadReason::Cancelled);
+        }
+        let ticker = self.find_ticker_by_order_id(order_id).unwrap();
+        let req = OrderbookRequest::CancelOrder(CancelOrderRequest { order_id });
+        let logs = self.orderbook_manager.handle_orderbook_request(ticker, req);
+        self.process_logs(logs)
+    }
+
+    // Cancel all live orders of an investor, returning their reservations
+    fn cancel_all_orders(&mut self, inv_id: &InvId) -> Vec<PortalTask> {
+        let mut tasks = vec![];
+        for order_id in self.order_info.live_orders(inv_id) {
+            // an earlier cancel may have cancelled it, as the other leg of an OCO pair
+            if self.order_info.get_resting(&order_id).is_some() {
+                tasks.extend(self.cancel_order(order_id));
+            }
+        }
         tasks
     }
 
@@ -119,45 +371,130 @@ impl Portal {
         self.account_manager.try_login(inv_id, password)
     }
 
-    // Make a potential order from a new order request
-    fn make_potential_order(&self, req: &PortalNewOrderRequest) -> PotentialOrder {
-        match req.direction {
-            Direction::Buy => PotentialOrder::PotentialBuy(req.price * req.size as f32),
-            Direction::Sell => PotentialOrder::PotentialSell(req.size.clone(), req.ticker.clone()),
+    // End the session of an investor, cancelling all its open orders if the session dropped with
+    // cancel-on-disconnect
+    pub fn logout(&mut self, inv_id: &InvId, cancel_orders: bool) -> Vec<PortalTask> {
+        self.account_manager.logout(inv_id);
+        match cancel_orders {
+            true => self.cancel_all_orders(inv_id),
+            false => vec![],
         }
     }
 
-    // Use best price to fill in req
-    fn fill_in_market_order(&mut self, req: PortalNewOrderRequest) -> PortalNewOrderRequest {
-        match req.direction {
-            Direction::Buy => {
-                let best_price = self
-                    .orderbook_manager
-                    .best_sell_price(&req.ticker)
-                    .unwrap_or_else(|| self.stock_manager.get_close_price(&req.ticker).unwrap());
+    // Check if the sessions of an investor cancel its open orders when they drop, by default
+    pub fn cancel_on_disconnect(&self, inv_id: &InvId) -> bool {
+        self.account_manager
+            .get_account(inv_id)
+            .is_some_and(|account| account.cancel_on_disconnect)
+    }
 
-                PortalNewOrderRequest {
-                    price: best_price,
-                    ..req
-                }
-            }
-            Direction::Sell => {
-                let best_price = self
-                    .orderbook_manager
-                    .best_buy_price(&req.ticker)
-                    .unwrap_or_else(|| self.stock_manager.get_close_price(&req.ticker).unwrap());
+    // Message-rate throttle of an investor's sessions
+    pub fn get_throttle(&self, inv_id: &InvId) -> ThrottleConfig {
+        self.account_manager
+            .get_account(inv_id)
+            .map(|account| account.throttle.clone())
+            .unwrap_or_default()
+    }
 
-                PortalNewOrderRequest {
-                    price: best_price,
-                    ..req
-                }
+    // Halt, resume or put a ticker in cancel-only mode on behalf of an operator
+    // - resuming a halted ticker uncrosses the orders accumulated meanwhile
+    pub fn set_ticker_state(
+        &mut self,
+        ticker: &Ticker,
+        state: TickerState,
+    ) -> Result<Vec<PortalTask>, String> {
+        let Some(current) = self.orderbook_manager.ticker_state(ticker) else {
+            return Err("Invalid ticker state request: Unknown ticker".to_string());
+        };
+        if *current == state {
+            return Err(format!(
+                "Invalid ticker state request: Ticker is already {:?}",
+                state
+            ));
+        }
+        let logs = match state {
+            TickerState::VolatilityAuction => return Err(
+                "Invalid ticker state request: Volatility auctions are started by the price bands"
+                    .to_string(),
+            ),
+            TickerState::Trading => {
+                let in_session_auction = self.session_manager.in_auction();
+                self.orderbook_manager.resume(ticker, in_session_auction)
             }
+            TickerState::Halted | TickerState::CancelOnly => {
+                self.orderbook_manager.halt(ticker, state)
+            }
+        };
+        let mut tasks = self.process_logs(logs);
+        tasks.extend(self.trigger_stops(ticker));
+        if has_fills(&tasks) {
+            tasks.extend(self.process_margin_calls());
+        }
+        Ok(tasks)
+    }
+
+    // Get (price, aggregate size) of each price level of one side of a ticker's orderbook
+    pub fn depth(&self, ticker: &Ticker, direction: &Direction) -> Vec<(Price, Size)> {
+        self.orderbook_manager.depth(ticker, direction)
+    }
+
+    // Trigger price of a new trailing stop, trailing the last trade price, else the close price
+    fn trailing_trigger(&self, ticker: &Ticker, direction: &Direction, trail: &Trail) -> Price {
+        let reference = self.stock_manager.get_reference_price(ticker).unwrap();
+        let tick = self.stock_manager.get_mpf(ticker).unwrap();
+        trail.trigger_price(direction, reference, tick)
+    }
+
+    // Make a potential order from a new order request, None if the cash it reserves overflows
+    fn make_potential_order(
+        &self,
+        inv_id: InvId,
+        req: &PortalNewOrderRequest,
+    ) -> Option<PotentialOrder> {
+        if self.account_manager.is_margin(&inv_id) {
+            return Some(PotentialOrder::PotentialMargin(
+                req.ticker.clone(),
+                req.direction.clone(),
+                req.size,
+            ));
         }
+        match req.direction {
+            Direction::Buy => self
+                .fee_manager
+                .reserve_cash(req.price, req.size)
+                .map(PotentialOrder::PotentialBuy),
+            Direction::Sell => Some(PotentialOrder::PotentialSell(req.size, req.ticker.clone())),
+        }
+    }
+
+    // Price a market order at the worst price it reaches sweeping the book within the protection band,
+    // so that it is reserved for the worst case. None if there is no liquidity within the band
+    fn fill_in_market_order(&self, req: PortalNewOrderRequest) -> Option<PortalNewOrderRequest> {
+        let limit = self
+            .stock_manager
+            .market_protection_limit(&req.ticker, &req.direction);
+        let sweep_price =
+            self.orderbook_manager
+                .sweep_price(&req.ticker, &req.direction, req.size, limit)?;
+        Some(PortalNewOrderRequest {
+            price: sweep_price,
+            ..req
+        })
     }
 
     // process a request and return list of triggered tasks
     pub fn process_request(&mut self, seqnum: SeqNum, req: PortalRequest) -> Vec<PortalTask> {
-        match req {
+        let timestamp = match &req {
+            PortalRequest::NewOrder(_, req) => Some(req.timestamp),
+            PortalRequest::AmendOrder(_, req) => Some(req.timestamp),
+            PortalRequest::Timer(now) => Some(*now),
+            PortalRequest::EventHistory(_) | PortalRequest::CancelOrder(..) => None,
+        };
+        if let Some(timestamp) = timestamp {
+            self.now = self.now.max(timestamp);
+        }
+        let is_timer = matches!(req, PortalRequest::Timer(_));
+        let mut tasks = match req {
             PortalRequest::EventHistory(sub_id) => {
                 let events = self.event_history.get_history();
                 vec![PortalTask::EventHistory(sub_id, events)]
@@ -168,7 +505,179 @@ impl Portal {
             PortalRequest::CancelOrder(inv_id, order_id) => {
                 self.process_portal_cancel_order(inv_id, seqnum, order_id)
             }
+            PortalRequest::AmendOrder(inv_id, req) => {
+                self.process_portal_amend_order(inv_id, seqnum, req)
+            }
+            PortalRequest::Timer(now) => self.process_timer(now),
+        };
+        // margin calls only change with fills, or with the clock for calls that are due
+        if is_timer || has_fills(&tasks) {
+            tasks.extend(self.process_margin_calls());
+        }
+        tasks
+    }
+
+    // Call margin accounts whose equity fell below their maintenance margin, liquidate those whose call
+    // is due
+    fn process_margin_calls(&mut self) -> Vec<PortalTask> {
+        let calls = self
+            .account_manager
+            .update_margin_calls(self.now, |ticker| {
+                self.stock_manager.get_margin_rates(ticker)
+            });
+        let mut tasks = vec![];
+        for (inv_id, mut call) in calls {
+            let liquidation_tasks = match call.deadline <= self.now {
+                true => {
+                    let (liquidation_tasks, order_ids) = self.liquidate(inv_id);
+                    call.liquidation_orders = order_ids;
+                    liquidation_tasks
+                }
+                false => vec![],
+            };
+            tasks.push(PortalTask::MarginCall(inv_id, call));
+            tasks.extend(liquidation_tasks);
+        }
+        tasks
+    }
+
+    // Cancel all live orders of a margin account, then close each of its positions with an IOC market
+    // order. Return the tasks and the ids of those orders
+    // - positions of tickers not trading continuously or without liquidity are left for a later call
+    fn liquidate(&mut self, inv_id: InvId) -> (Vec<PortalTask>, Vec<OrderId>) {
+        let mut tasks = self.cancel_all_orders(&inv_id);
+        let mut order_ids = vec![];
+        if self.session_manager.phase() != &SessionPhase::Continuous {
+            return (tasks, order_ids);
+        }
+        for (ticker, position) in self.account_manager.open_positions(&inv_id) {
+            if self.orderbook_manager.ticker_state(&ticker) != Some(&TickerState::Trading) {
+                continue;
+            }
+            let req = PortalNewOrderRequest {
+                ticker: ticker.clone(),
+                direction: match position > 0 {
+                    true => Direction::Sell,
+                    false => Direction::Buy,
+                },
+                size: position.unsigned_abs() as Size,
+                price: Price::ZERO,
+                limit_or_market: LimitOrMarket::Market,
+                time_in_force: TimeInForce::IOC,
+                timestamp: self.now,
+                display_size: None,
+                hidden: false,
+                post_only: None,
+                min_quantity: None,
+                self_trade_prevention: None,
+                contingency: None,
+            };
+            let Some(req) = self.fill_in_market_order(req) else {
+                continue;
+            };
+            // a liquidation is forced: it needs no margin
+            let order_id = self.generate_order_id();
+            if let Some(p_order) = self.make_potential_order(inv_id, &req) {
+                self.account_manager
+                    .update_by_potential_order(inv_id, p_order);
+            }
+            self.order_info.add_new_order(&order_id, &inv_id, &req);
+            order_ids.push(order_id);
+            tasks.extend(self.process_new_order(order_id, req));
+            tasks.extend(self.trigger_stops(&ticker));
+        }
+        (tasks, order_ids)
+    }
+
+    // Periodic housekeeping driven by the server clock
+    fn process_timer(&mut self, now: Timestamp) -> Vec<PortalTask> {
+        let mut tasks = self.update_session(now);
+        tasks.extend(self.resume_trading(now));
+        tasks.extend(self.expire_orders(now));
+        tasks
+    }
+
+    // Re-open tickers whose volatility auction is over, only in the continuous session: an auction
+    // of the session ends with the session phase instead
+    fn resume_trading(&mut self, now: Timestamp) -> Vec<PortalTask> {
+        let mut tasks = vec![];
+        if self.session_manager.phase() != &SessionPhase::Continuous {
+            return tasks;
+        }
+        for ticker in self.stock_manager.tickers() {
+            let logs = self.orderbook_manager.resume_trading(&ticker, now);
+            if !logs.is_empty() {
+                tasks.extend(self.process_logs(logs));
+                tasks.extend(self.trigger_stops(&ticker));
+            }
+        }
+        tasks
+    }
+
+    // Move to the current session phase and publish the change
+    // - leaving a call auction uncrosses every orderbook, entering one starts it
+    // - Day orders expire at the close
+    fn update_session(&mut self, now: Timestamp) -> Vec<PortalTask> {
+        let Some(prev_phase) = self.session_manager.update(now) else {
+            return vec![];
+        };
+        let phase = self.session_manager.phase().clone();
+        let status = OrderbookLog::EventLog(Event::MarketStatus(MarketStatus {
+            phase: phase.clone(),
+        }));
+        let mut tasks = vec![self.process_log(status)];
+        if prev_phase.is_auction() {
+            tasks.extend(self.uncross_auctions(prev_phase == SessionPhase::ClosingAuction));
+        }
+        if phase.is_auction() {
+            self.start_auctions();
+        }
+        if phase == SessionPhase::Closed {
+            tasks.extend(self.expire_day_orders());
+        }
+        tasks
+    }
+
+    // Put every orderbook in a call auction, referenced to the close price
+    fn start_auctions(&mut self) {
+        for ticker in self.stock_manager.tickers() {
+            let reference_price = self.stock_manager.get_close_price(&ticker).unwrap();
+            self.orderbook_manager
+                .start_auction(&ticker, reference_price);
+        }
+    }
+
+    // Uncross every orderbook, the closing auction sets the close price of each ticker that traded
+    fn uncross_auctions(&mut self, is_closing: bool) -> Vec<PortalTask> {
+        let mut tasks = vec![];
+        for ticker in self.stock_manager.tickers() {
+            let (logs, clearing_price) = self.orderbook_manager.uncross(&ticker);
+            tasks.extend(self.process_logs(logs));
+            if let (true, Some(price)) = (is_closing, clearing_price) {
+                self.stock_manager.set_close_price(&ticker, price);
+                self.orderbook_manager.set_close_price(&ticker, price);
+            }
+            tasks.extend(self.trigger_stops(&ticker));
         }
+        tasks
+    }
+
+    // Kill all resting Day orders, returning their reservations
+    fn expire_day_orders(&mut self) -> Vec<PortalTask> {
+        let mut tasks = vec![];
+        for order_id in self.order_info.day_orders() {
+            tasks.extend(self.cancel_order(order_id));
+        }
+        tasks
+    }
+
+    // Kill good-till-date orders whose expiry has passed, returning their reservations
+    fn expire_orders(&mut self, now: Timestamp) -> Vec<PortalTask> {
+        let mut tasks = vec![];
+        for order_id in self.order_info.take_expired(now) {
+            tasks.extend(self.cancel_order(order_id));
+        }
+        tasks
     }
 
     // check if the new order request is valid and process it
@@ -178,24 +687,288 @@ impl Portal {
         seqnum: SeqNum,
         req: PortalNewOrderRequest,
     ) -> Vec<PortalTask> {
-        if self
-            .stock_manager
-            .check_valid_order(&req.ticker, &req.price, &req.size)
+        if !self.session_manager.accepts_orders() {
+            return vec![PortalTask::OrderReject(
+                inv_id,
+                seqnum,
+                "Invalid new order request: Market is not open".to_string(),
+            )];
+        }
+        let ticker_state = self.orderbook_manager.ticker_state(&req.ticker);
+        if ticker_state == Some(&TickerState::CancelOnly) {
+            return vec![PortalTask::OrderReject(
+                inv_id,
+                seqnum,
+                "Invalid new order request: Ticker only accepts cancellations".to_string(),
+            )];
+        }
+        let in_auction = self.session_manager.in_auction()
+            || ticker_state.is_some_and(|ticker_state| ticker_state.is_auction());
+        if in_auction
+            && (matches!(
+                req.limit_or_market,
+                LimitOrMarket::Market | LimitOrMarket::Peg(..)
+            ) || !req.time_in_force.rests())
         {
-            let req = self.fill_in_market_order(req);
-            let p_order: PotentialOrder = self.make_potential_order(&req);
+            return vec![PortalTask::OrderReject(
+                inv_id,
+                seqnum,
+                "Invalid new order request: Only resting limit orders are accepted in an auction"
+                    .to_string(),
+            )];
+        }
+        if req.post_only.is_some()
+            && (!matches!(
+                req.limit_or_market,
+                LimitOrMarket::Limit | LimitOrMarket::StopLimit(_)
+            ) || !req.time_in_force.rests())
+        {
+            return vec![PortalTask::OrderReject(
+                inv_id,
+                seqnum,
+                "Invalid new order request: Post-only requires a resting limit order".to_string(),
+            )];
+        }
+        if req.hidden
+            && (!matches!(
+                req.limit_or_market,
+                LimitOrMarket::Limit | LimitOrMarket::StopLimit(_)
+            ) || !req.time_in_force.rests()
+                || req.display_size.is_some())
+        {
+            return vec![PortalTask::OrderReject(
+                inv_id,
+                seqnum,
+                "Invalid new order request: Hidden orders must be resting limit orders without a display size"
+                    .to_string(),
+            )];
+        }
+        let mut peg_price = None;
+        if let LimitOrMarket::Peg(reference, offset) = &req.limit_or_market {
+            // the limit price caps a pegged order, a buy reserves at its cap so it needs one
+            let valid_peg = req.time_in_force.rests()
+                && (req.price > Price::ZERO
+                    || req.direction == Direction::Sell && req.price == Price::ZERO)
+                && *offset >= Price::ZERO
+                && self
+                    .stock_manager
+                    .check_valid_order(&req.ticker, offset, &req.size);
+            if !valid_peg {
+                return vec![PortalTask::OrderReject(
+                    inv_id,
+                    seqnum,
+                    "Invalid new order request: Pegged orders must rest, with a valid offset and a cap for buys"
+                        .to_string(),
+                )];
+            }
+            peg_price = self.orderbook_manager.peg_price(
+                &req.ticker,
+                &req.direction,
+                reference,
+                *offset,
+                req.price,
+            );
+            if peg_price.is_none() {
+                return vec![PortalTask::OrderReject(
+                    inv_id,
+                    seqnum,
+                    "Invalid new order request: No reference price for the pegged order"
+                        .to_string(),
+                )];
+            }
+        }
+        if let LimitOrMarket::TrailingStop(trail, limit_offset) = &req.limit_or_market {
+            let valid_distance = |distance: &Price| {
+                *distance >= Price::ZERO
+                    && self
+                        .stock_manager
+                        .check_valid_order(&req.ticker, distance, &req.size)
+            };
+            let valid_trail = match trail {
+                Trail::Amount(amount) => *amount > Price::ZERO && valid_distance(amount),
+                Trail::Percent(percent) => {
+                    *percent > Price::ZERO && *percent < Price::from_int(100)
+                }
+            };
+            if !valid_trail || !limit_offset.as_ref().is_none_or(valid_distance) {
+                return vec![PortalTask::OrderReject(
+                    inv_id,
+                    seqnum,
+                    "Invalid new order request: Invalid trail or limit offset".to_string(),
+                )];
+            }
+        }
+        if let Some(min_quantity) = req.min_quantity {
+            let valid_min_quantity = req.time_in_force == TimeInForce::IOC
+                && min_quantity <= req.size
+                && self
+                    .stock_manager
+                    .check_valid_order(&req.ticker, &req.price, &min_quantity);
+            if !valid_min_quantity {
+                return vec![PortalTask::OrderReject(
+                    inv_id,
+                    seqnum,
+                    "Invalid new order request: Minimum quantity requires an IOC order of at least that size"
+                        .to_string(),
+                )];
+            }
+        }
+        match &req.contingency {
+            Some(Contingency::Oco(other)) => {
+                let valid_oco = req.limit_or_market != LimitOrMarket::Market
+                    && req.time_in_force.rests()
+                    && self.order_info.valid_cancel_order(other, &inv_id)
+                    && self.find_ticker_by_order_id(*other).as_ref() == Some(&req.ticker)
+                    && !self.contingent_manager.is_linked(other);
+                if !valid_oco {
+                    return vec![PortalTask::OrderReject(
+                        inv_id,
+                        seqnum,
+                        "Invalid new order request: OCO legs must be resting orders of the same ticker, not linked yet"
+                            .to_string(),
+                    )];
+                }
+            }
+            Some(Contingency::Bracket {
+                take_profit,
+                stop_loss,
+            }) => {
+                let valid_child = |price: &Option<Price>| {
+                    price.is_none_or(|price| {
+                        price > Price::ZERO
+                            && self
+                                .stock_manager
+                                .check_valid_order(&req.ticker, &price, &req.size)
+                    })
+                };
+                if (take_profit.is_none() && stop_loss.is_none())
+                    || !valid_child(take_profit)
+                    || !valid_child(stop_loss)
+                {
+                    return vec![PortalTask::OrderReject(
+                        inv_id,
+                        seqnum,
+                        "Invalid new order request: Invalid take-profit or stop-loss price"
+                            .to_string(),
+                    )];
+                }
+            }
+            None => {}
+        }
+        if let TimeInForce::GTD(expiry) = req.time_in_force {
+            if expiry <= req.timestamp {
+                return vec![PortalTask::OrderReject(
+                    inv_id,
+                    seqnum,
+                    "Invalid new order request: Expiry time has passed".to_string(),
+                )];
+            }
+        }
+        // limit and stop-limit orders trade at their price, market orders are priced by the exchange
+        let valid_price = match req.limit_or_market {
+            LimitOrMarket::Limit | LimitOrMarket::StopLimit(_) => req.price > Price::ZERO,
+            _ => true,
+        };
+        let valid_trigger = req.limit_or_market.trigger_price().is_none_or(|trigger| {
+            trigger > Price::ZERO
+                && self
+                    .stock_manager
+                    .check_valid_order(&req.ticker, &trigger, &req.size)
+        });
+        let valid_display = req.display_size.is_none_or(|display_size| {
+            self.stock_manager
+                .check_valid_order(&req.ticker, &req.price, &display_size)
+        });
+        if valid_price
+            && valid_trigger
+            && valid_display
+            && self
+                .stock_manager
+                .check_valid_order(&req.ticker, &req.price, &req.size)
+        {
+            let trigger = match &req.limit_or_market {
+                LimitOrMarket::TrailingStop(trail, _) => {
+                    Some(self.trailing_trigger(&req.ticker, &req.direction, trail))
+                }
+                limit_or_market => limit_or_market.trigger_price(),
+            };
+            let req = match req.limit_or_market {
+                LimitOrMarket::Market => match self.fill_in_market_order(req) {
+                    Some(req) => req,
+                    None => {
+                        return vec![PortalTask::OrderReject(
+                        inv_id,
+                        seqnum,
+                        "Invalid new order request: No liquidity within the market protection band"
+                            .to_string(),
+                    )]
+                    }
+                },
+                // reserve at the trigger price until the stop is released
+                LimitOrMarket::StopMarket(trigger) => PortalNewOrderRequest {
+                    price: trigger,
+                    ..req
+                },
+                // reserve at the limit price it starts with, else its trigger price
+                LimitOrMarket::TrailingStop(_, limit_offset) => PortalNewOrderRequest {
+                    price: trailing_limit_price(&req.direction, trigger.unwrap(), limit_offset),
+                    ..req
+                },
+                LimitOrMarket::Limit | LimitOrMarket::StopLimit(_) | LimitOrMarket::Peg(..) => req,
+            };
+            // a pegged order may trade at its peg price, not only at its cap
+            let risk_order = RiskOrder {
+                ticker: req.ticker.clone(),
+                direction: req.direction.clone(),
+                size: req.size,
+                price: peg_price.unwrap_or(req.price),
+                reference: self.stock_manager.get_reference_price(&req.ticker),
+                replaced: Cash::ZERO,
+            };
             if self
-                .account_manager
-                .valid_potential_order(&inv_id, &p_order)
+                .fee_manager
+                .reserve_cash(risk_order.price.max(req.price), req.size)
+                .is_none()
             {
+                return vec![PortalTask::OrderReject(
+                    inv_id,
+                    seqnum,
+                    "Invalid new order request: Order value too large".to_string(),
+                )];
+            }
+            if let Err(reason) = self.check_risk(inv_id, &risk_order, None) {
+                return vec![PortalTask::OrderReject(
+                    inv_id,
+                    seqnum,
+                    format!("Invalid new order request: {}", reason),
+                )];
+            }
+            // the stop leg of an OCO pair reserves once it is triggered
+            let unreserved = matches!(req.contingency, Some(Contingency::Oco(_)))
+                && req.limit_or_market.is_stop();
+            let p_order = self
+                .make_potential_order(inv_id, &req)
+                .filter(|p_order| self.valid_potential_order(&inv_id, p_order));
+            if unreserved || p_order.is_some() {
                 // valid new order request
                 let order_id = self.generate_order_id();
                 let mut tasks: Vec<PortalTask> =
-                    vec![PortalTask::OrderAck(inv_id.clone(), seqnum, order_id)];
-                self.account_manager
-                    .update_by_potential_order(inv_id, p_order);
+                    vec![PortalTask::OrderAck(inv_id, seqnum, order_id)];
+                match p_order {
+                    Some(p_order) if !unreserved => self
+                        .account_manager
+                        .update_by_potential_order(inv_id, p_order),
+                    _ => self.contingent_manager.set_unreserved(order_id),
+                }
+                self.risk_manager.record(inv_id, &risk_order, self.now);
                 self.order_info.add_new_order(&order_id, &inv_id, &req);
-                tasks.extend(self.process_new_order(order_id, req));
+                self.link_contingency(inv_id, order_id, &req.contingency);
+                let ticker = req.ticker.clone();
+                match trigger {
+                    Some(trigger) => self.stop_manager.add_stop(order_id, trigger, req),
+                    None => tasks.extend(self.process_new_order(order_id, req)),
+                }
+                tasks.extend(self.trigger_stops(&ticker));
                 tasks
             } else {
                 // invalid new order request: insufficient cash or insufficient lot
@@ -225,10 +998,7 @@ impl Portal {
     ) -> Vec<PortalTask> {
         if self.order_info.valid_cancel_order(&order_id, &inv_id) {
             // valid cancel order request
-            let req = OrderbookRequest::CancelOrder(CancelOrderRequest { order_id });
-            let ticker = self.find_ticker_by_order_id(order_id).unwrap();
-            let logs = self.orderbook_manager.handle_orderbook_request(ticker, req);
-            self.process_logs(logs)
+            self.cancel_order(order_id)
         } else {
             // invalid cancel order request
             vec![PortalTask::CancelReject(
@@ -239,20 +1009,369 @@ impl Portal {
         }
     }
 
+    // Make the change of reservation when an order of resting_size is changed to size at price: an extra
+    // potential order when it needs more cash or positions, or the account update that releases the excess.
+    // None if the cash it reserves overflows
+    fn make_reservation_change(
+        &self,
+        inv_id: InvId,
+        order_id: &OrderId,
+        resting_size: Size,
+        size: Size,
+        price: Price,
+    ) -> Option<(Option<PotentialOrder>, Option<AccountUpdate>)> {
+        let order_rec = self.order_info.get_order_record(order_id).unwrap();
+        if self.account_manager.is_margin(&inv_id) {
+            let (ticker, direction) = (order_rec.ticker.clone(), order_rec.direction.clone());
+            return Some(match size > resting_size {
+                true => (
+                    Some(PotentialOrder::PotentialMargin(
+                        ticker,
+                        direction,
+                        size - resting_size,
+                    )),
+                    None,
+                ),
+                false => (
+                    None,
+                    Some(AccountUpdate::MinusOpen(
+                        inv_id,
+                        ticker,
+                        direction,
+                        resting_size - size,
+                    )),
+                ),
+            });
+        }
+        Some(match order_rec.direction {
+            Direction::Buy => {
+                let reserved = self
+                    .fee_manager
+                    .reserve_cash(order_rec.limit_price, resting_size)?;
+                let required = self.fee_manager.reserve_cash(price, size)?;
+                if required > reserved {
+                    (
+                        Some(PotentialOrder::PotentialBuy(required - reserved)),
+                        None,
+                    )
+                } else {
+                    (
+                        None,
+                        Some(AccountUpdate::UpdCash(inv_id, reserved - required)),
+                    )
+                }
+            }
+            Direction::Sell => {
+                let ticker = order_rec.ticker.clone();
+                if size > resting_size {
+                    let extra = size - resting_size;
+                    (Some(PotentialOrder::PotentialSell(extra, ticker)), None)
+                } else {
+                    let released = resting_size - size;
+                    (None, Some(AccountUpdate::AddPos(inv_id, ticker, released)))
+                }
+            }
+        })
+    }
+
+    // Check if an account can afford a potential order, margin is required at the current mark prices
+    fn valid_potential_order(&self, inv_id: &InvId, p_order: &PotentialOrder) -> bool {
+        self.account_manager
+            .valid_potential_order(inv_id, p_order, |ticker| {
+                self.stock_manager.get_margin_rates(ticker)
+            })
+    }
+
+    // Check an order against the risk limits of the investor and of its ticker, an amended order is left out
+    // of the holdings
+    fn check_risk(
+        &self,
+        inv_id: InvId,
+        order: &RiskOrder,
+        amended: Option<OrderId>,
+    ) -> Result<(), &'static str> {
+        let (Some(account), Some(ticker_limits)) = (
+            self.account_manager.get_account(&inv_id),
+            self.stock_manager.get_risk_limits(&order.ticker),
+        ) else {
+            return Ok(());
+        };
+        let mut holdings: Vec<Holding> = self
+            .stock_manager
+            .tickers()
+            .into_iter()
+            .map(|ticker| Holding {
+                mark: self.stock_manager.get_reference_price(&ticker).unwrap(),
+                position: account.positions.get(&ticker).copied().unwrap_or(0),
+                buys: 0,
+                sells: 0,
+                orders: 0,
+                ticker,
+            })
+            .collect();
+        for order_id in self.order_info.live_orders(&inv_id) {
+            if amended == Some(order_id) {
+                continue;
+            }
+            let (Some(order_rec), Some(size)) = (
+                self.order_info.get_order_record(&order_id),
+                self.order_info.resting.get(&order_id),
+            ) else {
+                continue;
+            };
+            let Some(holding) = holdings.iter_mut().find(|h| h.ticker == order_rec.ticker) else {
+                continue;
+            };
+            match order_rec.direction {
+                Direction::Buy => holding.buys += size,
+                Direction::Sell => holding.sells += size,
+            }
+            holding.orders += 1;
+        }
+        self.risk_manager.check(
+            inv_id,
+            order,
+            &account.risk_limits,
+            ticker_limits,
+            &holdings,
+            self.now,
+        )
+    }
+
+    // Apply a change of reservation, false if it overflows or the account cannot afford the extra
+    // reservation
+    fn apply_reservation_change(
+        &mut self,
+        inv_id: InvId,
+        change: Option<(Option<PotentialOrder>, Option<AccountUpdate>)>,
+    ) -> bool {
+        let Some((p_order, release)) = change else {
+            return false;
+        };
+        if let Some(p_order) = p_order {
+            if !self.valid_potential_order(&inv_id, &p_order) {
+                return false;
+            }
+            self.account_manager
+                .update_by_potential_order(inv_id, p_order);
+        }
+        if let Some(release) = release {
+            self.account_manager.update(release);
+        }
+        true
+    }
+
+    // Release stop orders triggered by the last trade price of a ticker into the orderbook, again
+    // and again as long as the released orders trade and trigger further stops
+    // - stops are only released while the ticker trades continuously
+    fn trigger_stops(&mut self, ticker: &Ticker) -> Vec<PortalTask> {
+        let mut tasks = vec![];
+        if self.session_manager.phase() != &SessionPhase::Continuous {
+            return tasks;
+        }
+        while self.orderbook_manager.ticker_state(ticker) == Some(&TickerState::Trading) {
+            let Some(last_price) = self.stock_manager.get_last_price(ticker) else {
+                break;
+            };
+            let triggered = self.stop_manager.triggered(ticker, last_price);
+            if triggered.is_empty() {
+                break;
+            }
+            for order_id in triggered {
+                // an earlier release may have cancelled it, as the other leg of an OCO pair
+                if let Some((trigger, req)) = self.stop_manager.remove(&order_id) {
+                    tasks.extend(self.release_stop(order_id, trigger, req));
+                }
+            }
+        }
+        tasks
+    }
+
+    // Send a triggered stop order to the orderbook, the other leg of its OCO pair is cancelled first
+    // - a stop-market order is priced at the market and its reservation moved to that price; it is
+    //   killed if there is no liquidity within the protection band or the account cannot afford it
+    // - a stop leg of an OCO pair reserves only now, it is killed if the account cannot afford it
+    // - a trailing stop-limit order is priced off the trigger price it reached
+    fn release_stop(
+        &mut self,
+        order_id: OrderId,
+        trigger: Price,
+        req: PortalNewOrderRequest,
+    ) -> Vec<PortalTask> {
+        let mut tasks = match self.contingent_manager.unlink_oco(&order_id) {
+            Some(other) => self.process_contingent_action(ContingentAction::Cancel(other)),
+            None => vec![],
+        };
+        let req = match req.limit_or_market {
+            LimitOrMarket::StopLimit(_) => PortalNewOrderRequest {
+                limit_or_market: LimitOrMarket::Limit,
+                ..req
+            },
+            LimitOrMarket::TrailingStop(_, limit_offset @ Some(_)) => PortalNewOrderRequest {
+                limit_or_market: LimitOrMarket::Limit,
+                price: trailing_limit_price(&req.direction, trigger, limit_offset),
+                ..req
+            },
+            _ => match self.fill_in_market_order(PortalNewOrderRequest {
+                limit_or_market: LimitOrMarket::Market,
+                ..req
+            }) {
+                Some(req) => req,
+                None => {
+                    tasks.extend(self.kill_order(order_id, DeadReason::Unfilled));
+                    return tasks;
+                }
+            },
+        };
+        let inv_id = self.order_info.get_order_record(&order_id).unwrap().inv_id;
+        let reserved_size = match self.contingent_manager.is_unreserved(&order_id) {
+            true => 0,
+            false => req.size,
+        };
+        let change =
+            self.make_reservation_change(inv_id, &order_id, reserved_size, req.size, req.price);
+        if !self.apply_reservation_change(inv_id, change) {
+            tasks.extend(self.kill_order(order_id, DeadReason::InsufficientFunds));
+            return tasks;
+        }
+        self.contingent_manager.set_reserved(&order_id);
+        self.order_info.amend_limit_price(&order_id, req.price);
+        tasks.extend(self.process_new_order(order_id, req));
+        tasks
+    }
+
+    // check if the amend order request is valid and process it
+    fn process_portal_amend_order(
+        &mut self,
+        inv_id: InvId,
+        seqnum: SeqNum,
+        req: PortalAmendOrderRequest,
+    ) -> Vec<PortalTask> {
+        if !self.session_manager.accepts_orders() {
+            return vec![PortalTask::AmendReject(
+                inv_id,
+                seqnum,
+                "Invalid amend order request: Market is not open".to_string(),
+            )];
+        }
+        if !self.order_info.valid_cancel_order(&req.order_id, &inv_id) {
+            return vec![PortalTask::AmendReject(
+                inv_id,
+                seqnum,
+                "Invalid amend order request: Order is not resting".to_string(),
+            )];
+        }
+        if self.stop_manager.contains(&req.order_id) {
+            return vec![PortalTask::AmendReject(
+                inv_id,
+                seqnum,
+                "Invalid amend order request: Stop order has not been triggered".to_string(),
+            )];
+        }
+        let ticker = self.find_ticker_by_order_id(req.order_id).unwrap();
+        if self.orderbook_manager.ticker_state(&ticker) == Some(&TickerState::CancelOnly) {
+            return vec![PortalTask::AmendReject(
+                inv_id,
+                seqnum,
+                "Invalid amend order request: Ticker only accepts cancellations".to_string(),
+            )];
+        }
+        if self.orderbook_manager.is_pegged(&ticker, &req.order_id) {
+            return vec![PortalTask::AmendReject(
+                inv_id,
+                seqnum,
+                "Invalid amend order request: Pegged orders cannot be amended".to_string(),
+            )];
+        }
+        if req.price <= Price::ZERO
+            || !self
+                .stock_manager
+                .check_valid_order(&ticker, &req.price, &req.size)
+        {
+            return vec![PortalTask::AmendReject(
+                inv_id,
+                seqnum,
+                "Invalid amend order request: Invalid price or size".to_string(),
+            )];
+        }
+        if self.fee_manager.reserve_cash(req.price, req.size).is_none() {
+            return vec![PortalTask::AmendReject(
+                inv_id,
+                seqnum,
+                "Invalid amend order request: Order value too large".to_string(),
+            )];
+        }
+        let resting_size = self.order_info.get_resting(&req.order_id).unwrap();
+        let order_rec = self.order_info.get_order_record(&req.order_id).unwrap();
+        let risk_order = RiskOrder {
+            ticker: ticker.clone(),
+            direction: order_rec.direction.clone(),
+            size: req.size,
+            price: req.price,
+            reference: self.stock_manager.get_reference_price(&ticker),
+            replaced: order_rec.limit_price * resting_size,
+        };
+        if let Err(reason) = self.check_risk(inv_id, &risk_order, Some(req.order_id)) {
+            return vec![PortalTask::AmendReject(
+                inv_id,
+                seqnum,
+                format!("Invalid amend order request: {}", reason),
+            )];
+        }
+        let change =
+            self.make_reservation_change(inv_id, &req.order_id, resting_size, req.size, req.price);
+        if !self.apply_reservation_change(inv_id, change) {
+            return vec![PortalTask::AmendReject(
+                inv_id,
+                seqnum,
+                "Invalid amend order request: Insufficient cash or lot to complete the order"
+                    .to_string(),
+            )];
+        }
+
+        // valid amend order request
+        self.risk_manager.record(inv_id, &risk_order, self.now);
+        self.order_info.amend_limit_price(&req.order_id, req.price);
+        self.order_info.set_resting(&req.order_id, req.size);
+        let mut tasks = vec![PortalTask::AmendAck(inv_id, seqnum, req.order_id)];
+        let order_book_req = OrderbookRequest::AmendOrder(AmendOrderRequest {
+            order_id: req.order_id,
+            size: req.size,
+            price: req.price,
+            timestamp: req.timestamp,
+        });
+        let logs = self
+            .orderbook_manager
+            .handle_orderbook_request(ticker.clone(), order_book_req);
+        tasks.extend(self.process_logs(logs));
+        tasks.extend(self.trigger_stops(&ticker));
+        tasks
+    }
+
     // process a valid new order request and return list of triggered tasks
     fn process_new_order(
         &mut self,
         order_id: OrderId,
         req: PortalNewOrderRequest,
     ) -> Vec<PortalTask> {
+        let inv_id = self.order_info.get_order_record(&order_id).unwrap().inv_id;
         let order_book_req = OrderbookRequest::NewOrder(NewOrderRequest {
             order_id,
+            inv_id,
             direction: req.direction,
             size: req.size,
             price: req.price,
             limit_or_market: req.limit_or_market,
             time_in_force: req.time_in_force,
             timestamp: req.timestamp,
+            // the orderbook shows nothing of an order with a display size of 0
+            display_size: match req.hidden {
+                true => Some(0),
+                false => req.display_size,
+            },
+            post_only: req.post_only,
+            min_quantity: req.min_quantity,
+            self_trade_prevention: req.self_trade_prevention,
         });
         let logs = self
             .orderbook_manager
@@ -260,3 +1379,1415 @@ impl Portal {
         self.process_logs(logs)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::common::{
+        Cash, Contingency, PegReference, Position, PostOnly, SelfTradePrevention, TickerState,
+        Trail,
+    };
+    use crate::types::event::{AuctionIndicative, OrderModified, OrderRemoved};
+    use crate::types::orderbook::Liquidity;
+    use crate::types::portal::MarginCallResponse;
+    use crate::utils::test_support::{write_config, INVESTORS, STOCKS};
+    use account_manager::MARGIN_CALL_SECS;
+
+    // Build a portal from config contents written to temporary files
+    fn make_portal(name: &str, investors: &str, stocks: &str) -> Portal {
+        let (investor_path, stock_path) = write_config(name, investors, stocks);
+        Portal::new(investor_path, stock_path)
+    }
+
+    fn limit_order(direction: Direction, size: Size, price: &str) -> PortalNewOrderRequest {
+        PortalNewOrderRequest {
+            ticker: "XYZ".to_string(),
+            direction,
+            size,
+            price: price.parse().unwrap(),
+            limit_or_market: LimitOrMarket::Limit,
+            time_in_force: TimeInForce::Day,
+            timestamp: 0,
+            display_size: None,
+            hidden: false,
+            post_only: None,
+            min_quantity: None,
+            self_trade_prevention: None,
+            contingency: None,
+        }
+    }
+
+    fn cash_of(portal: &Portal, inv_id: InvId) -> Cash {
+        portal.account_manager.get_account(&inv_id).unwrap().cash
+    }
+
+    fn position_of(portal: &Portal, inv_id: InvId) -> Position {
+        let account = portal.account_manager.get_account(&inv_id).unwrap();
+        account.positions.get("XYZ").copied().unwrap_or(0)
+    }
+
+    #[test]
+    fn test_cash_reconciles_after_many_fills() {
+        let mut portal = make_portal("reconcile", INVESTORS, STOCKS);
+        for seqnum in 0..5000 {
+            let sell = limit_order(Direction::Sell, 1, "10.01");
+            portal.process_request(seqnum, PortalRequest::NewOrder(2, sell));
+            // buyer reserves at 10.03 and is refunded the price improvement
+            let buy = limit_order(Direction::Buy, 1, "10.03");
+            portal.process_request(seqnum, PortalRequest::NewOrder(1, buy));
+        }
+        assert_eq!(cash_of(&portal, 1), "49950".parse().unwrap());
+        assert_eq!(cash_of(&portal, 2), "50050".parse().unwrap());
+        assert_eq!(position_of(&portal, 1), 5000);
+        assert_eq!(position_of(&portal, 2), 5000);
+    }
+
+    #[test]
+    fn test_reject_off_tick_price() {
+        let mut portal = make_portal("off_tick", INVESTORS, STOCKS);
+        let buy = limit_order(Direction::Buy, 1, "10.015");
+        let tasks = portal.process_request(1, PortalRequest::NewOrder(1, buy));
+        assert!(matches!(tasks[..], [PortalTask::OrderReject(1, 1, _)]));
+        assert_eq!(cash_of(&portal, 1), "100000".parse().unwrap());
+    }
+
+    #[test]
+    fn test_reject_non_positive_prices() {
+        let mut portal = make_portal("non_positive", INVESTORS, STOCKS);
+        let price = |price: &str| -> Price { price.parse().unwrap() };
+        let requests = [
+            limit_order(Direction::Sell, 100, "-5"),
+            limit_order(Direction::Sell, 100, "0"),
+            PortalNewOrderRequest {
+                limit_or_market: LimitOrMarket::StopLimit(price("9")),
+                ..limit_order(Direction::Sell, 100, "0")
+            },
+            PortalNewOrderRequest {
+                limit_or_market: LimitOrMarket::StopMarket(price("-9")),
+                ..limit_order(Direction::Sell, 100, "0")
+            },
+            PortalNewOrderRequest {
+                limit_or_market: LimitOrMarket::Peg(PegReference::Primary, Price::ZERO),
+                ..limit_order(Direction::Sell, 100, "-5")
+            },
+        ];
+        for (seqnum, req) in requests.into_iter().enumerate() {
+            let tasks = portal.process_request(seqnum as SeqNum, PortalRequest::NewOrder(2, req));
+            assert!(matches!(tasks[..], [PortalTask::OrderReject(2, _, _)]));
+        }
+        let bracket = PortalNewOrderRequest {
+            contingency: Some(Contingency::Bracket {
+                take_profit: Some(price("-12")),
+                stop_loss: None,
+            }),
+            ..limit_order(Direction::Buy, 100, "10")
+        };
+        let tasks = portal.process_request(6, PortalRequest::NewOrder(1, bracket));
+        assert!(matches!(&tasks[..], [PortalTask::OrderReject(1, 6, _)]));
+
+        // an amendment cannot move a resting order to a price of 0 either
+        let tasks = portal.process_request(
+            7,
+            PortalRequest::NewOrder(2, limit_order(Direction::Sell, 100, "11")),
+        );
+        assert!(matches!(tasks[0], PortalTask::OrderAck(2, 7, 1)));
+        let amend = PortalAmendOrderRequest {
+            order_id: 1,
+            size: 100,
+            price: Price::ZERO,
+            timestamp: 0,
+        };
+        let tasks = portal.process_request(8, PortalRequest::AmendOrder(2, amend));
+        assert!(matches!(&tasks[..], [PortalTask::AmendReject(2, 8, _)]));
+        assert_eq!(cash_of(&portal, 1), "100000".parse().unwrap());
+        assert_eq!(position_of(&portal, 2), 9900);
+    }
+
+    // The value of an order must fit in cash: a buy of 4e9 shares at 1e8 overflowed its reservation
+    #[test]
+    fn test_reject_order_value_overflow() {
+        let mut portal = make_portal("value_overflow", INVESTORS, STOCKS);
+        let too_large = |tasks: &[PortalTask]| match tasks {
+            [PortalTask::OrderReject(_, _, reason)] | [PortalTask::AmendReject(_, _, reason)] => {
+                reason.ends_with("Order value too large")
+            }
+            _ => false,
+        };
+        let tasks = portal.process_request(
+            1,
+            PortalRequest::NewOrder(1, limit_order(Direction::Buy, 4_000_000_000, "100000000")),
+        );
+        assert!(too_large(&tasks));
+        let tasks = portal.process_request(
+            2,
+            PortalRequest::NewOrder(2, limit_order(Direction::Sell, 4_000_000_000, "100000000")),
+        );
+        assert!(too_large(&tasks));
+
+        // an amendment cannot grow a resting order past it either
+        let tasks = portal.process_request(
+            3,
+            PortalRequest::NewOrder(1, limit_order(Direction::Buy, 100, "9")),
+        );
+        assert!(matches!(tasks[0], PortalTask::OrderAck(1, 3, 1)));
+        let amend = PortalAmendOrderRequest {
+            order_id: 1,
+            size: 4_000_000_000,
+            price: "100000000".parse().unwrap(),
+            timestamp: 0,
+        };
+        let tasks = portal.process_request(4, PortalRequest::AmendOrder(1, amend));
+        assert!(too_large(&tasks));
+        assert_eq!(cash_of(&portal, 1), "99100".parse().unwrap());
+        assert_eq!(
+            portal.depth(&"XYZ".to_string(), &Direction::Buy),
+            vec![(Price::from_int(9), 100)]
+        );
+    }
+
+    // A price band must let the price move by a tick: 0.05% of 10 is half a tick
+    #[test]
+    #[should_panic(expected = "Price bands of XYZ must be at least one tick wide")]
+    fn test_reject_band_narrower_than_a_tick() {
+        let stocks = r#"{"stocks": [
+            {"ticker": "XYZ", "close_price": 10.0, "lot_size": 1, "mpf": 0.01, "name": "XYZ Corp.",
+             "static_band": 20, "dynamic_band": 0.05}
+        ]}"#;
+        make_portal("narrow_band", INVESTORS, stocks);
+    }
+
+    // The shipped config passes the checks done on loading it
+    #[test]
+    fn test_load_shipped_config() {
+        Portal::new(
+            "config/investor_list.json".to_string(),
+            "config/stock_list.json".to_string(),
+        );
+    }
+
+    // A limit order keeps its own price: it used to be re-priced at the best opposite price, or at the
+    // close price on an empty side, so it could rest or trade at a price the investor never set
+    #[test]
+    fn test_limit_order_keeps_its_price() {
+        let mut portal = make_portal("limit_price", INVESTORS, STOCKS);
+        let xyz = "XYZ".to_string();
+        // an empty book: the buy rests at 9, not at the close price of 10
+        portal.process_request(
+            1,
+            PortalRequest::NewOrder(1, limit_order(Direction::Buy, 100, "9")),
+        );
+        assert_eq!(
+            portal.depth(&xyz, &Direction::Buy),
+            vec![(Price::from_int(9), 100)]
+        );
+        assert_eq!(cash_of(&portal, 1), "99100".parse().unwrap());
+        // a sell below the best bid trades at the bid, a sell above it rests at its own price
+        portal.process_request(
+            2,
+            PortalRequest::NewOrder(2, limit_order(Direction::Sell, 50, "8.5")),
+        );
+        portal.process_request(
+            3,
+            PortalRequest::NewOrder(2, limit_order(Direction::Sell, 50, "9.5")),
+        );
+        assert_eq!(cash_of(&portal, 2), "450".parse().unwrap());
+        assert_eq!(
+            portal.depth(&xyz, &Direction::Sell),
+            vec![("9.5".parse().unwrap(), 50)]
+        );
+    }
+
+    #[test]
+    fn test_amend_adjusts_reservation() {
+        let mut portal = make_portal("amend", INVESTORS, STOCKS);
+        let buy = limit_order(Direction::Buy, 100, "10");
+        portal.process_request(1, PortalRequest::NewOrder(1, buy));
+        assert_eq!(cash_of(&portal, 1), "99000".parse().unwrap());
+
+        // size up and price up: reserve the delta
+        let amend = PortalAmendOrderRequest {
+            order_id: 1,
+            size: 200,
+            price: "10.5".parse().unwrap(),
+            timestamp: 1,
+        };
+        let tasks = portal.process_request(2, PortalRequest::AmendOrder(1, amend));
+        assert!(matches!(tasks[0], PortalTask::AmendAck(1, 2, 1)));
+        assert_eq!(cash_of(&portal, 1), "97900".parse().unwrap());
+
+        // size down: release the excess
+        let amend = PortalAmendOrderRequest {
+            order_id: 1,
+            size: 50,
+            price: "10.5".parse().unwrap(),
+            timestamp: 2,
+        };
+        portal.process_request(3, PortalRequest::AmendOrder(1, amend));
+        assert_eq!(cash_of(&portal, 1), "99475".parse().unwrap());
+
+        // fill at the amended price, then the order is no longer amendable
+        let sell = limit_order(Direction::Sell, 50, "10.5");
+        portal.process_request(4, PortalRequest::NewOrder(2, sell));
+        assert_eq!(cash_of(&portal, 1), "99475".parse().unwrap());
+        assert_eq!(position_of(&portal, 1), 50);
+        let amend = PortalAmendOrderRequest {
+            order_id: 1,
+            size: 50,
+            price: "10".parse().unwrap(),
+            timestamp: 3,
+        };
+        let tasks = portal.process_request(5, PortalRequest::AmendOrder(1, amend));
+        assert!(matches!(tasks[..], [PortalTask::AmendReject(1, 5, _)]));
+    }
+
+    #[test]
+    fn test_amend_rejects_insufficient_position() {
+        let mut portal = make_portal("amend_sell", INVESTORS, STOCKS);
+        let sell = limit_order(Direction::Sell, 6000, "11");
+        portal.process_request(1, PortalRequest::NewOrder(2, sell));
+        assert_eq!(position_of(&portal, 2), 4000);
+
+        let amend = PortalAmendOrderRequest {
+            order_id: 1,
+            size: 12000,
+            price: "11".parse().unwrap(),
+            timestamp: 1,
+        };
+        let tasks = portal.process_request(2, PortalRequest::AmendOrder(2, amend));
+        assert!(matches!(tasks[..], [PortalTask::AmendReject(2, 2, _)]));
+        assert_eq!(position_of(&portal, 2), 4000);
+
+        let amend = PortalAmendOrderRequest {
+            order_id: 1,
+            size: 1000,
+            price: "11".parse().unwrap(),
+            timestamp: 2,
+        };
+        portal.process_request(3, PortalRequest::AmendOrder(2, amend));
+        assert_eq!(position_of(&portal, 2), 9000);
+    }
+
+    #[test]
+    fn test_gtd_expiry_returns_reservation() {
+        let mut portal = make_portal("gtd", INVESTORS, STOCKS);
+        let buy = PortalNewOrderRequest {
+            time_in_force: TimeInForce::GTD(100),
+            ..limit_order(Direction::Buy, 100, "10")
+        };
+        portal.process_request(1, PortalRequest::NewOrder(1, buy));
+        let sell = PortalNewOrderRequest {
+            time_in_force: TimeInForce::GTD(200),
+            ..limit_order(Direction::Sell, 100, "12")
+        };
+        portal.process_request(2, PortalRequest::NewOrder(2, sell));
+        assert_eq!(cash_of(&portal, 1), "99000".parse().unwrap());
+
+        assert!(portal
+            .process_request(0, PortalRequest::Timer(99))
+            .is_empty());
+        let tasks = portal.process_request(0, PortalRequest::Timer(150));
+        assert!(matches!(
+            tasks[..],
+            [
+                PortalTask::OrderResponse(1, OrderResponse::OrderDead(_)),
+                PortalTask::IncrementalEvent(Event::OrderRemoved(OrderRemoved { order_id: 1 }))
+            ]
+        ));
+        assert_eq!(cash_of(&portal, 1), "100000".parse().unwrap());
+        assert_eq!(portal.depth(&"XYZ".to_string(), &Direction::Buy), vec![]);
+
+        // an order that is already dead is not expired again
+        let cancel = PortalRequest::CancelOrder(2, 2);
+        portal.process_request(3, cancel);
+        assert!(portal
+            .process_request(0, PortalRequest::Timer(300))
+            .is_empty());
+        assert_eq!(position_of(&portal, 2), 10000);
+
+        // expiry in the past is rejected
+        let buy = PortalNewOrderRequest {
+            time_in_force: TimeInForce::GTD(0),
+            ..limit_order(Direction::Buy, 100, "10")
+        };
+        let tasks = portal.process_request(4, PortalRequest::NewOrder(1, buy));
+        assert!(matches!(tasks[..], [PortalTask::OrderReject(1, 4, _)]));
+    }
+
+    #[test]
+    fn test_unfilled_ioc_returns_reservation() {
+        let mut portal = make_portal("ioc", INVESTORS, STOCKS);
+        let sell = limit_order(Direction::Sell, 40, "10");
+        portal.process_request(1, PortalRequest::NewOrder(2, sell));
+        for time_in_force in [TimeInForce::IOC, TimeInForce::FOK] {
+            let buy = PortalNewOrderRequest {
+                time_in_force,
+                ..limit_order(Direction::Buy, 100, "10")
+            };
+            portal.process_request(2, PortalRequest::NewOrder(1, buy));
+        }
+        // IOC fills 40 and kills 60, FOK kills all 100
+        assert_eq!(cash_of(&portal, 1), "99600".parse().unwrap());
+        assert_eq!(position_of(&portal, 1), 40);
+        let cancel = PortalRequest::CancelOrder(1, 2);
+        let tasks = portal.process_request(3, cancel);
+        assert!(matches!(tasks[..], [PortalTask::CancelReject(1, 3, _)]));
+    }
+
+    #[test]
+    fn test_session_phases() {
+        let stocks = r#"{
+            "stocks": [
+                {"ticker": "XYZ", "close_price": 10.0, "lot_size": 1, "mpf": 0.01, "name": "XYZ Corp."}
+            ],
+            "session": {"pre_open": "08:00:00", "open": "09:30:00", "close": "16:00:00"}
+        }"#;
+        let mut portal = make_portal("session", INVESTORS, stocks);
+        let day = 20_000 * 86_400;
+        let is_status = |tasks: &[PortalTask], expected: SessionPhase| matches!(tasks, [PortalTask::IncrementalEvent(Event::MarketStatus(MarketStatus { phase }))] if *phase == expected);
+
+        // closed until the first clock update, then the opening auction takes resting orders only
+        let buy = limit_order(Direction::Buy, 100, "10");
+        let tasks = portal.process_request(1, PortalRequest::NewOrder(1, buy));
+        assert!(matches!(tasks[..], [PortalTask::OrderReject(1, 1, _)]));
+        let tasks = portal.process_request(0, PortalRequest::Timer(day + 8 * 3600));
+        assert!(is_status(&tasks, SessionPhase::PreOpen));
+        let ioc = PortalNewOrderRequest {
+            time_in_force: TimeInForce::IOC,
+            ..limit_order(Direction::Buy, 100, "10")
+        };
+        let tasks = portal.process_request(2, PortalRequest::NewOrder(1, ioc));
+        assert!(matches!(tasks[..], [PortalTask::OrderReject(1, 2, _)]));
+
+        // continuous: a Day order and a GTC order rest
+        let tasks = portal.process_request(0, PortalRequest::Timer(day + 9 * 3600 + 1800));
+        assert!(is_status(&tasks, SessionPhase::Continuous));
+        let buy = limit_order(Direction::Buy, 100, "10");
+        portal.process_request(3, PortalRequest::NewOrder(1, buy));
+        let gtc = PortalNewOrderRequest {
+            time_in_force: TimeInForce::GTC,
+            ..limit_order(Direction::Buy, 100, "9")
+        };
+        portal.process_request(4, PortalRequest::NewOrder(1, gtc));
+        assert_eq!(cash_of(&portal, 1), "98100".parse().unwrap());
+        assert!(portal
+            .process_request(0, PortalRequest::Timer(day + 12 * 3600))
+            .is_empty());
+
+        // close: the Day order is killed, the GTC order stays
+        let tasks = portal.process_request(0, PortalRequest::Timer(day + 16 * 3600));
+        assert!(matches!(
+            tasks[..],
+            [
+                PortalTask::IncrementalEvent(Event::MarketStatus(MarketStatus {
+                    phase: SessionPhase::Closed
+                })),
+                PortalTask::OrderResponse(1, OrderResponse::OrderDead(_)),
+                PortalTask::IncrementalEvent(Event::OrderRemoved(OrderRemoved { order_id: 1 }))
+            ]
+        ));
+        assert_eq!(cash_of(&portal, 1), "99100".parse().unwrap());
+        let xyz = "XYZ".to_string();
+        assert_eq!(
+            portal.depth(&xyz, &Direction::Buy),
+            vec![(Price::from_int(9), 100)]
+        );
+
+        // cancels are still accepted while closed, amends are not
+        let amend = PortalAmendOrderRequest {
+            order_id: 2,
+            size: 50,
+            price: "9".parse().unwrap(),
+            timestamp: 0,
+        };
+        let tasks = portal.process_request(5, PortalRequest::AmendOrder(1, amend));
+        assert!(matches!(tasks[..], [PortalTask::AmendReject(1, 5, _)]));
+        portal.process_request(6, PortalRequest::CancelOrder(1, 2));
+        assert_eq!(cash_of(&portal, 1), "100000".parse().unwrap());
+    }
+
+    #[test]
+    fn test_opening_and_closing_auctions() {
+        let stocks = r#"{
+            "stocks": [
+                {"ticker": "XYZ", "close_price": 10.0, "lot_size": 1, "mpf": 0.01, "name": "XYZ Corp."}
+            ],
+            "session": {"pre_open": "08:00:00", "open": "09:30:00", "closing_auction": "15:50:00", "close": "16:00:00"}
+        }"#;
+        let mut portal = make_portal("auction", INVESTORS, stocks);
+        let day = 20_000 * 86_400;
+        let xyz = "XYZ".to_string();
+        portal.process_request(0, PortalRequest::Timer(day + 8 * 3600));
+
+        // crossing orders rest without trading, each one publishes the indicative uncross
+        let buy = limit_order(Direction::Buy, 100, "10.2");
+        portal.process_request(1, PortalRequest::NewOrder(1, buy));
+        let sell = limit_order(Direction::Sell, 60, "9.9");
+        let tasks = portal.process_request(2, PortalRequest::NewOrder(2, sell));
+        assert!(matches!(
+            &tasks[..],
+            [
+                PortalTask::OrderAck(2, 2, 2),
+                PortalTask::IncrementalEvent(Event::OrderAdded(_)),
+                PortalTask::IncrementalEvent(Event::AuctionIndicative(AuctionIndicative {
+                    indicative_price: Some(price),
+                    matched_size: 60,
+                    imbalance_size: 40,
+                    imbalance_direction: Some(Direction::Buy),
+                    ..
+                }))
+            ] if *price == "9.9".parse().unwrap()
+        ));
+
+        // 9.9 and 10.2 match the same volume, the open uncrosses at 9.9 as it is closer to the reference
+        // price; the buyer keeps 40 resting
+        portal.process_request(0, PortalRequest::Timer(day + 9 * 3600 + 1800));
+        assert_eq!(position_of(&portal, 1), 60);
+        assert_eq!(cash_of(&portal, 2), "594".parse().unwrap());
+        assert_eq!(
+            portal.depth(&xyz, &Direction::Buy),
+            vec![("10.2".parse().unwrap(), 40)]
+        );
+        assert!(portal.depth(&xyz, &Direction::Sell).is_empty());
+
+        // the closing auction sets the close price; the unfilled Day order is killed at the close
+        portal.process_request(0, PortalRequest::Timer(day + 15 * 3600 + 3000));
+        let sell = limit_order(Direction::Sell, 10, "10.1");
+        portal.process_request(3, PortalRequest::NewOrder(2, sell));
+        assert_eq!(portal.depth(&xyz, &Direction::Sell).len(), 1);
+        portal.process_request(0, PortalRequest::Timer(day + 16 * 3600));
+        assert_eq!(
+            portal.stock_manager.get_close_price(&xyz),
+            Some("10.1".parse().unwrap())
+        );
+        assert_eq!(position_of(&portal, 1), 70);
+        assert!(portal.depth(&xyz, &Direction::Buy).is_empty());
+        assert_eq!(cash_of(&portal, 1), "99305".parse().unwrap());
+    }
+
+    #[test]
+    fn test_stop_orders_cascade() {
+        let mut portal = make_portal("stop", INVESTORS, STOCKS);
+        for (seqnum, price) in [(1, "10"), (2, "11"), (3, "12")] {
+            let sell = limit_order(Direction::Sell, 100, price);
+            portal.process_request(seqnum, PortalRequest::NewOrder(2, sell));
+        }
+        // held off-book: acked with nothing published
+        let stop_limit = PortalNewOrderRequest {
+            limit_or_market: LimitOrMarket::StopLimit("10".parse().unwrap()),
+            ..limit_order(Direction::Buy, 150, "11")
+        };
+        let tasks = portal.process_request(4, PortalRequest::NewOrder(1, stop_limit));
+        assert!(matches!(tasks[..], [PortalTask::OrderAck(1, 4, 4)]));
+        let stop_market = PortalNewOrderRequest {
+            limit_or_market: LimitOrMarket::StopMarket("11".parse().unwrap()),
+            ..limit_order(Direction::Buy, 100, "0")
+        };
+        portal.process_request(5, PortalRequest::NewOrder(1, stop_market));
+        assert_eq!(cash_of(&portal, 1), "97250".parse().unwrap());
+        let amend = PortalAmendOrderRequest {
+            order_id: 4,
+            size: 100,
+            price: "11".parse().unwrap(),
+            timestamp: 0,
+        };
+        let tasks = portal.process_request(6, PortalRequest::AmendOrder(1, amend));
+        assert!(matches!(tasks[..], [PortalTask::AmendReject(1, 6, _)]));
+
+        // a trade at 10 releases the stop-limit, which trades at 11 and releases the stop-market,
+        // which sweeps the rest of 11 and part of 12
+        let buy = limit_order(Direction::Buy, 10, "10");
+        portal.process_request(7, PortalRequest::NewOrder(1, buy));
+        assert_eq!(position_of(&portal, 1), 260);
+        assert_eq!(cash_of(&portal, 1), "97180".parse().unwrap());
+        assert_eq!(
+            portal.depth(&"XYZ".to_string(), &Direction::Sell),
+            vec![(Price::from_int(12), 40)]
+        );
+
+        // a pending stop is cancelled like a resting order
+        let stop_sell = PortalNewOrderRequest {
+            limit_or_market: LimitOrMarket::StopMarket("9".parse().unwrap()),
+            ..limit_order(Direction::Sell, 50, "0")
+        };
+        portal.process_request(8, PortalRequest::NewOrder(2, stop_sell));
+        assert_eq!(position_of(&portal, 2), 9650);
+        let tasks = portal.process_request(9, PortalRequest::CancelOrder(2, 7));
+        assert!(matches!(
+            tasks[..],
+            [PortalTask::OrderResponse(2, OrderResponse::OrderDead(_))]
+        ));
+        assert_eq!(position_of(&portal, 2), 9700);
+    }
+
+    #[test]
+    fn test_iceberg_reservation() {
+        let mut portal = make_portal("iceberg", INVESTORS, STOCKS);
+        let sell = PortalNewOrderRequest {
+            display_size: Some(100),
+            ..limit_order(Direction::Sell, 300, "10")
+        };
+        portal.process_request(1, PortalRequest::NewOrder(2, sell));
+        let buy = limit_order(Direction::Buy, 150, "10");
+        portal.process_request(2, PortalRequest::NewOrder(1, buy));
+        assert_eq!(cash_of(&portal, 2), "1500".parse().unwrap());
+        let xyz = "XYZ".to_string();
+        assert_eq!(
+            portal.depth(&xyz, &Direction::Sell),
+            vec![(Price::from_int(10), 50)]
+        );
+
+        // 150 open, 50 shown: an amend to 120 cuts the hidden reserve and keeps the slice
+        let amend = PortalAmendOrderRequest {
+            order_id: 1,
+            size: 120,
+            price: "10".parse().unwrap(),
+            timestamp: 3,
+        };
+        let tasks = portal.process_request(3, PortalRequest::AmendOrder(2, amend));
+        assert!(matches!(
+            tasks[..],
+            [
+                PortalTask::AmendAck(2, 3, 1),
+                PortalTask::IncrementalEvent(Event::OrderModified(OrderModified {
+                    resting_size: 50,
+                    ..
+                }))
+            ]
+        ));
+        assert_eq!(position_of(&portal, 2), 9730);
+        portal.process_request(4, PortalRequest::CancelOrder(2, 1));
+        assert_eq!(position_of(&portal, 2), 9850);
+
+        // the display size must be a valid size
+        let sell = PortalNewOrderRequest {
+            display_size: Some(0),
+            ..limit_order(Direction::Sell, 300, "10")
+        };
+        let tasks = portal.process_request(5, PortalRequest::NewOrder(2, sell));
+        assert!(matches!(tasks[..], [PortalTask::OrderReject(2, 5, _)]));
+    }
+
+    #[test]
+    fn test_post_only_and_min_quantity() {
+        let mut portal = make_portal("post_only", INVESTORS, STOCKS);
+        portal.process_request(
+            1,
+            PortalRequest::NewOrder(2, limit_order(Direction::Sell, 100, "10")),
+        );
+
+        // a repriced post-only buy rests one tick below the best offer, reserved at its own limit
+        let buy = PortalNewOrderRequest {
+            post_only: Some(PostOnly::Reprice),
+            ..limit_order(Direction::Buy, 50, "10.5")
+        };
+        portal.process_request(2, PortalRequest::NewOrder(1, buy));
+        let xyz = "XYZ".to_string();
+        assert_eq!(
+            portal.depth(&xyz, &Direction::Buy),
+            vec![("9.99".parse().unwrap(), 50)]
+        );
+        assert_eq!(cash_of(&portal, 1), "99475".parse().unwrap());
+        portal.process_request(3, PortalRequest::CancelOrder(1, 2));
+        assert_eq!(cash_of(&portal, 1), "100000".parse().unwrap());
+
+        // minimum quantity is only accepted on IOC orders
+        let buy = PortalNewOrderRequest {
+            min_quantity: Some(50),
+            ..limit_order(Direction::Buy, 100, "10")
+        };
+        let tasks = portal.process_request(4, PortalRequest::NewOrder(1, buy));
+        assert!(matches!(tasks[..], [PortalTask::OrderReject(1, 4, _)]));
+        let buy = PortalNewOrderRequest {
+            min_quantity: Some(150),
+            time_in_force: TimeInForce::IOC,
+            ..limit_order(Direction::Buy, 200, "10")
+        };
+        portal.process_request(5, PortalRequest::NewOrder(1, buy));
+        assert_eq!(cash_of(&portal, 1), "100000".parse().unwrap());
+        assert_eq!(position_of(&portal, 1), 0);
+    }
+
+    #[test]
+    fn test_self_trade_prevention_reservation() {
+        let investors = r#"{"investors": [
+            {"inv_id": 1, "account_name": "Carol", "password": "c", "stocks": {"XYZ": 1000}, "cash_amount": 10000.0}
+        ]}"#;
+        let mut portal = make_portal("self_trade", investors, STOCKS);
+        portal.process_request(
+            1,
+            PortalRequest::NewOrder(1, limit_order(Direction::Sell, 100, "10")),
+        );
+        assert_eq!(position_of(&portal, 1), 900);
+
+        // the resting sell is reduced by 40 and the buy is cancelled, both release their reservation
+        let buy = PortalNewOrderRequest {
+            self_trade_prevention: Some(SelfTradePrevention::DecrementAndCancel),
+            ..limit_order(Direction::Buy, 40, "10")
+        };
+        let tasks = portal.process_request(2, PortalRequest::NewOrder(1, buy));
+        assert!(matches!(
+            tasks[..],
+            [
+                PortalTask::OrderAck(1, 2, 2),
+                PortalTask::OrderResponse(1, OrderResponse::OrderReduced(_)),
+                PortalTask::IncrementalEvent(Event::OrderModified(_)),
+                PortalTask::OrderResponse(1, OrderResponse::OrderDead(_)),
+            ]
+        ));
+        assert_eq!(cash_of(&portal, 1), "10000".parse().unwrap());
+        assert_eq!(position_of(&portal, 1), 940);
+        portal.process_request(3, PortalRequest::CancelOrder(1, 1));
+        assert_eq!(position_of(&portal, 1), 1000);
+    }
+
+    #[test]
+    fn test_market_order_sweep() {
+        let stocks = r#"{"stocks": [
+            {"ticker": "XYZ", "close_price": 10.0, "lot_size": 1, "mpf": 0.01, "name": "XYZ Corp.", "market_protection": 10}
+        ]}"#;
+        let mut portal = make_portal("market_sweep", INVESTORS, stocks);
+        let market = |direction, size| PortalNewOrderRequest {
+            limit_or_market: LimitOrMarket::Market,
+            time_in_force: TimeInForce::IOC,
+            ..limit_order(direction, size, "0")
+        };
+        // nothing to trade with
+        let tasks =
+            portal.process_request(1, PortalRequest::NewOrder(1, market(Direction::Buy, 10)));
+        assert!(matches!(tasks[..], [PortalTask::OrderReject(1, 1, _)]));
+        for (seqnum, price) in [(2, "10"), (3, "10.5"), (4, "12")] {
+            let sell = limit_order(Direction::Sell, 100, price);
+            portal.process_request(seqnum, PortalRequest::NewOrder(2, sell));
+        }
+
+        // the band is 10% around the close price: the buy sweeps 10 and 10.5 but not 12
+        portal.process_request(5, PortalRequest::NewOrder(1, market(Direction::Buy, 250)));
+        assert_eq!(position_of(&portal, 1), 200);
+        assert_eq!(cash_of(&portal, 1), "97950".parse().unwrap());
+        let xyz = "XYZ".to_string();
+        assert_eq!(
+            portal.depth(&xyz, &Direction::Sell),
+            vec![(Price::from_int(12), 100)]
+        );
+
+        // the band moves with the last trade price, to 11.55
+        let tasks =
+            portal.process_request(6, PortalRequest::NewOrder(1, market(Direction::Buy, 10)));
+        assert!(matches!(tasks[..], [PortalTask::OrderReject(1, 6, _)]));
+    }
+
+    #[test]
+    fn test_volatility_auction() {
+        let stocks = r#"{"stocks": [
+            {"ticker": "XYZ", "close_price": 10.0, "lot_size": 1, "mpf": 0.01, "name": "XYZ Corp.", "static_band": 10, "volatility_auction": 60}
+        ]}"#;
+        let mut portal = make_portal("volatility_auction", INVESTORS, stocks);
+        let status_is = |task: &PortalTask, state: TickerState| matches!(task, PortalTask::IncrementalEvent(Event::TickerStatus(status)) if status.state == state);
+        for (seqnum, price) in [(1, "10"), (2, "11.5")] {
+            let sell = limit_order(Direction::Sell, 100, price);
+            portal.process_request(seqnum, PortalRequest::NewOrder(2, sell));
+        }
+
+        // 11.5 is outside the band of 9 to 11: the buy trades 100 @ 10 and rests in the auction
+        let buy = PortalNewOrderRequest {
+            timestamp: 100,
+            ..limit_order(Direction::Buy, 200, "12")
+        };
+        let tasks = portal.process_request(3, PortalRequest::NewOrder(1, buy));
+        assert!(tasks
+            .iter()
+            .any(|task| status_is(task, TickerState::VolatilityAuction)));
+        assert_eq!(position_of(&portal, 1), 100);
+        let tasks = portal.process_request(0, PortalRequest::Timer(159));
+        assert!(!tasks
+            .iter()
+            .any(|task| status_is(task, TickerState::Trading)));
+
+        // the re-opening auction uncrosses at 11.5
+        let tasks = portal.process_request(0, PortalRequest::Timer(160));
+        assert!(tasks
+            .iter()
+            .any(|task| status_is(task, TickerState::Trading)));
+        assert_eq!(position_of(&portal, 1), 200);
+        assert_eq!(cash_of(&portal, 1), "97850".parse().unwrap());
+    }
+
+    #[test]
+    fn test_trading_halts() {
+        let mut portal = make_portal("trading_halts", INVESTORS, STOCKS);
+        let xyz = "XYZ".to_string();
+        let status_is = |tasks: &[PortalTask], state: TickerState| matches!(tasks, [PortalTask::IncrementalEvent(Event::TickerStatus(status))] if status.state == state);
+        assert!(portal
+            .set_ticker_state(&"ABC".to_string(), TickerState::Halted)
+            .is_err());
+        assert!(portal
+            .set_ticker_state(&xyz, TickerState::VolatilityAuction)
+            .is_err());
+        let sell = limit_order(Direction::Sell, 100, "10");
+        portal.process_request(1, PortalRequest::NewOrder(2, sell));
+
+        // a halted ticker only takes resting limit orders, without matching
+        let tasks = portal.set_ticker_state(&xyz, TickerState::Halted).unwrap();
+        assert!(status_is(&tasks, TickerState::Halted));
+        assert!(portal.set_ticker_state(&xyz, TickerState::Halted).is_err());
+        portal.process_request(
+            2,
+            PortalRequest::NewOrder(1, limit_order(Direction::Buy, 60, "11")),
+        );
+        assert_eq!(position_of(&portal, 1), 0);
+        let ioc = PortalNewOrderRequest {
+            time_in_force: TimeInForce::IOC,
+            ..limit_order(Direction::Buy, 10, "11")
+        };
+        let tasks = portal.process_request(3, PortalRequest::NewOrder(1, ioc));
+        assert!(matches!(tasks[..], [PortalTask::OrderReject(1, 3, _)]));
+
+        // a cancel-only ticker rejects new orders and amends, but not cancels
+        let tasks = portal
+            .set_ticker_state(&xyz, TickerState::CancelOnly)
+            .unwrap();
+        assert!(status_is(&tasks, TickerState::CancelOnly));
+        let tasks = portal.process_request(
+            4,
+            PortalRequest::NewOrder(1, limit_order(Direction::Buy, 10, "11")),
+        );
+        assert!(matches!(tasks[..], [PortalTask::OrderReject(1, 4, _)]));
+        let amend = PortalAmendOrderRequest {
+            order_id: 2,
+            size: 50,
+            price: Price::from_int(11),
+            timestamp: 0,
+        };
+        let tasks = portal.process_request(5, PortalRequest::AmendOrder(1, amend));
+        assert!(matches!(tasks[..], [PortalTask::AmendReject(1, 5, _)]));
+
+        // resuming uncrosses the halted book at the close price, the closest to the reference
+        let tasks = portal.set_ticker_state(&xyz, TickerState::Trading).unwrap();
+        assert!(matches!(
+            tasks.last(),
+            Some(PortalTask::IncrementalEvent(Event::TickerStatus(status))) if status.state == TickerState::Trading
+        ));
+        assert_eq!(position_of(&portal, 1), 60);
+        assert_eq!(cash_of(&portal, 1), "99400".parse().unwrap());
+        let tasks = portal.process_request(6, PortalRequest::CancelOrder(2, 1));
+        assert!(matches!(tasks[..], [PortalTask::OrderResponse(2, _), ..]));
+        assert_eq!(position_of(&portal, 2), 9940);
+    }
+
+    #[test]
+    fn test_oco_orders() {
+        let mut portal = make_portal("oco_orders", INVESTORS, STOCKS);
+        let stop_sell = |trigger: &str, other| PortalNewOrderRequest {
+            limit_or_market: LimitOrMarket::StopMarket(trigger.parse().unwrap()),
+            contingency: Some(Contingency::Oco(other)),
+            ..limit_order(Direction::Sell, 100, trigger)
+        };
+        let cancelled = |tasks: &[PortalTask], order_id| {
+            tasks.iter().any(|task| {
+                matches!(task, PortalTask::OrderResponse(_, OrderResponse::OrderDead(dead))
+                    if dead.order_id == order_id && dead.reason == DeadReason::Cancelled)
+            })
+        };
+        // 1: take-profit sell 100 @ 12, 2: stop-loss sell 100 triggered at 9, which reserves nothing
+        portal.process_request(
+            1,
+            PortalRequest::NewOrder(2, limit_order(Direction::Sell, 100, "12")),
+        );
+        portal.process_request(2, PortalRequest::NewOrder(2, stop_sell("9", 1)));
+        assert_eq!(position_of(&portal, 2), 9900);
+
+        // a fill on one leg cancels the other
+        let tasks = portal.process_request(
+            3,
+            PortalRequest::NewOrder(1, limit_order(Direction::Buy, 50, "12")),
+        );
+        assert!(cancelled(&tasks, 2));
+        assert_eq!(position_of(&portal, 2), 9900);
+        assert_eq!(cash_of(&portal, 2), "600".parse().unwrap());
+
+        // only live orders of the same investor can be linked
+        let tasks = portal.process_request(4, PortalRequest::NewOrder(2, stop_sell("10", 2)));
+        assert!(matches!(tasks[..], [PortalTask::OrderReject(2, 4, _)]));
+        let buy = PortalNewOrderRequest {
+            contingency: Some(Contingency::Oco(1)),
+            ..limit_order(Direction::Buy, 10, "9")
+        };
+        let tasks = portal.process_request(5, PortalRequest::NewOrder(1, buy));
+        assert!(matches!(tasks[..], [PortalTask::OrderReject(1, 5, _)]));
+
+        // 4: a new stop leg for the rest of 1, triggered by a trade @ 10: it cancels 1 and sells @ 9.5
+        portal.process_request(6, PortalRequest::NewOrder(2, stop_sell("10", 1)));
+        portal.process_request(
+            7,
+            PortalRequest::NewOrder(1, limit_order(Direction::Buy, 100, "9.5")),
+        );
+        portal.process_request(
+            8,
+            PortalRequest::NewOrder(2, limit_order(Direction::Sell, 10, "10")),
+        );
+        let tasks = portal.process_request(
+            9,
+            PortalRequest::NewOrder(1, limit_order(Direction::Buy, 10, "10")),
+        );
+        assert!(cancelled(&tasks, 1));
+        assert_eq!(position_of(&portal, 1), 160);
+        assert_eq!(position_of(&portal, 2), 9840);
+        assert_eq!(cash_of(&portal, 2), "1650".parse().unwrap());
+        let xyz = "XYZ".to_string();
+        assert!(portal.depth(&xyz, &Direction::Sell).is_empty());
+        assert!(portal.depth(&xyz, &Direction::Buy).is_empty());
+    }
+
+    #[test]
+    fn test_bracket_order() {
+        let mut portal = make_portal("bracket_order", INVESTORS, STOCKS);
+        let bracket = |take_profit: Option<&str>, stop_loss: Option<&str>| {
+            Some(Contingency::Bracket {
+                take_profit: take_profit.map(|price| price.parse().unwrap()),
+                stop_loss: stop_loss.map(|price| price.parse().unwrap()),
+            })
+        };
+        let entry = PortalNewOrderRequest {
+            contingency: bracket(None, None),
+            ..limit_order(Direction::Buy, 150, "10")
+        };
+        let tasks = portal.process_request(1, PortalRequest::NewOrder(1, entry));
+        assert!(matches!(tasks[..], [PortalTask::OrderReject(1, 1, _)]));
+        portal.process_request(
+            2,
+            PortalRequest::NewOrder(2, limit_order(Direction::Sell, 100, "10")),
+        );
+
+        // 2: the IOC entry fills 100, then 3: take-profit sell 100 @ 12 and 4: stop-loss at 9
+        let entry = PortalNewOrderRequest {
+            time_in_force: TimeInForce::IOC,
+            contingency: bracket(Some("12"), Some("9")),
+            ..limit_order(Direction::Buy, 150, "10")
+        };
+        let tasks = portal.process_request(3, PortalRequest::NewOrder(1, entry));
+        let activated: Vec<OrderId> = tasks
+            .iter()
+            .filter_map(|task| match task {
+                PortalTask::OrderResponse(1, OrderResponse::OrderActivated(activated)) => {
+                    assert_eq!(activated.parent_id, 2);
+                    Some(activated.order_id)
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(activated, vec![3, 4]);
+        assert_eq!(position_of(&portal, 1), 0);
+        assert_eq!(cash_of(&portal, 1), "99000".parse().unwrap());
+        let xyz = "XYZ".to_string();
+        assert_eq!(
+            portal.depth(&xyz, &Direction::Sell),
+            vec![(Price::from_int(12), 100)]
+        );
+
+        // the take-profit trades, which cancels the stop-loss
+        let tasks = portal.process_request(
+            4,
+            PortalRequest::NewOrder(2, limit_order(Direction::Buy, 50, "12")),
+        );
+        assert!(tasks.iter().any(|task| matches!(task,
+            PortalTask::OrderResponse(1, OrderResponse::OrderDead(dead)) if dead.order_id == 4)));
+        assert_eq!(cash_of(&portal, 1), "99600".parse().unwrap());
+        portal.process_request(5, PortalRequest::CancelOrder(1, 3));
+        assert_eq!(position_of(&portal, 1), 50);
+
+        // 6: a resting entry gets children for each fill, 8: take-profit sell 40 @ 11 while it rests
+        let entry = PortalNewOrderRequest {
+            contingency: bracket(Some("11"), None),
+            ..limit_order(Direction::Buy, 100, "10")
+        };
+        portal.process_request(6, PortalRequest::NewOrder(1, entry));
+        let tasks = portal.process_request(
+            7,
+            PortalRequest::NewOrder(2, limit_order(Direction::Sell, 40, "10")),
+        );
+        assert!(tasks.iter().any(|task| matches!(task,
+            PortalTask::OrderResponse(1, OrderResponse::OrderActivated(activated))
+                if activated.order_id == 8 && activated.parent_id == 6)));
+        assert_eq!(
+            portal.depth(&xyz, &Direction::Sell),
+            vec![(Price::from_int(11), 40)]
+        );
+        assert_eq!(
+            portal.depth(&xyz, &Direction::Buy),
+            vec![(Price::from_int(10), 60)]
+        );
+        // 10: take-profit sell 60 @ 11 for the rest of the entry
+        portal.process_request(
+            8,
+            PortalRequest::NewOrder(2, limit_order(Direction::Sell, 60, "10")),
+        );
+        assert_eq!(
+            portal.depth(&xyz, &Direction::Sell),
+            vec![(Price::from_int(11), 100)]
+        );
+        assert_eq!(position_of(&portal, 1), 50);
+    }
+
+    #[test]
+    fn test_pegged_orders() {
+        let mut portal = make_portal("pegged_orders", INVESTORS, STOCKS);
+        let midpoint = |direction, size, cap: &str| PortalNewOrderRequest {
+            limit_or_market: LimitOrMarket::Peg(PegReference::Midpoint, Price::ZERO),
+            ..limit_order(direction, size, cap)
+        };
+        portal.process_request(
+            1,
+            PortalRequest::NewOrder(2, limit_order(Direction::Sell, 100, "10.01")),
+        );
+        portal.process_request(
+            2,
+            PortalRequest::NewOrder(1, limit_order(Direction::Buy, 100, "10")),
+        );
+
+        // a buy peg needs a cap, it reserves at its cap
+        let tasks = portal.process_request(
+            3,
+            PortalRequest::NewOrder(1, midpoint(Direction::Buy, 50, "0")),
+        );
+        assert!(matches!(tasks[..], [PortalTask::OrderReject(1, 3, _)]));
+        portal.process_request(
+            4,
+            PortalRequest::NewOrder(1, midpoint(Direction::Buy, 50, "10.5")),
+        );
+        assert_eq!(cash_of(&portal, 1), "98475".parse().unwrap());
+        assert_eq!(
+            portal.depth(&"XYZ".to_string(), &Direction::Buy)[0],
+            ("10.005".parse().unwrap(), 50)
+        );
+
+        // 3 trades with a midpoint sell at 10.005, the rest of its reservation is returned
+        portal.process_request(
+            5,
+            PortalRequest::NewOrder(2, midpoint(Direction::Sell, 20, "0")),
+        );
+        assert_eq!(cash_of(&portal, 1), "98484.9".parse().unwrap());
+        assert_eq!(cash_of(&portal, 2), "200.1".parse().unwrap());
+        assert_eq!(position_of(&portal, 1), 20);
+
+        // pegged orders cannot be amended, only cancelled
+        let amend = PortalAmendOrderRequest {
+            order_id: 3,
+            size: 30,
+            price: "11".parse().unwrap(),
+            timestamp: 0,
+        };
+        let tasks = portal.process_request(6, PortalRequest::AmendOrder(1, amend));
+        assert!(matches!(tasks[..], [PortalTask::AmendReject(1, 6, _)]));
+        portal.process_request(7, PortalRequest::CancelOrder(1, 3));
+        assert_eq!(cash_of(&portal, 1), "98799.9".parse().unwrap());
+    }
+
+    #[test]
+    fn test_trailing_stop() {
+        let mut portal = make_portal("trailing_stop", INVESTORS, STOCKS);
+        let trailing = |trail| PortalNewOrderRequest {
+            limit_or_market: LimitOrMarket::TrailingStop(trail, Some("0.2".parse().unwrap())),
+            ..limit_order(Direction::Sell, 100, "0")
+        };
+        let tasks = portal.process_request(
+            1,
+            PortalRequest::NewOrder(2, trailing(Trail::Percent(Price::ZERO))),
+        );
+        assert!(matches!(tasks[..], [PortalTask::OrderReject(2, 1, _)]));
+
+        // 1: trigger 1 below the close price, then 1 below the trade @ 12
+        let trail = Trail::Amount("1".parse().unwrap());
+        portal.process_request(2, PortalRequest::NewOrder(2, trailing(trail)));
+        assert_eq!(position_of(&portal, 2), 9900);
+        portal.process_request(
+            3,
+            PortalRequest::NewOrder(2, limit_order(Direction::Sell, 10, "12")),
+        );
+        portal.process_request(
+            4,
+            PortalRequest::NewOrder(1, limit_order(Direction::Buy, 10, "12")),
+        );
+        portal.process_request(
+            5,
+            PortalRequest::NewOrder(1, limit_order(Direction::Buy, 200, "10.9")),
+        );
+
+        // a trade @ 11 triggers 1: a sell limit @ 10.8, which trades @ 10.9
+        portal.process_request(
+            6,
+            PortalRequest::NewOrder(2, limit_order(Direction::Sell, 10, "11")),
+        );
+        let tasks = portal.process_request(
+            7,
+            PortalRequest::NewOrder(1, limit_order(Direction::Buy, 10, "11")),
+        );
+        let filled = tasks.iter().any(|task| {
+            matches!(task, PortalTask::OrderResponse(2, OrderResponse::OrderFill(fill))
+                if fill.order_id == 1 && fill.fill_price == "10.9".parse().unwrap())
+        });
+        assert!(filled);
+        assert_eq!(position_of(&portal, 1), 120);
+        assert_eq!(position_of(&portal, 2), 9880);
+        assert_eq!(cash_of(&portal, 1), "97590".parse().unwrap());
+        assert_eq!(cash_of(&portal, 2), "1320".parse().unwrap());
+    }
+
+    #[test]
+    fn test_hidden_orders() {
+        let mut portal = make_portal("hidden_orders", INVESTORS, STOCKS);
+        let hidden_sell = |limit_or_market| PortalNewOrderRequest {
+            limit_or_market,
+            hidden: true,
+            ..limit_order(Direction::Sell, 100, "10")
+        };
+        let tasks = portal.process_request(
+            1,
+            PortalRequest::NewOrder(2, hidden_sell(LimitOrMarket::Market)),
+        );
+        assert!(matches!(tasks[..], [PortalTask::OrderReject(2, 1, _)]));
+
+        // a hidden order reserves like any order, and is acknowledged but not published
+        let tasks = portal.process_request(
+            2,
+            PortalRequest::NewOrder(2, hidden_sell(LimitOrMarket::Limit)),
+        );
+        assert!(matches!(tasks[..], [PortalTask::OrderAck(2, 2, 1)]));
+        assert_eq!(position_of(&portal, 2), 9900);
+        assert!(portal
+            .depth(&"XYZ".to_string(), &Direction::Sell)
+            .is_empty());
+
+        let tasks = portal.process_request(
+            3,
+            PortalRequest::NewOrder(1, limit_order(Direction::Buy, 60, "10")),
+        );
+        assert!(tasks.iter().any(|task| matches!(
+            task,
+            PortalTask::IncrementalEvent(Event::OrderExecuted(executed)) if executed.order_id == 1
+        )));
+        assert_eq!(position_of(&portal, 1), 60);
+        assert_eq!(cash_of(&portal, 2), "600".parse().unwrap());
+        portal.process_request(4, PortalRequest::CancelOrder(2, 1));
+        assert_eq!(position_of(&portal, 2), 9940);
+
+        // the depth shows displayed levels only, a level of hidden orders is left out
+        portal.process_request(
+            5,
+            PortalRequest::NewOrder(2, hidden_sell(LimitOrMarket::Limit)),
+        );
+        portal.process_request(
+            6,
+            PortalRequest::NewOrder(2, limit_order(Direction::Sell, 50, "10.5")),
+        );
+        assert_eq!(
+            portal.depth(&"XYZ".to_string(), &Direction::Sell),
+            vec![("10.5".parse().unwrap(), 50)]
+        );
+    }
+
+    #[test]
+    fn test_margin_account() {
+        let stocks = r#"{"stocks": [
+            {"ticker": "XYZ", "close_price": 10.0, "lot_size": 1, "mpf": 0.01, "name": "XYZ Corp.",
+             "initial_margin": 50, "maintenance_margin": 25}
+        ]}"#;
+        let investors = r#"{"investors": [
+            {"inv_id": 1, "account_name": "Alice", "password": "a", "stocks": {}, "cash_amount": 100000.0},
+            {"inv_id": 2, "account_name": "Bob", "password": "b", "stocks": {"XYZ": 10000}, "cash_amount": 0},
+            {"inv_id": 3, "account_name": "Carol", "password": "c", "stocks": {}, "cash_amount": 10000, "margin": true}
+        ]}"#;
+        let mut portal = make_portal("margin_account", investors, stocks);
+
+        // a short sale needs 50% of its value as equity
+        let tasks = portal.process_request(
+            1,
+            PortalRequest::NewOrder(3, limit_order(Direction::Sell, 2001, "10")),
+        );
+        assert!(matches!(tasks[..], [PortalTask::OrderReject(3, 1, _)]));
+        portal.process_request(
+            2,
+            PortalRequest::NewOrder(3, limit_order(Direction::Sell, 2000, "10")),
+        );
+        portal.process_request(
+            3,
+            PortalRequest::NewOrder(1, limit_order(Direction::Buy, 2000, "10")),
+        );
+        assert_eq!(position_of(&portal, 3), -2000);
+        assert_eq!(cash_of(&portal, 3), Cash::from_int(30000));
+
+        // buying power: a buy only needs margin for the long position it could leave
+        let tasks = portal.process_request(
+            4,
+            PortalRequest::NewOrder(3, limit_order(Direction::Buy, 4001, "9")),
+        );
+        assert!(matches!(tasks[..], [PortalTask::OrderReject(3, 4, _)]));
+        let tasks = portal.process_request(
+            5,
+            PortalRequest::NewOrder(3, limit_order(Direction::Buy, 4000, "9")),
+        );
+        assert!(matches!(tasks[0], PortalTask::OrderAck(3, 5, 3)));
+        portal.process_request(6, PortalRequest::CancelOrder(3, 3));
+
+        // a trade @ 12.5 leaves 5000 of equity against 6250 of maintenance margin: a margin call
+        portal.process_request(
+            7,
+            PortalRequest::NewOrder(2, limit_order(Direction::Sell, 100, "12.5")),
+        );
+        let tasks = portal.process_request(
+            8,
+            PortalRequest::NewOrder(1, limit_order(Direction::Buy, 100, "12.5")),
+        );
+        let call = MarginCallResponse {
+            equity: Cash::from_int(5000),
+            requirement: Cash::from_int(6250),
+            deadline: MARGIN_CALL_SECS,
+            liquidation_orders: vec![],
+        };
+        assert!(matches!(tasks.last(), Some(PortalTask::MarginCall(3, called)) if *called == call));
+        assert!(portal
+            .process_request(9, PortalRequest::Timer(MARGIN_CALL_SECS - 1))
+            .is_empty());
+
+        // still short at the deadline: the position is bought back at the market
+        portal.process_request(
+            10,
+            PortalRequest::NewOrder(2, limit_order(Direction::Sell, 2000, "13")),
+        );
+        let tasks = portal.process_request(11, PortalRequest::Timer(MARGIN_CALL_SECS));
+        assert!(matches!(
+            &tasks[0],
+            PortalTask::MarginCall(3, call) if call.liquidation_orders == vec![7]
+        ));
+        assert_eq!(position_of(&portal, 3), 0);
+        assert_eq!(cash_of(&portal, 3), Cash::from_int(4000));
+        portal.process_request(12, PortalRequest::Timer(MARGIN_CALL_SECS + 1));
+        let account = portal.account_manager.get_account(&3).unwrap();
+        assert_eq!(account.margin_call, None);
+    }
+
+    // A liquidation closes what it can and is retried by the clock, the account's resting orders are
+    // cancelled first; a request without a fill leaves a due call to the clock
+    #[test]
+    fn test_margin_liquidation() {
+        let stocks = r#"{"stocks": [
+            {"ticker": "XYZ", "close_price": 10.0, "lot_size": 1, "mpf": 0.01, "name": "XYZ Corp.",
+             "initial_margin": 50, "maintenance_margin": 25}
+        ]}"#;
+        let investors = r#"{"investors": [
+            {"inv_id": 1, "account_name": "Alice", "password": "a", "stocks": {}, "cash_amount": 100000.0},
+            {"inv_id": 2, "account_name": "Bob", "password": "b", "stocks": {"XYZ": 10000}, "cash_amount": 0},
+            {"inv_id": 3, "account_name": "Carol", "password": "c", "stocks": {}, "cash_amount": 10000, "margin": true}
+        ]}"#;
+        let mut portal = make_portal("margin_liquidation", investors, stocks);
+        let requests = [
+            (3, limit_order(Direction::Sell, 2000, "10")),
+            (1, limit_order(Direction::Buy, 2000, "10")),
+            (3, limit_order(Direction::Buy, 100, "9")),
+            (2, limit_order(Direction::Sell, 100, "12.5")),
+            (1, limit_order(Direction::Buy, 100, "12.5")),
+        ];
+        for (seqnum, (inv_id, req)) in requests.into_iter().enumerate() {
+            portal.process_request(seqnum as SeqNum, PortalRequest::NewOrder(inv_id, req));
+        }
+        let account = portal.account_manager.get_account(&3).unwrap();
+        assert_eq!(account.margin_call, Some(MARGIN_CALL_SECS));
+
+        // an order that does not trade comes at the deadline, a read-only request after it
+        let tasks = portal.process_request(
+            6,
+            PortalRequest::NewOrder(
+                1,
+                PortalNewOrderRequest {
+                    timestamp: MARGIN_CALL_SECS,
+                    ..limit_order(Direction::Buy, 10, "9")
+                },
+            ),
+        );
+        assert!(matches!(tasks[0], PortalTask::OrderAck(1, 6, 6)));
+        assert!(!tasks
+            .iter()
+            .any(|task| matches!(task, PortalTask::MarginCall(..))));
+        let tasks = portal.process_request(7, PortalRequest::EventHistory(1));
+        assert!(matches!(tasks[..], [PortalTask::EventHistory(1, _)]));
+        assert_eq!(position_of(&portal, 3), -2000);
+
+        // only 500 can be bought back: the resting buy is cancelled and the rest waits for the next tick
+        portal.process_request(
+            8,
+            PortalRequest::NewOrder(2, limit_order(Direction::Sell, 500, "14")),
+        );
+        let tasks = portal.process_request(9, PortalRequest::Timer(MARGIN_CALL_SECS));
+        assert!(matches!(
+            &tasks[0],
+            PortalTask::MarginCall(3, call) if call.liquidation_orders == vec![8]
+        ));
+        assert!(tasks.iter().any(|task| matches!(
+            task,
+            PortalTask::OrderResponse(3, OrderResponse::OrderDead(dead)) if dead.order_id == 3
+        )));
+        assert_eq!(position_of(&portal, 3), -1500);
+        assert_eq!(cash_of(&portal, 3), Cash::from_int(23000));
+
+        // 2000 of equity against 5250 of maintenance margin: the next tick closes the rest
+        portal.process_request(
+            10,
+            PortalRequest::NewOrder(2, limit_order(Direction::Sell, 1500, "14")),
+        );
+        let tasks = portal.process_request(11, PortalRequest::Timer(MARGIN_CALL_SECS + 1));
+        assert!(matches!(
+            &tasks[0],
+            PortalTask::MarginCall(3, call) if call.liquidation_orders == vec![10]
+        ));
+        assert_eq!(position_of(&portal, 3), 0);
+        assert_eq!(cash_of(&portal, 3), Cash::from_int(2000));
+        portal.process_request(12, PortalRequest::Timer(MARGIN_CALL_SECS + 2));
+        let account = portal.account_manager.get_account(&3).unwrap();
+        assert_eq!(account.margin_call, None);
+    }
+
+    #[test]
+    fn test_fees() {
+        let stocks = r#"{"stocks": [
+            {"ticker": "XYZ", "close_price": 10.0, "lot_size": 1, "mpf": 0.01, "name": "XYZ Corp."}
+        ], "fee_tiers": [
+            {"maker": {"per_share": -0.002}, "taker": {"per_share": 0.003, "minimum": 1}}
+        ]}"#;
+        let mut portal = make_portal("fees", INVESTORS, stocks);
+        portal.process_request(
+            1,
+            PortalRequest::NewOrder(2, limit_order(Direction::Sell, 100, "10")),
+        );
+        let tasks = portal.process_request(
+            2,
+            PortalRequest::NewOrder(1, limit_order(Direction::Buy, 100, "10")),
+        );
+        // the taker pays at least the minimum, the maker gets a rebate
+        let fee_of = |inv_id: InvId| {
+            tasks.iter().find_map(|task| match task {
+                PortalTask::OrderResponse(id, OrderResponse::OrderFill(fill)) if *id == inv_id => {
+                    Some((fill.liquidity.clone(), fill.fee))
+                }
+                _ => None,
+            })
+        };
+        assert_eq!(fee_of(1), Some((Liquidity::Taker, Cash::from_int(1))));
+        assert_eq!(fee_of(2), Some((Liquidity::Maker, "-0.2".parse().unwrap())));
+        assert_eq!(cash_of(&portal, 1), Cash::from_int(98999));
+        assert_eq!(cash_of(&portal, 2), "1000.2".parse().unwrap());
+
+        // a buy reserves the minimum for every share, since each may fill on its own
+        let tasks = portal.process_request(
+            3,
+            PortalRequest::NewOrder(2, limit_order(Direction::Buy, 100, "10")),
+        );
+        assert!(matches!(tasks[..], [PortalTask::OrderReject(2, 3, _)]));
+        portal.process_request(
+            4,
+            PortalRequest::NewOrder(2, limit_order(Direction::Buy, 90, "10")),
+        );
+        assert_eq!(cash_of(&portal, 2), "10.2".parse().unwrap());
+        // a fill releases what its fee did not use, the death of the order the rest
+        portal.process_request(
+            5,
+            PortalRequest::NewOrder(1, limit_order(Direction::Sell, 40, "10")),
+        );
+        assert_eq!(cash_of(&portal, 2), "50.28".parse().unwrap());
+        portal.process_request(6, PortalRequest::CancelOrder(2, 3));
+        assert_eq!(cash_of(&portal, 2), "600.28".parse().unwrap());
+    }
+
+    #[test]
+    fn test_login_after_logout() {
+        let mut portal = make_portal("logout", INVESTORS, STOCKS);
+        assert!(!portal.try_login(1, &"b".to_string()));
+        assert!(portal.try_login(1, &"a".to_string()));
+        // one session per account until it logs out
+        assert!(!portal.try_login(1, &"a".to_string()));
+        assert!(portal.logout(&1, false).is_empty());
+        assert!(portal.try_login(1, &"a".to_string()));
+    }
+
+    #[test]
+    fn test_cancel_on_disconnect() {
+        let mut portal = make_portal("cod", INVESTORS, STOCKS);
+        assert!(portal.try_login(1, &"a".to_string()));
+        portal.process_request(
+            1,
+            PortalRequest::NewOrder(1, limit_order(Direction::Buy, 100, "9")),
+        );
+        assert_eq!(cash_of(&portal, 1), "99100".parse().unwrap());
+        assert!(!portal.cancel_on_disconnect(&1));
+
+        // the dropped session cancels its resting order and gets its reservation back
+        let tasks = portal.logout(&1, true);
+        assert!(matches!(
+            tasks[..],
+            [
+                PortalTask::OrderResponse(1, OrderResponse::OrderDead(_)),
+                PortalTask::IncrementalEvent(Event::OrderRemoved(OrderRemoved { order_id: 1 }))
+            ]
+        ));
+        assert_eq!(cash_of(&portal, 1), "100000".parse().unwrap());
+        assert_eq!(portal.depth(&"XYZ".to_string(), &Direction::Buy), vec![]);
+        assert!(portal.try_login(1, &"a".to_string()));
+    }
+
+    #[test]
+    fn test_risk_limits() {
+        let investors = r#"{"investors": [
+            {"inv_id": 1, "account_name": "Alice", "password": "a", "stocks": {}, "cash_amount": 100000.0,
+             "risk_limits": {"max_open_orders": 1}}
+        ]}"#;
+        let stocks = r#"{"stocks": [
+            {"ticker": "XYZ", "close_price": 10.0, "lot_size": 1, "mpf": 0.01, "name": "XYZ Corp.",
+             "risk_limits": {"max_price_deviation": 5}}
+        ]}"#;
+        let mut portal = make_portal("risk", investors, stocks);
+        let rejected = |tasks: &[PortalTask], reason: &str| matches!(tasks, [PortalTask::OrderReject(1, _, r)] if r == reason);
+        let tasks = portal.process_request(
+            1,
+            PortalRequest::NewOrder(1, limit_order(Direction::Buy, 100, "9.49")),
+        );
+        assert!(rejected(
+            &tasks,
+            "Invalid new order request: Price too far from the reference price"
+        ));
+        let tasks = portal.process_request(
+            2,
+            PortalRequest::NewOrder(1, limit_order(Direction::Buy, 100, "9.5")),
+        );
+        assert!(matches!(tasks[0], PortalTask::OrderAck(1, 2, 1)));
+        let tasks = portal.process_request(
+            3,
+            PortalRequest::NewOrder(1, limit_order(Direction::Buy, 100, "9.6")),
+        );
+        assert!(rejected(
+            &tasks,
+            "Invalid new order request: Too many open orders"
+        ));
+        // a cancelled order is not open anymore
+        portal.process_request(4, PortalRequest::CancelOrder(1, 1));
+        let tasks = portal.process_request(
+            5,
+            PortalRequest::NewOrder(1, limit_order(Direction::Buy, 100, "9.6")),
+        );
+        assert!(matches!(tasks[0], PortalTask::OrderAck(1, 5, 2)));
+
+        // an amendment is checked as well, without counting the order it amends as another open order
+        let amend = |price: &str| PortalAmendOrderRequest {
+            order_id: 2,
+            size: 100,
+            price: price.parse().unwrap(),
+            timestamp: 0,
+        };
+        let tasks = portal.process_request(6, PortalRequest::AmendOrder(1, amend("10.6")));
+        assert!(matches!(&tasks[..], [PortalTask::AmendReject(1, 6, r)]
+            if r == "Invalid amend order request: Price too far from the reference price"));
+        assert_eq!(
+            portal.depth(&"XYZ".to_string(), &Direction::Buy),
+            vec![("9.6".parse().unwrap(), 100)]
+        );
+        let tasks = portal.process_request(7, PortalRequest::AmendOrder(1, amend("9.7")));
+        assert!(matches!(tasks[0], PortalTask::AmendAck(1, 7, 2)));
+    }
+}
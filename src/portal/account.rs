@@ -1,45 +1,148 @@
 // Account: a struct that stores the information of a single account/investor
+// - a margin account may borrow cash and sell short: instead of reserving cash or positions, its orders
+//   need the initial margin of the positions they could open, and its trades settle in full
 
 use crate::types::{
-    account_manager::{AccountUpdate, PotentialOrder},
-    common::{AccountName, Cash, InvId, Password, Size, Ticker},
+    account_manager::{AccountUpdate, MarginRates, PotentialOrder},
+    common::{Cash, Direction, InvId, Password, Position, Price, Size, Ticker, Timestamp},
+    config::{RiskLimits, ThrottleConfig},
 };
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 
 #[derive(Debug)]
 pub struct Account {
     pub inv_id: InvId,
-    pub acc_name: AccountName,
     pub password: Password,
     pub cash: Cash,
-    pub positions: HashMap<Ticker, Size>,
+    pub positions: HashMap<Ticker, Position>,
+    pub margin: bool,
+    pub open_orders: HashMap<Ticker, (Size, Size)>, // open size of buy and sell orders, margin only
+    pub margin_call: Option<Timestamp>,             // deadline of a pending margin call
+    pub risk_limits: RiskLimits,
+    pub throttle: ThrottleConfig,
+    pub cancel_on_disconnect: bool,
+}
+
+// Value of a position at a price, negative for a short position
+pub fn market_value(price: Price, position: Position) -> Cash {
+    let value = price * position.unsigned_abs() as Size;
+    match position < 0 {
+        true => -value,
+        false => value,
+    }
 }
 
 impl Account {
     // Init
-    pub fn new(inv_id: InvId, acc_name: AccountName, password: Password, cash: Cash) -> Self {
+    pub fn new(
+        inv_id: InvId,
+        password: Password,
+        cash: Cash,
+        margin: bool,
+        risk_limits: RiskLimits,
+        throttle: ThrottleConfig,
+    ) -> Self {
         Account {
             inv_id,
-            acc_name,
             password,
             cash,
             positions: HashMap::new(),
+            margin,
+            open_orders: HashMap::new(),
+            margin_call: None,
+            risk_limits,
+            throttle,
+            cancel_on_disconnect: false,
         }
     }
 
     // Used in initialization
     pub fn add_position(&mut self, ticker: Ticker, size: Size) {
-        self.positions.insert(ticker, size);
+        self.positions.insert(ticker, size as Position);
     }
 
-    // Check if the potential order is valid: enough cash or enough positions
-    pub fn valid_potential_order(&self, p_order: &PotentialOrder) -> bool {
+    // Check if the potential order is valid: enough cash or enough positions, or enough equity to cover
+    // the initial margin with the open size of a margin order added
+    pub fn valid_potential_order(
+        &self,
+        p_order: &PotentialOrder,
+        rates: &impl Fn(&Ticker) -> Option<MarginRates>,
+    ) -> bool {
         match p_order {
-            PotentialOrder::PotentialBuy(total_price) => &self.cash >= &total_price,
+            PotentialOrder::PotentialBuy(total_price) => &self.cash >= total_price,
             PotentialOrder::PotentialSell(size, ticker) => self
                 .positions
                 .get(ticker)
-                .map_or(false, |own_size| own_size >= &size),
+                .is_some_and(|own_size| *own_size >= *size as Position),
+            PotentialOrder::PotentialMargin(ticker, direction, size) => {
+                self.equity(rates) >= self.initial_margin(rates, Some((ticker, direction, *size)))
+            }
+        }
+    }
+
+    // Cash plus the positions at their mark price
+    pub fn equity(&self, rates: &impl Fn(&Ticker) -> Option<MarginRates>) -> Cash {
+        self.positions
+            .iter()
+            .filter_map(|(ticker, position)| Some(market_value(rates(ticker)?.mark, *position)))
+            .fold(self.cash, |equity, value| equity + value)
+    }
+
+    // Initial margin of the positions if all open orders of the side that needs more margin traded,
+    // with an extra open order
+    fn initial_margin(
+        &self,
+        rates: &impl Fn(&Ticker) -> Option<MarginRates>,
+        extra: Option<(&Ticker, &Direction, Size)>,
+    ) -> Cash {
+        let mut tickers: HashSet<&Ticker> = self
+            .positions
+            .keys()
+            .chain(self.open_orders.keys())
+            .collect();
+        tickers.extend(extra.map(|(ticker, ..)| ticker));
+        let mut margin = Cash::ZERO;
+        for ticker in tickers {
+            let Some(rates) = rates(ticker) else {
+                continue;
+            };
+            let position = self.positions.get(ticker).copied().unwrap_or(0);
+            let (mut buys, mut sells) = self.open_orders.get(ticker).copied().unwrap_or((0, 0));
+            match extra {
+                Some((extra_ticker, Direction::Buy, size)) if extra_ticker == ticker => {
+                    buys += size
+                }
+                Some((extra_ticker, Direction::Sell, size)) if extra_ticker == ticker => {
+                    sells += size
+                }
+                _ => {}
+            }
+            let exposure = std::cmp::max(
+                (position + buys as Position).abs(),
+                (position - sells as Position).abs(),
+            );
+            margin += market_value(rates.mark, exposure).percent(rates.initial);
+        }
+        margin
+    }
+
+    // Maintenance margin of the positions
+    pub fn maintenance_margin(&self, rates: &impl Fn(&Ticker) -> Option<MarginRates>) -> Cash {
+        self.positions
+            .iter()
+            .filter_map(|(ticker, position)| {
+                let rates = rates(ticker)?;
+                Some(market_value(rates.mark, position.abs()).percent(rates.maintenance))
+            })
+            .fold(Cash::ZERO, |margin, value| margin + value)
+    }
+
+    // Open size of the orders of one side of a ticker
+    fn open_size(&mut self, ticker: Ticker, direction: &Direction) -> &mut Size {
+        let (buys, sells) = self.open_orders.entry(ticker).or_default();
+        match direction {
+            Direction::Buy => buys,
+            Direction::Sell => sells,
         }
     }
 
@@ -50,15 +153,16 @@ impl Account {
                 self.cash += delta_cash;
             }
             AccountUpdate::AddPos(_inv_id, ticker, add_size) => {
-                self.positions
-                    .entry(ticker)
-                    .and_modify(|size| *size += add_size)
-                    .or_insert(add_size);
+                *self.positions.entry(ticker).or_insert(0) += add_size as Position;
             }
             AccountUpdate::MinusPos(_inv_id, ticker, minus_size) => {
-                self.positions
-                    .entry(ticker)
-                    .and_modify(|size| *size -= minus_size);
+                *self.positions.entry(ticker).or_insert(0) -= minus_size as Position;
+            }
+            AccountUpdate::AddOpen(_inv_id, ticker, direction, size) => {
+                *self.open_size(ticker, &direction) += size;
+            }
+            AccountUpdate::MinusOpen(_inv_id, ticker, direction, size) => {
+                *self.open_size(ticker, &direction) -= size;
             }
         }
     }
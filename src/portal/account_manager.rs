@@ -1,11 +1,17 @@
 // AccountManager: stores and manages the information of all accounts/investors
+// - a margin account whose equity falls below its maintenance margin is called, and liquidated if it is
+//   still below at the deadline of the call
 
 use super::account::Account;
-use crate::types::account_manager::{AccountUpdate, PotentialOrder};
+use crate::types::account_manager::{AccountUpdate, MarginRates, PotentialOrder};
 use crate::types::common::*;
+use crate::types::portal::MarginCallResponse;
 use crate::utils::get_inv_id;
 use std::collections::{HashMap, HashSet};
 
+// Time a called margin account has to get back above its maintenance margin, in seconds
+pub const MARGIN_CALL_SECS: Timestamp = 15 * 60;
+
 pub struct AccountManager {
     accounts: HashMap<InvId, Account>,
     login_accs: HashSet<InvId>,
@@ -24,11 +30,73 @@ impl AccountManager {
         self.accounts.insert(inv_id, account);
     }
 
-    // Check if the potential order is valid: enough cash or enough positions
-    pub fn valid_potential_order(&self, inv_id: &InvId, p_order: &PotentialOrder) -> bool {
-        self.accounts
-            .get(&inv_id)
-            .map_or(false, |acc| acc.valid_potential_order(p_order))
+    pub fn get_account(&self, inv_id: &InvId) -> Option<&Account> {
+        self.accounts.get(inv_id)
+    }
+
+    pub fn is_margin(&self, inv_id: &InvId) -> bool {
+        self.get_account(inv_id).is_some_and(|acc| acc.margin)
+    }
+
+    // Check if the potential order is valid: enough cash or enough positions, or enough margin
+    pub fn valid_potential_order(
+        &self,
+        inv_id: &InvId,
+        p_order: &PotentialOrder,
+        rates: impl Fn(&Ticker) -> Option<MarginRates>,
+    ) -> bool {
+        self.get_account(inv_id)
+            .is_some_and(|acc| acc.valid_potential_order(p_order, &rates))
+    }
+
+    // Call the margin accounts whose equity fell below their maintenance margin, clear the calls of
+    // those back above it. Return the new calls and the calls whose deadline has passed, by inv_id
+    pub fn update_margin_calls(
+        &mut self,
+        now: Timestamp,
+        rates: impl Fn(&Ticker) -> Option<MarginRates>,
+    ) -> Vec<(InvId, MarginCallResponse)> {
+        let mut calls = vec![];
+        for acc in self.accounts.values_mut().filter(|acc| acc.margin) {
+            let equity = acc.equity(&rates);
+            let requirement = acc.maintenance_margin(&rates);
+            // an account without positions has nothing left to liquidate
+            if requirement == Cash::ZERO || equity >= requirement {
+                acc.margin_call = None;
+                continue;
+            }
+            let is_new = acc.margin_call.is_none();
+            let deadline = *acc.margin_call.get_or_insert(now + MARGIN_CALL_SECS);
+            if is_new || deadline <= now {
+                calls.push((
+                    acc.inv_id,
+                    MarginCallResponse {
+                        equity,
+                        requirement,
+                        deadline,
+                        liquidation_orders: vec![],
+                    },
+                ));
+            }
+        }
+        calls.sort_by_key(|(inv_id, _)| *inv_id);
+        calls
+    }
+
+    // Positions of an account that are not flat, sorted by ticker
+    pub fn open_positions(&self, inv_id: &InvId) -> Vec<(Ticker, Position)> {
+        let mut positions: Vec<(Ticker, Position)> = self
+            .get_account(inv_id)
+            .map(|acc| {
+                acc.positions
+                    .iter()
+                    .filter(|(_, position)| **position != 0)
+                    .map(|(ticker, position)| (ticker.clone(), *position))
+                    .collect()
+            })
+            .unwrap_or_default();
+        positions.sort();
+        positions
     }
 
     // Update account with account update: update cash or positions
@@ -39,7 +107,7 @@ impl AccountManager {
             .map_or((), |acc: &mut Account| acc.update(update))
     }
 
-    // Try to login with inv_id and password. The second time login will fail.
+    // Try to login with inv_id and password. Login fails while the account is logged in.
     pub fn try_login(&mut self, inv_id: InvId, password: &Password) -> bool {
         if let Some(account) = self.accounts.get(&inv_id) {
             if account.password == *password && !self.login_accs.contains(&inv_id) {
@@ -51,6 +119,11 @@ impl AccountManager {
     }
 
     // Advance drawdown: update cash and positions by potential order
+    // Release the login of an account, so that it can login again
+    pub fn logout(&mut self, inv_id: &InvId) {
+        self.login_accs.remove(inv_id);
+    }
+
     pub fn update_by_potential_order(&mut self, inv_id: InvId, p_order: PotentialOrder) {
         if let Some(_acc) = self.accounts.get(&inv_id) {
             match p_order {
@@ -60,6 +133,9 @@ impl AccountManager {
                 PotentialOrder::PotentialSell(size, ticker) => {
                     self.update(AccountUpdate::MinusPos(inv_id, ticker, size));
                 }
+                PotentialOrder::PotentialMargin(ticker, direction, size) => {
+                    self.update(AccountUpdate::AddOpen(inv_id, ticker, direction, size));
+                }
             }
         }
     }
@@ -0,0 +1,325 @@
+// BookSide: all resting orders on one side (buy or sell) of an orderbook
+// - price levels are kept sorted by price, each level is a FIFO queue of orders
+// - every resting order is indexed by order_id, so it can be removed in O(1) within its level
+// - iceberg orders show one slice at a time, each new slice goes to the back of its level
+// - hidden orders show nothing and come after every displayed order of their level
+
+use crate::types::common::{Direction, OrderId, Price, Size, Timestamp};
+use crate::types::order::{OrderOwner, PriceLevel, RestingOrder};
+use std::collections::{BTreeMap, HashMap};
+
+pub struct BookSide {
+    direction: Direction,
+    levels: BTreeMap<Price, PriceLevel>,
+    orders: HashMap<OrderId, RestingOrder>,
+}
+
+impl BookSide {
+    pub fn new(direction: Direction) -> Self {
+        BookSide {
+            direction,
+            levels: BTreeMap::new(),
+            orders: HashMap::new(),
+        }
+    }
+
+    // Iterate price levels from the best price to the worst price
+    pub fn levels(&self) -> Box<dyn Iterator<Item = &PriceLevel> + '_> {
+        match self.direction {
+            Direction::Buy => Box::new(self.levels.values().rev()),
+            Direction::Sell => Box::new(self.levels.values()),
+        }
+    }
+
+    // Iterate orders of a price level in time priority, displayed orders first
+    pub fn level_orders<'a>(&'a self, level: &PriceLevel) -> LevelOrders<'a> {
+        LevelOrders {
+            orders: &self.orders,
+            cursor: level.head,
+            hidden_head: level.hidden_head,
+        }
+    }
+
+    pub fn best_level(&self) -> Option<&PriceLevel> {
+        self.levels().next()
+    }
+
+    pub fn best_price(&self) -> Option<Price> {
+        self.best_level().map(|level| level.price)
+    }
+
+    pub fn get(&self, order_id: &OrderId) -> Option<&RestingOrder> {
+        self.orders.get(order_id)
+    }
+
+    // The order with the highest priority on this side
+    pub fn front(&self) -> Option<&RestingOrder> {
+        self.best_level()
+            .and_then(|level| self.level_orders(level).next())
+    }
+
+    // Append an order to the back of the queue at its price
+    // - it displays at most display_size of its size, None displays the whole size, 0 hides it
+    pub fn push_back(
+        &mut self,
+        order_id: OrderId,
+        owner: OrderOwner,
+        size: Size,
+        display_size: Option<Size>,
+        price: Price,
+        timestamp: Timestamp,
+    ) {
+        let hidden = display_size == Some(0);
+        let shown = match hidden {
+            true => size,
+            false => display_size.map_or(size, |display_size| display_size.min(size)),
+        };
+        let hidden_size = size - shown;
+        let size = shown;
+        let level = self
+            .levels
+            .entry(price)
+            .or_insert_with(|| PriceLevel::new(price));
+        let (head, tail) = level.queue_mut(hidden);
+        let prev = *tail;
+        if let Some(tail_id) = prev {
+            if let Some(tail) = self.orders.get_mut(&tail_id) {
+                tail.next = Some(order_id);
+            }
+        } else {
+            *head = Some(order_id);
+        }
+        *tail = Some(order_id);
+        match hidden {
+            true => level.hidden_size += size,
+            false => level.total_size += size,
+        }
+        level.hidden_size += hidden_size;
+        level.order_count += 1;
+        self.orders.insert(
+            order_id,
+            RestingOrder {
+                order_id,
+                owner,
+                size,
+                hidden_size,
+                display_size,
+                price,
+                timestamp,
+                prev,
+                next: None,
+            },
+        );
+    }
+
+    // Unlink an order from its level, drop the level if it becomes empty
+    pub fn remove(&mut self, order_id: &OrderId) -> Option<RestingOrder> {
+        let order = self.orders.remove(order_id)?;
+        if let Some(prev_id) = order.prev {
+            if let Some(prev) = self.orders.get_mut(&prev_id) {
+                prev.next = order.next;
+            }
+        }
+        if let Some(next_id) = order.next {
+            if let Some(next) = self.orders.get_mut(&next_id) {
+                next.prev = order.prev;
+            }
+        }
+        if let Some(level) = self.levels.get_mut(&order.price) {
+            let (head, tail) = level.queue_mut(order.is_hidden());
+            if *head == Some(order.order_id) {
+                *head = order.next;
+            }
+            if *tail == Some(order.order_id) {
+                *tail = order.prev;
+            }
+            match order.is_hidden() {
+                true => level.hidden_size -= order.size,
+                false => level.total_size -= order.size,
+            }
+            level.hidden_size -= order.hidden_size;
+            level.order_count -= 1;
+            if level.is_empty() {
+                self.levels.remove(&order.price);
+            }
+        }
+        Some(order)
+    }
+
+    // Reduce the displayed size of a resting order in place, keeping its priority
+    pub fn reduce(&mut self, order_id: &OrderId, size: Size) {
+        if let Some(order) = self.orders.get_mut(order_id) {
+            order.size -= size;
+            if let Some(level) = self.levels.get_mut(&order.price) {
+                match order.is_hidden() {
+                    true => level.hidden_size -= size,
+                    false => level.total_size -= size,
+                }
+            }
+        }
+    }
+
+    // Reduce the hidden reserve of a resting order in place, keeping its priority
+    pub fn reduce_hidden(&mut self, order_id: &OrderId, size: Size) {
+        if let Some(order) = self.orders.get_mut(order_id) {
+            order.hidden_size -= size;
+            if let Some(level) = self.levels.get_mut(&order.price) {
+                level.hidden_size -= size;
+            }
+        }
+    }
+
+    // The displayed slice of an iceberg order is used up: show the next slice from its hidden reserve
+    // at the back of its level. Return the new displayed size, None if nothing is hidden
+    pub fn replenish(&mut self, order_id: &OrderId) -> Option<Size> {
+        if self.orders.get(order_id)?.hidden_size == 0 {
+            return None;
+        }
+        let order = self.remove(order_id)?;
+        self.push_back(
+            order.order_id,
+            order.owner,
+            order.hidden_size,
+            order.display_size,
+            order.price,
+            order.timestamp,
+        );
+        self.orders.get(order_id).map(|order| order.size)
+    }
+}
+
+// Iterator over the orders of one price level, head to tail, then over its hidden orders
+pub struct LevelOrders<'a> {
+    orders: &'a HashMap<OrderId, RestingOrder>,
+    cursor: Option<OrderId>,
+    hidden_head: Option<OrderId>,
+}
+
+impl<'a> Iterator for LevelOrders<'a> {
+    type Item = &'a RestingOrder;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let order_id = self.cursor.or_else(|| self.hidden_head.take())?;
+        let order = self.orders.get(&order_id)?;
+        self.cursor = order.next;
+        Some(order)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn owner() -> OrderOwner {
+        OrderOwner {
+            inv_id: 1,
+            self_trade_prevention: None,
+        }
+    }
+
+    fn level_ids(side: &BookSide) -> Vec<Vec<OrderId>> {
+        side.levels()
+            .map(|level| side.level_orders(level).map(|o| o.order_id).collect())
+            .collect()
+    }
+
+    // Test price priority across levels and time priority inside a level
+    #[test]
+    fn test_buy_side_priority() {
+        let mut side = BookSide::new(Direction::Buy);
+        side.push_back(1, owner(), 100, None, Price::from_int(100), 5);
+        side.push_back(2, owner(), 100, None, Price::from_int(100), 6);
+        side.push_back(3, owner(), 100, None, Price::from_int(101), 7);
+        side.push_back(4, owner(), 100, None, Price::from_int(99), 8);
+        assert_eq!(side.best_price(), Some(Price::from_int(101)));
+        assert_eq!(level_ids(&side), vec![vec![3], vec![1, 2], vec![4]]);
+        assert_eq!(side.levels().nth(1).unwrap().total_size, 200);
+    }
+
+    #[test]
+    fn test_sell_side_priority() {
+        let mut side = BookSide::new(Direction::Sell);
+        side.push_back(1, owner(), 100, None, Price::from_int(100), 5);
+        side.push_back(2, owner(), 100, None, Price::from_int(100), 6);
+        side.push_back(3, owner(), 100, None, Price::from_int(101), 7);
+        side.push_back(4, owner(), 100, None, Price::from_int(99), 8);
+        assert_eq!(side.best_price(), Some(Price::from_int(99)));
+        assert_eq!(level_ids(&side), vec![vec![4], vec![1, 2], vec![3]]);
+        assert_eq!(side.front().unwrap().order_id, 4);
+    }
+
+    // Test removal from the head, middle and tail of a level
+    #[test]
+    fn test_remove() {
+        let mut side = BookSide::new(Direction::Buy);
+        side.push_back(1, owner(), 10, None, Price::from_int(100), 1);
+        side.push_back(2, owner(), 20, None, Price::from_int(100), 2);
+        side.push_back(3, owner(), 30, None, Price::from_int(100), 3);
+        side.push_back(4, owner(), 40, None, Price::from_int(100), 4);
+
+        assert_eq!(side.remove(&2).unwrap().size, 20);
+        assert_eq!(level_ids(&side), vec![vec![1, 3, 4]]);
+        side.remove(&1);
+        assert_eq!(level_ids(&side), vec![vec![3, 4]]);
+        side.remove(&4);
+        assert_eq!(level_ids(&side), vec![vec![3]]);
+        assert_eq!(side.best_level().unwrap().total_size, 30);
+        assert!(side.remove(&4).is_none());
+
+        side.remove(&3);
+        assert!(side.best_level().is_none());
+        side.push_back(5, owner(), 50, None, Price::from_int(100), 5);
+        assert_eq!(level_ids(&side), vec![vec![5]]);
+    }
+
+    #[test]
+    fn test_reduce() {
+        let mut side = BookSide::new(Direction::Sell);
+        side.push_back(1, owner(), 100, None, Price::from_int(10), 1);
+        side.push_back(2, owner(), 100, None, Price::from_int(10), 2);
+        side.reduce(&1, 60);
+        assert_eq!(side.front().unwrap().order_id, 1);
+        assert_eq!(side.front().unwrap().size, 40);
+        assert_eq!(side.best_level().unwrap().total_size, 140);
+    }
+
+    #[test]
+    fn test_iceberg_replenish() {
+        let mut side = BookSide::new(Direction::Sell);
+        side.push_back(1, owner(), 250, Some(100), Price::from_int(10), 1);
+        side.push_back(2, owner(), 50, None, Price::from_int(10), 2);
+        let level = side.best_level().unwrap();
+        assert_eq!((level.total_size, level.hidden_size), (150, 150));
+
+        // the next slice loses time priority
+        assert_eq!(side.replenish(&1), Some(100));
+        assert_eq!(level_ids(&side), vec![vec![2, 1]]);
+        assert_eq!(side.replenish(&1), Some(50));
+        assert_eq!(side.get(&1).unwrap().hidden_size, 0);
+        assert_eq!(side.replenish(&1), None);
+        let level = side.best_level().unwrap();
+        assert_eq!((level.total_size, level.hidden_size), (100, 0));
+    }
+
+    #[test]
+    fn test_hidden_priority() {
+        let mut side = BookSide::new(Direction::Buy);
+        side.push_back(1, owner(), 100, Some(0), Price::from_int(10), 1);
+        side.push_back(2, owner(), 50, None, Price::from_int(10), 2);
+        side.push_back(3, owner(), 70, Some(0), Price::from_int(10), 3);
+        side.push_back(4, owner(), 30, None, Price::from_int(10), 4);
+        // displayed orders first, hidden orders only count as hidden size
+        assert_eq!(level_ids(&side), vec![vec![2, 4, 1, 3]]);
+        let level = side.best_level().unwrap();
+        assert_eq!((level.total_size, level.hidden_size), (80, 170));
+
+        side.reduce(&1, 40);
+        side.remove(&2);
+        side.remove(&3);
+        assert_eq!(level_ids(&side), vec![vec![4, 1]]);
+        let level = side.best_level().unwrap();
+        assert_eq!((level.total_size, level.hidden_size), (30, 60));
+        side.remove(&4);
+        assert_eq!(side.front().unwrap().order_id, 1);
+    }
+}
@@ -0,0 +1,150 @@
+// ContingentManager: links orders whose life depends on another order
+// - the legs of an OCO pair: as soon as one leg trades, is triggered or dies, the other leg is cancelled
+// - bracket entries: every fill of the entry activates take-profit and stop-loss children for the size
+//   it filled, as an OCO pair, so that no filled share waits for the rest of the entry
+// - a stop leg of an OCO pair reserves nothing until it is triggered, the other leg holds the reservation
+
+use crate::types::common::{OrderId, Price, Size};
+use std::collections::{HashMap, HashSet};
+
+pub struct Bracket {
+    pub take_profit: Option<Price>,
+    pub stop_loss: Option<Price>,
+    pub size: Size, // size of the entry fill the children cover
+}
+
+// What the portal has to do after a response of a contingent order
+pub enum ContingentAction {
+    Cancel(OrderId),            // cancel the other leg of an OCO pair
+    Activate(OrderId, Bracket), // activate children for a fill of a bracket entry
+}
+
+pub struct ContingentManager {
+    oco: HashMap<OrderId, OrderId>, // each leg of an OCO pair to the other leg
+    brackets: HashMap<OrderId, (Option<Price>, Option<Price>)>, // take-profit and stop-loss of each entry
+    unreserved: HashSet<OrderId>,
+    actions: Vec<ContingentAction>,
+}
+
+impl ContingentManager {
+    pub fn new() -> Self {
+        ContingentManager {
+            oco: HashMap::new(),
+            brackets: HashMap::new(),
+            unreserved: HashSet::new(),
+            actions: vec![],
+        }
+    }
+
+    // Check if an order is an OCO leg or a bracket entry already
+    pub fn is_linked(&self, order_id: &OrderId) -> bool {
+        self.oco.contains_key(order_id) || self.brackets.contains_key(order_id)
+    }
+
+    pub fn link_oco(&mut self, first: OrderId, second: OrderId) {
+        self.oco.insert(first, second);
+        self.oco.insert(second, first);
+    }
+
+    // Unlink an OCO pair, return the other leg
+    pub fn unlink_oco(&mut self, order_id: &OrderId) -> Option<OrderId> {
+        let other = self.oco.remove(order_id)?;
+        self.oco.remove(&other);
+        Some(other)
+    }
+
+    pub fn add_bracket(
+        &mut self,
+        order_id: OrderId,
+        take_profit: Option<Price>,
+        stop_loss: Option<Price>,
+    ) {
+        self.brackets.insert(order_id, (take_profit, stop_loss));
+    }
+
+    pub fn set_unreserved(&mut self, order_id: OrderId) {
+        self.unreserved.insert(order_id);
+    }
+
+    // The order has reserved its cash or positions
+    pub fn set_reserved(&mut self, order_id: &OrderId) {
+        self.unreserved.remove(order_id);
+    }
+
+    pub fn is_unreserved(&self, order_id: &OrderId) -> bool {
+        self.unreserved.contains(order_id)
+    }
+
+    // An order traded: it cancels the other leg of its OCO pair, a bracket entry activates children for
+    // the fill
+    pub fn on_fill(&mut self, order_id: &OrderId, fill_size: Size) {
+        if let Some(other) = self.unlink_oco(order_id) {
+            self.actions.push(ContingentAction::Cancel(other));
+        }
+        if let Some((take_profit, stop_loss)) = self.brackets.get(order_id) {
+            self.actions.push(ContingentAction::Activate(
+                *order_id,
+                Bracket {
+                    take_profit: *take_profit,
+                    stop_loss: *stop_loss,
+                    size: fill_size,
+                },
+            ));
+        }
+    }
+
+    // An order is dead: it cancels the other leg of its OCO pair
+    pub fn on_dead(&mut self, order_id: &OrderId) {
+        self.unreserved.remove(order_id);
+        if let Some(other) = self.unlink_oco(order_id) {
+            self.actions.push(ContingentAction::Cancel(other));
+        }
+        self.brackets.remove(order_id);
+    }
+
+    // Take the actions triggered since the last call, in the order they were triggered
+    pub fn take_actions(&mut self) -> Vec<ContingentAction> {
+        std::mem::take(&mut self.actions)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_oco_and_bracket_actions() {
+        let mut contingent_manager = ContingentManager::new();
+        contingent_manager.link_oco(1, 2);
+        contingent_manager.add_bracket(3, Some(Price::from_int(12)), None);
+        assert!(contingent_manager.is_linked(&2));
+
+        // the first fill of a leg cancels the other leg, only once
+        contingent_manager.on_fill(&1, 10);
+        contingent_manager.on_fill(&1, 10);
+        contingent_manager.on_dead(&2);
+        let actions = contingent_manager.take_actions();
+        assert!(matches!(actions[..], [ContingentAction::Cancel(2)]));
+        assert!(!contingent_manager.is_linked(&1));
+
+        // a bracket entry activates children for each fill, until it is dead
+        contingent_manager.on_fill(&3, 30);
+        contingent_manager.on_fill(&3, 20);
+        let actions = contingent_manager.take_actions();
+        assert!(matches!(
+            actions[..],
+            [
+                ContingentAction::Activate(3, Bracket { size: 30, .. }),
+                ContingentAction::Activate(3, Bracket { size: 20, .. })
+            ]
+        ));
+        contingent_manager.on_dead(&3);
+        contingent_manager.on_fill(&3, 10);
+        assert!(contingent_manager.take_actions().is_empty());
+
+        // an entry that never traded has no children
+        contingent_manager.add_bracket(4, None, Some(Price::from_int(9)));
+        contingent_manager.on_dead(&4);
+        assert!(contingent_manager.take_actions().is_empty());
+    }
+}
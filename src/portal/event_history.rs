@@ -28,6 +28,20 @@ impl EventHistory {
             Event::OrderRemoved(order_removed) => {
                 self.add_event(Event::OrderRemoved(order_removed))
             }
+            Event::OrderModified(order_modified) => {
+                self.add_event(Event::OrderModified(order_modified))
+            }
+            Event::OrderReplaced(order_replaced) => {
+                self.add_event(Event::OrderReplaced(order_replaced))
+            }
+            Event::MarketStatus(market_status) => {
+                self.add_event(Event::MarketStatus(market_status))
+            }
+            Event::TickerStatus(ticker_status) => {
+                self.add_event(Event::TickerStatus(ticker_status))
+            }
+            // indicative prices are transient, only the live feed carries them
+            Event::AuctionIndicative(_) => {}
         }
     }
 }
@@ -36,7 +50,7 @@ impl EventHistory {
 mod tests {
     use super::*;
     use crate::types::{
-        common::Direction,
+        common::{Direction, Price},
         event::{OrderAdded, OrderExecuted, OrderRemoved},
     };
 
@@ -54,19 +68,19 @@ mod tests {
     #[test]
     fn test_event_history() {
         let mut event_history = EventHistory::new();
-        let resps = vec![
+        let resps = [
             Event::OrderAdded(OrderAdded {
                 order_id: 1,
                 ticker: "AAPL".to_string(),
                 direction: Direction::Buy,
                 resting_size: 100,
-                limit_price: 100.0,
+                limit_price: Price::from_int(100),
             }),
             Event::OrderExecuted(OrderExecuted {
                 order_id: 1,
                 ticker: "AAPL".to_string(),
                 execution_size: 100,
-                execution_price: 100.0,
+                execution_price: Price::from_int(100),
             }),
             Event::OrderRemoved(OrderRemoved { order_id: 1 }),
         ];
@@ -79,13 +93,13 @@ mod tests {
                 ticker: "AAPL".to_string(),
                 direction: Direction::Buy,
                 resting_size: 100,
-                limit_price: 100.0,
+                limit_price: Price::from_int(100),
             }),
             Event::OrderExecuted(OrderExecuted {
                 order_id: 1,
                 ticker: "AAPL".to_string(),
                 execution_size: 100,
-                execution_price: 100.0,
+                execution_price: Price::from_int(100),
             }),
             Event::OrderRemoved(OrderRemoved { order_id: 1 }),
         ];
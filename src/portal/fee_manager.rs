@@ -0,0 +1,226 @@
+// FeeManager: charges every fill by the fee schedule and keeps the volume each investor traded in the month
+// - the tier of a fill is the highest tier whose minimum volume the investor traded earlier in the month
+// - makers, takers and auction fills have their own rates, a negative fee is a rebate
+// - volumes start again from zero with every calendar month (UTC)
+// - buy orders reserve the most their shares may pay in fees, as if each share filled on its own at the
+//   highest rate, so that a cash account never pays a fee it has not reserved
+
+use crate::types::common::{Cash, InvId, Price, Size, Timestamp};
+use crate::types::config::{FeeRate, FeeTier};
+use crate::types::orderbook::Liquidity;
+use std::collections::HashMap;
+
+pub struct FeeManager {
+    tiers: Vec<FeeTier>, // by minimum volume
+    month: i64,
+    volumes: HashMap<InvId, u64>, // shares traded in the month
+}
+
+// Calendar month (UTC) of a unix timestamp, counted from January 1970
+fn month_of(timestamp: Timestamp) -> i64 {
+    // civil date from the days since 1970-01-01, in eras of 400 years starting on March 1st
+    let days = (timestamp / 86_400) as i64 + 719_468;
+    let (era, day_of_era) = (days / 146_097, days % 146_097);
+    let year_of_era =
+        (day_of_era - day_of_era / 1_460 + day_of_era / 36_524 - day_of_era / 146_096) / 365;
+    let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+    let month_from_march = (5 * day_of_year + 2) / 153;
+    let year = era * 400 + year_of_era + (month_from_march >= 10) as i64;
+    let month = (month_from_march + 2) % 12; // 0 for January
+    (year - 1970) * 12 + month
+}
+
+impl FeeManager {
+    pub fn new(tiers: Vec<FeeTier>) -> Self {
+        FeeManager {
+            tiers,
+            month: 0,
+            volumes: HashMap::new(),
+        }
+    }
+
+    // Price a buy order reserves cash at: its limit price plus the most one of its shares may pay in fees,
+    // None if it overflows
+    pub fn reserve_price(&self, price: Price) -> Option<Price> {
+        let rates = self.tiers.iter().flat_map(|tier| {
+            [&tier.maker, &tier.taker]
+                .into_iter()
+                .chain(tier.auction.as_ref())
+        });
+        let fee = rates.fold(Price::ZERO, |fee, rate| {
+            // a fill below the price gets a smaller rebate, and is charged basis points rounded down
+            let bps = match rate.bps > Price::ZERO {
+                true => price.basis_points_up(rate.bps),
+                false => Price::ZERO,
+            };
+            let per_share = rate.per_share + bps;
+            fee.max(rate.minimum.max(per_share))
+        });
+        price.checked_add(fee)
+    }
+
+    // Cash a buy order of size at price reserves, None if it overflows
+    pub fn reserve_cash(&self, price: Price, size: Size) -> Option<Cash> {
+        self.reserve_price(price)?.checked_mul(size)
+    }
+
+    // Fee of a fill of an investor at now, negative for a rebate; the fill counts towards the volume of
+    // the investor
+    pub fn charge(
+        &mut self,
+        inv_id: InvId,
+        liquidity: &Liquidity,
+        size: Size,
+        price: Price,
+        now: Timestamp,
+    ) -> Cash {
+        let month = month_of(now);
+        if month != self.month {
+            self.month = month;
+            self.volumes.clear();
+        }
+        let volume = self.volumes.entry(inv_id).or_insert(0);
+        let tier = self
+            .tiers
+            .iter()
+            .rev()
+            .find(|tier| tier.min_volume <= *volume);
+        *volume += size as u64;
+        let Some(tier) = tier else {
+            return Cash::ZERO;
+        };
+        let rate: &FeeRate = match liquidity {
+            Liquidity::Maker => &tier.maker,
+            Liquidity::Taker => &tier.taker,
+            Liquidity::Auction => tier.auction.as_ref().unwrap_or(&tier.taker),
+        };
+        let fee = rate.per_share * size + (price * size).basis_points(rate.bps);
+        match fee > Cash::ZERO {
+            true => fee.max(rate.minimum),
+            false => fee,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_month_of() {
+        assert_eq!(month_of(0), 0);
+        // 2024-02-29 23:59:59 and 2024-03-01 00:00:00
+        assert_eq!(month_of(1_709_251_199), 54 * 12 + 1);
+        assert_eq!(month_of(1_709_251_200), 54 * 12 + 2);
+        // 2000-12-31 23:59:59 and 2001-01-01 00:00:00
+        assert_eq!(month_of(978_307_199), 30 * 12 + 11);
+        assert_eq!(month_of(978_307_200), 31 * 12);
+    }
+
+    #[test]
+    fn test_charge() {
+        let price = |price: &str| -> Price { price.parse().unwrap() };
+        let rate = |per_share: &str, bps: &str, minimum: &str| FeeRate {
+            per_share: price(per_share),
+            bps: price(bps),
+            minimum: price(minimum),
+        };
+        // 3 mils to take and a 2 mils rebate to make, 1 mil to take and 1 bp in auctions from 1000 shares
+        let mut fee_manager = FeeManager::new(vec![
+            FeeTier {
+                min_volume: 0,
+                maker: rate("-0.002", "0", "0"),
+                taker: rate("0.003", "0", "1"),
+                auction: None,
+            },
+            FeeTier {
+                min_volume: 1000,
+                maker: rate("-0.002", "0", "0"),
+                taker: rate("0.001", "0", "0"),
+                auction: Some(rate("0", "1", "0")),
+            },
+        ]);
+        let now = 1_709_251_199;
+        // the minimum applies to charges only
+        assert_eq!(
+            fee_manager.charge(1, &Liquidity::Taker, 100, price("10"), now),
+            price("1")
+        );
+        assert_eq!(
+            fee_manager.charge(1, &Liquidity::Maker, 900, price("10"), now),
+            price("-1.8")
+        );
+        assert_eq!(
+            fee_manager.charge(1, &Liquidity::Taker, 1000, price("10"), now),
+            price("1")
+        );
+        assert_eq!(
+            fee_manager.charge(1, &Liquidity::Auction, 1000, price("10"), now),
+            price("1")
+        );
+        // auction fills pay the taker rate by default, volumes are per investor and per month
+        assert_eq!(
+            fee_manager.charge(2, &Liquidity::Auction, 1000, price("10"), now),
+            price("3")
+        );
+        assert_eq!(
+            fee_manager.charge(1, &Liquidity::Taker, 1000, price("10"), now + 1),
+            price("3")
+        );
+    }
+
+    #[test]
+    fn test_reserve_price() {
+        let price = |price: &str| -> Price { price.parse().unwrap() };
+        let rate = |per_share: &str, bps: &str, minimum: &str| FeeRate {
+            per_share: price(per_share),
+            bps: price(bps),
+            minimum: price(minimum),
+        };
+        assert_eq!(
+            FeeManager::new(vec![]).reserve_price(price("10")),
+            Some(price("10"))
+        );
+        // the highest rate of any tier, rebates reserve nothing
+        let fee_manager = FeeManager::new(vec![
+            FeeTier {
+                min_volume: 0,
+                maker: rate("-0.002", "0", "0"),
+                taker: rate("0.003", "0", "0"),
+                auction: Some(rate("0", "10", "0")),
+            },
+            FeeTier {
+                min_volume: 1000,
+                maker: rate("-0.002", "0", "0"),
+                taker: rate("0.005", "0", "0"),
+                auction: None,
+            },
+        ]);
+        assert_eq!(fee_manager.reserve_price(price("10")), Some(price("10.01")));
+        assert_eq!(fee_manager.reserve_price(price("1")), Some(price("1.005")));
+        assert_eq!(
+            fee_manager.reserve_price(price("10.0001")),
+            Some(price("10.0102"))
+        );
+        // a minimum may be charged on a fill of a single share
+        let fee_manager = FeeManager::new(vec![FeeTier {
+            min_volume: 0,
+            maker: rate("0", "0", "0"),
+            taker: rate("0.003", "0", "1"),
+            auction: None,
+        }]);
+        assert_eq!(fee_manager.reserve_price(price("10")), Some(price("11")));
+        assert_eq!(
+            fee_manager.reserve_cash(price("10"), 1000),
+            Some(price("11000"))
+        );
+        assert_eq!(
+            fee_manager.reserve_cash(price("100000000"), 4_000_000_000),
+            None
+        );
+        assert_eq!(
+            fee_manager.reserve_price(Price::from_units(i64::MAX - 1)),
+            None
+        );
+    }
+}
@@ -0,0 +1,131 @@
+// MatchingPolicy: how an incoming order is allocated among the resting orders of one price level
+// - price priority always comes first, the policy only decides between orders at the same price
+// - only the displayed size of iceberg orders takes part in an allocation
+
+use crate::types::common::{MatchingPolicy, OrderId, Size};
+
+impl MatchingPolicy {
+    // Allocate size among the orders (order_id, displayed size) of a level given in time priority
+    // Return the non-zero fills in the order they are executed
+    pub fn allocate(&self, orders: &[(OrderId, Size)], size: Size) -> Vec<(OrderId, Size)> {
+        match self {
+            MatchingPolicy::PriceTime => allocate_in_order(orders, size),
+            MatchingPolicy::SizeTime => {
+                // larger orders first, time priority between orders of the same size
+                let mut by_size = orders.to_vec();
+                by_size.sort_by_key(|(_, order_size)| std::cmp::Reverse(*order_size));
+                allocate_in_order(&by_size, size)
+            }
+            MatchingPolicy::ProRata => allocate_pro_rata(orders, size),
+            MatchingPolicy::ProRataTopOrder => {
+                // the first order in time is filled first, the rest is shared pro-rata
+                let Some((&(top_id, top_size), rest)) = orders.split_first() else {
+                    return vec![];
+                };
+                let top_fill = std::cmp::min(size, top_size);
+                let mut fills = allocate_pro_rata(rest, size - top_fill);
+                if top_fill > 0 {
+                    fills.insert(0, (top_id, top_fill));
+                }
+                fills
+            }
+        }
+    }
+}
+
+// Fill orders one after the other
+fn allocate_in_order(orders: &[(OrderId, Size)], size: Size) -> Vec<(OrderId, Size)> {
+    let mut left_size = size;
+    let mut fills = vec![];
+    for &(order_id, order_size) in orders {
+        if left_size == 0 {
+            break;
+        }
+        let fill_size = std::cmp::min(left_size, order_size);
+        fills.push((order_id, fill_size));
+        left_size -= fill_size;
+    }
+    fills
+}
+
+// Share size in proportion to order sizes, rounded down, the rounding remainder goes one unit at a
+// time in time priority
+fn allocate_pro_rata(orders: &[(OrderId, Size)], size: Size) -> Vec<(OrderId, Size)> {
+    let total: u64 = orders
+        .iter()
+        .map(|(_, order_size)| *order_size as u64)
+        .sum();
+    if total == 0 || size == 0 {
+        return vec![];
+    }
+    let size = std::cmp::min(size as u64, total);
+    let mut shares: Vec<(OrderId, Size, Size)> = orders
+        .iter()
+        .map(|&(order_id, order_size)| {
+            let share = (size * order_size as u64 / total) as Size;
+            (order_id, order_size, share)
+        })
+        .collect();
+    let mut remainder = size as Size - shares.iter().map(|(_, _, share)| share).sum::<Size>();
+    // rounding leaves less than one unit per order: one more unit each until none is left
+    for (_, order_size, share) in shares.iter_mut() {
+        if remainder > 0 && *share < *order_size {
+            *share += 1;
+            remainder -= 1;
+        }
+    }
+    shares
+        .into_iter()
+        .filter(|(_, _, share)| *share > 0)
+        .map(|(order_id, _, share)| (order_id, share))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // 1: 100, 2: 300, 3: 100 in time priority, 250 incoming
+    const LEVEL: [(OrderId, Size); 3] = [(1, 100), (2, 300), (3, 100)];
+
+    #[test]
+    fn test_allocate() {
+        assert_eq!(
+            MatchingPolicy::PriceTime.allocate(&LEVEL, 250),
+            vec![(1, 100), (2, 150)]
+        );
+        assert_eq!(
+            MatchingPolicy::SizeTime.allocate(&LEVEL, 250),
+            vec![(2, 250)]
+        );
+        // 50, 150, 50 exactly
+        assert_eq!(
+            MatchingPolicy::ProRata.allocate(&LEVEL, 250),
+            vec![(1, 50), (2, 150), (3, 50)]
+        );
+        // 150 shared pro-rata between 2 and 3 is 112.5 and 37.5: the remainder goes to 2
+        assert_eq!(
+            MatchingPolicy::ProRataTopOrder.allocate(&LEVEL, 250),
+            vec![(1, 100), (2, 113), (3, 37)]
+        );
+    }
+
+    #[test]
+    fn test_pro_rata_rounding() {
+        // 1/3 each rounds down to 0: the remainder is allocated in time priority
+        let level = [(1, 10), (2, 10), (3, 10)];
+        assert_eq!(
+            MatchingPolicy::ProRata.allocate(&level, 2),
+            vec![(1, 1), (2, 1)]
+        );
+        // more than the level: every order is filled
+        assert_eq!(
+            MatchingPolicy::ProRata.allocate(&level, 50),
+            vec![(1, 10), (2, 10), (3, 10)]
+        );
+        assert_eq!(
+            MatchingPolicy::ProRataTopOrder.allocate(&level, 5),
+            vec![(1, 5)]
+        );
+    }
+}
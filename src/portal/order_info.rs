@@ -1,20 +1,23 @@
 // OrderInfo: stores and manages all orders: resting size and static properties (OrderRecord) by order_id
 
 use crate::types::{common::*, event::*, portal::PortalNewOrderRequest};
-use std::collections::HashMap;
+use std::collections::{BTreeSet, HashMap};
 
-// Static properties once order is added
+// Static properties once order is added, only the limit price can be changed by an amend
 pub struct OrderRecord {
     pub inv_id: InvId,
     pub ticker: Ticker,
     pub direction: Direction,
     pub limit_price: Price,
-    pub initial_size: Size,
+    pub time_in_force: TimeInForce,
 }
 
+// resting: open size of every live order, from its acceptance until it is dead
+// - includes the hidden reserve of iceberg orders, which events do not show
 pub struct OrderInfo {
-    pub bind: HashMap<OrderId, OrderRecord>, // static properties
-    pub resting: HashMap<OrderId, Size>,     // mutable properties
+    pub bind: HashMap<OrderId, OrderRecord>,  // static properties
+    pub resting: HashMap<OrderId, Size>,      // mutable properties
+    expiries: BTreeSet<(Timestamp, OrderId)>, // good-till-date orders by expiry
 }
 
 impl OrderInfo {
@@ -22,6 +25,7 @@ impl OrderInfo {
         OrderInfo {
             bind: HashMap::new(),
             resting: HashMap::new(),
+            expiries: BTreeSet::new(),
         }
     }
 
@@ -29,11 +33,11 @@ impl OrderInfo {
     pub fn get_resting(&mut self, order_id: &OrderId) -> Option<Size> {
         if let Some((size, should_remove)) = self
             .resting
-            .get_mut(&order_id)
+            .get_mut(order_id)
             .map(|size| (*size, *size == 0))
         {
             if should_remove {
-                self.resting.remove(&order_id);
+                self.resting.remove(order_id);
                 None
             } else {
                 Some(size)
@@ -43,12 +47,63 @@ impl OrderInfo {
         }
     }
 
+    // Take size off the open size of an order without a trade
+    pub fn reduce_resting(&mut self, order_id: &OrderId, size: Size) {
+        if let Some(resting) = self.resting.get_mut(order_id) {
+            *resting -= size;
+        }
+    }
+
+    // Forget the open size of a dead order
+    pub fn remove_resting(&mut self, order_id: &OrderId) {
+        self.resting.remove(order_id);
+    }
+
+    // Take all live orders whose expiry is not later than now
+    pub fn take_expired(&mut self, now: Timestamp) -> Vec<OrderId> {
+        let live = self.expiries.split_off(&(now + 1, 0));
+        let expired = std::mem::replace(&mut self.expiries, live);
+        expired
+            .into_iter()
+            .map(|(_, order_id)| order_id)
+            .filter(|order_id| self.resting.contains_key(order_id))
+            .collect()
+    }
+
+    // Get all live Day orders, ordered by order_id
+    pub fn day_orders(&self) -> Vec<OrderId> {
+        let mut day_orders: Vec<OrderId> = self
+            .resting
+            .keys()
+            .filter(|order_id| {
+                self.bind
+                    .get(order_id)
+                    .is_some_and(|p| p.time_in_force == TimeInForce::Day)
+            })
+            .copied()
+            .collect();
+        day_orders.sort_unstable();
+        day_orders
+    }
+
+    // Get all live orders of an investor, ordered by order_id
+    pub fn live_orders(&self, inv_id: &InvId) -> Vec<OrderId> {
+        let mut live_orders: Vec<OrderId> = self
+            .resting
+            .iter()
+            .filter(|(order_id, size)| {
+                **size > 0 && self.bind.get(order_id).is_some_and(|p| p.inv_id == *inv_id)
+            })
+            .map(|(order_id, _)| *order_id)
+            .collect();
+        live_orders.sort_unstable();
+        live_orders
+    }
+
     // Check if an order is valid to cancel: order exists and inv_id matches
     pub fn valid_cancel_order(&mut self, order_id: &OrderId, inv_id: &InvId) -> bool {
-        if let Some(_) = self.get_resting(order_id) {
-            self.bind
-                .get(&order_id)
-                .map_or(false, |p| p.inv_id == *inv_id)
+        if self.get_resting(order_id).is_some() {
+            self.bind.get(order_id).is_some_and(|p| p.inv_id == *inv_id)
         } else {
             false
         }
@@ -59,12 +114,19 @@ impl OrderInfo {
         self.bind.insert(order_id, order_rec);
     }
 
+    // Set the open size of an order, e.g. when it is amended
+    pub fn set_resting(&mut self, order_id: &OrderId, size: Size) {
+        self.resting.insert(*order_id, size);
+    }
+
     // Update resting size by event
+    // - modified and replaced events only show the displayed size, amends set the open size instead
     pub fn update_by_event(&mut self, event: Event) {
         match event {
             Event::OrderAdded(order_added) => {
                 self.resting
-                    .insert(order_added.order_id, order_added.resting_size);
+                    .entry(order_added.order_id)
+                    .or_insert(order_added.resting_size);
             }
             Event::OrderExecuted(order_executed) => {
                 if let Some(size) = self.get_resting(&order_executed.order_id) {
@@ -77,12 +139,24 @@ impl OrderInfo {
             Event::OrderRemoved(order_removed) => {
                 self.resting.remove(&order_removed.order_id);
             }
+            Event::OrderModified(_)
+            | Event::OrderReplaced(_)
+            | Event::MarketStatus(_)
+            | Event::AuctionIndicative(_)
+            | Event::TickerStatus(_) => {}
+        }
+    }
+
+    // Record the new limit price of an amended order
+    pub fn amend_limit_price(&mut self, order_id: &OrderId, price: Price) {
+        if let Some(order_rec) = self.bind.get_mut(order_id) {
+            order_rec.limit_price = price;
         }
     }
 
     // Get order static properties
     pub fn get_order_record(&self, order_id: &OrderId) -> Option<&OrderRecord> {
-        self.bind.get(&order_id)
+        self.bind.get(order_id)
     }
 
     // Add a new order
@@ -93,15 +167,19 @@ impl OrderInfo {
         req: &PortalNewOrderRequest,
     ) {
         self.bind_order(
-            order_id.clone(),
+            *order_id,
             OrderRecord {
-                inv_id: inv_id.clone(),
+                inv_id: *inv_id,
                 ticker: req.ticker.clone(),
                 direction: req.direction.clone(),
-                limit_price: req.price.clone(),
-                initial_size: req.size.clone(),
+                limit_price: req.price,
+                time_in_force: req.time_in_force.clone(),
             },
         );
+        self.resting.insert(*order_id, req.size);
+        if let TimeInForce::GTD(expiry) = req.time_in_force {
+            self.expiries.insert((expiry, *order_id));
+        }
     }
 }
 
@@ -113,8 +191,8 @@ mod tests {
             inv_id,
             ticker: "AAPL".to_string(),
             direction: Direction::Buy,
-            limit_price: 100.0,
-            initial_size: 100,
+            limit_price: Price::from_int(100),
+            time_in_force: TimeInForce::Day,
         }
     }
 
@@ -128,7 +206,7 @@ mod tests {
             ticker: "AAPL".to_string(),
             direction: Direction::Buy,
             resting_size: 100,
-            limit_price: 100.0,
+            limit_price: Price::from_int(100),
         }));
     }
     #[test]
@@ -140,17 +218,17 @@ mod tests {
             ticker: "AAPL".to_string(),
             direction: Direction::Buy,
             resting_size: 100,
-            limit_price: 100.0,
+            limit_price: Price::from_int(100),
         }));
-        assert_eq!(order_info.valid_cancel_order(&1, &1), true);
+        assert!(order_info.valid_cancel_order(&1, &1));
 
         order_info.update_by_event(Event::OrderExecuted(OrderExecuted {
             order_id: 1,
             ticker: "AAPL".to_string(),
             execution_size: 100,
-            execution_price: 100.0,
+            execution_price: Price::from_int(100),
         }));
-        assert_eq!(order_info.valid_cancel_order(&1, &1), false);
+        assert!(!order_info.valid_cancel_order(&1, &1));
     }
     #[test]
     fn test_removed() {
@@ -161,12 +239,12 @@ mod tests {
             ticker: "AAPL".to_string(),
             direction: Direction::Buy,
             resting_size: 100,
-            limit_price: 100.0,
+            limit_price: Price::from_int(100),
         }));
-        assert_eq!(order_info.valid_cancel_order(&1, &1), true);
+        assert!(order_info.valid_cancel_order(&1, &1));
 
         order_info.update_by_event(Event::OrderRemoved(OrderRemoved { order_id: 1 }));
-        assert_eq!(order_info.valid_cancel_order(&1, &1), false);
+        assert!(!order_info.valid_cancel_order(&1, &1));
     }
     #[test]
     fn test_invalid() {
@@ -175,26 +253,26 @@ mod tests {
         order_info.bind_order(2, make_order_record(2));
 
         // order not exist
-        assert_eq!(order_info.valid_cancel_order(&4, &1), false);
+        assert!(!order_info.valid_cancel_order(&4, &1));
         // inv not exist
-        assert_eq!(order_info.valid_cancel_order(&1, &3), false);
+        assert!(!order_info.valid_cancel_order(&1, &3));
         // not the owner
-        assert_eq!(order_info.valid_cancel_order(&1, &2), false);
+        assert!(!order_info.valid_cancel_order(&1, &2));
 
         order_info.update_by_event(Event::OrderAdded(OrderAdded {
             order_id: 1,
             ticker: "AAPL".to_string(),
             direction: Direction::Buy,
             resting_size: 100,
-            limit_price: 100.0,
+            limit_price: Price::from_int(100),
         }));
         order_info.update_by_event(Event::OrderExecuted(OrderExecuted {
             order_id: 1,
             ticker: "AAPL".to_string(),
             execution_size: 100,
-            execution_price: 100.0,
+            execution_price: Price::from_int(100),
         }));
 
-        assert_eq!(order_info.valid_cancel_order(&1, &1), false);
+        assert!(!order_info.valid_cancel_order(&1, &1));
     }
 }
@@ -1,54 +1,189 @@
 // Orderbook: stores and maintains all resting order for a ticker
-// - stores all resting orders in two hashmaps (one for all buy orders, one for all sell orders)
+// - stores all resting orders in two book sides (one for all buy orders, one for all sell orders)
+// - each side is a set of price levels, each level is a FIFO queue of orders
+// - the matching policy of the book decides how orders at the same price share an incoming order
+// - in a call auction orders accumulate without matching until the book is uncrossed at a single price
+// - pegged orders are moved after every change of the best prices of the orders that are not pegged
 
+use super::book_side::BookSide;
 use crate::types::common::*;
-use crate::types::event::{Event, OrderAdded, OrderExecuted, OrderRemoved};
-use crate::types::order::{BuyOrder, SellOrder};
+use crate::types::event::{
+    AuctionIndicative, Event, OrderAdded, OrderExecuted, OrderModified, OrderRemoved,
+    OrderReplaced, TickerStatus,
+};
+use crate::types::order::{OrderOwner, PriceLevel, RestingOrder};
 use crate::types::orderbook::*;
 use crate::types::portal::OrderResponse;
-use std::collections::{BinaryHeap, HashSet};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
 
 pub struct OrderBook {
     ticker: Ticker,
-    buy_orders: BinaryHeap<BuyOrder>,
-    sell_orders: BinaryHeap<SellOrder>,
-    lazy_deleted: HashSet<OrderId>,
+    tick_size: Price,
+    buy_orders: BookSide,
+    sell_orders: BookSide,
+    auction_reference: Option<Price>, // Some during a call auction: the reference price of the uncross
+    matching_policy: MatchingPolicy,
+    price_bands: Option<PriceBands>,
+    close_price: Price,           // reference of the static price band
+    last_price: Option<Price>,    // reference of the dynamic price band
+    resume_at: Option<Timestamp>, // Some during a volatility auction: when it is uncrossed
+    state: TickerState,
+    pegs: BTreeMap<OrderId, Peg>, // pegged orders by order_id, i.e. in time priority of their entry
+    now: Timestamp,               // latest timestamp of a request
+}
+
+// How the price of a pegged order is derived
+struct Peg {
+    direction: Direction,
+    reference: PegReference,
+    offset: Price,
+    cap: Price,
+}
+
+// Executable volume of a crossed book at one price
+struct Equilibrium {
+    price: Price,
+    buy_size: Size,
+    sell_size: Size,
+}
+
+impl Equilibrium {
+    fn matched_size(&self) -> Size {
+        std::cmp::min(self.buy_size, self.sell_size)
+    }
+
+    fn imbalance_size(&self) -> Size {
+        self.buy_size.abs_diff(self.sell_size)
+    }
+
+    fn imbalance_direction(&self) -> Option<Direction> {
+        match self.buy_size.cmp(&self.sell_size) {
+            std::cmp::Ordering::Greater => Some(Direction::Buy),
+            std::cmp::Ordering::Less => Some(Direction::Sell),
+            std::cmp::Ordering::Equal => None,
+        }
+    }
 }
 
 impl OrderBook {
-    pub fn new(ticker: Ticker) -> OrderBook {
+    pub fn new(ticker: Ticker, tick_size: Price, matching_policy: MatchingPolicy) -> OrderBook {
         OrderBook {
             ticker,
-            buy_orders: BinaryHeap::new(),
-            sell_orders: BinaryHeap::new(),
-            lazy_deleted: HashSet::new(),
+            tick_size,
+            buy_orders: BookSide::new(Direction::Buy),
+            sell_orders: BookSide::new(Direction::Sell),
+            auction_reference: None,
+            matching_policy,
+            price_bands: None,
+            close_price: Price::ZERO,
+            last_price: None,
+            resume_at: None,
+            state: TickerState::Trading,
+            pegs: BTreeMap::new(),
+            now: 0,
         }
     }
 
-    // Find the best buy order and pop it off the heap
-    fn get_best_buy_order(&mut self) -> Option<BuyOrder> {
-        while let Some(best_order) = self.buy_orders.pop() {
-            if !self.lazy_deleted.contains(&best_order.order_id) {
-                return Some(best_order);
-            } else {
-                self.lazy_deleted.remove(&best_order.order_id);
-                continue;
-            }
+    pub fn set_price_bands(&mut self, price_bands: PriceBands, close_price: Price) {
+        self.price_bands = Some(price_bands);
+        self.close_price = close_price;
+    }
+
+    pub fn set_close_price(&mut self, close_price: Price) {
+        self.close_price = close_price;
+    }
+
+    // Check a trade price against the static band around the close price and the dynamic band around
+    // the last trade price, both bounds included
+    fn within_bands(&self, price: Price, last_price: Option<Price>) -> bool {
+        let Some(bands) = &self.price_bands else {
+            return true;
+        };
+        let within =
+            |reference: Price, band: Price| (price - reference).abs() <= reference.percent(band);
+        bands
+            .static_band
+            .is_none_or(|band| within(self.close_price, band))
+            && bands
+                .dynamic_band
+                .is_none_or(|band| last_price.is_none_or(|last_price| within(last_price, band)))
+    }
+
+    // Interrupt continuous trading with a call auction until now + the auction duration
+    fn start_volatility_auction(&mut self, now: Timestamp) -> OrderbookLog {
+        let duration = self
+            .price_bands
+            .as_ref()
+            .map_or(0, |bands| bands.auction_duration);
+        self.auction_reference = Some(self.last_price.unwrap_or(self.close_price));
+        self.resume_at = Some(now + duration);
+        self.set_state(TickerState::VolatilityAuction)
+    }
+
+    // Uncross a volatility auction once its time is up and resume continuous trading
+    pub fn resume_trading(&mut self, now: Timestamp) -> Vec<OrderbookLog> {
+        if self.resume_at.is_none_or(|resume_at| resume_at > now) {
+            return vec![];
         }
-        None
+        self.resume(false)
     }
 
-    // Find the best sell order and pop it off the heap
-    fn get_best_sell_order(&mut self) -> Option<SellOrder> {
-        while let Some(best_order) = self.sell_orders.pop() {
-            if !self.lazy_deleted.contains(&best_order.order_id) {
-                return Some(best_order);
-            } else {
-                self.lazy_deleted.remove(&best_order.order_id);
-                continue;
-            }
+    pub fn state(&self) -> &TickerState {
+        &self.state
+    }
+
+    fn set_state(&mut self, state: TickerState) -> OrderbookLog {
+        self.state = state.clone();
+        OrderbookLog::EventLog(Event::TickerStatus(TickerStatus {
+            ticker: self.ticker.clone(),
+            state,
+        }))
+    }
+
+    // Halt the ticker or put it in cancel-only mode, resting orders stay in the book
+    // - a halted book accumulates orders like a call auction until trading resumes
+    pub fn halt(&mut self, state: TickerState) -> OrderbookLog {
+        if state == TickerState::Halted && self.auction_reference.is_none() {
+            self.auction_reference = Some(self.last_price.unwrap_or(self.close_price));
+        }
+        self.resume_at = None;
+        self.set_state(state)
+    }
+
+    // Resume continuous trading, uncrossing the orders accumulated since the interruption unless the
+    // session is in a call auction: the session uncross takes care of them then
+    pub fn resume(&mut self, in_session_auction: bool) -> Vec<OrderbookLog> {
+        let mut responses = match in_session_auction {
+            true => vec![],
+            false => self.uncross_book().0,
+        };
+        self.resume_at = None;
+        responses.push(self.set_state(TickerState::Trading));
+        responses.extend(self.reprice_pegs());
+        responses
+    }
+
+    fn side(&self, direction: &Direction) -> &BookSide {
+        match direction {
+            Direction::Buy => &self.buy_orders,
+            Direction::Sell => &self.sell_orders,
+        }
+    }
+
+    fn side_mut(&mut self, direction: &Direction) -> &mut BookSide {
+        match direction {
+            Direction::Buy => &mut self.buy_orders,
+            Direction::Sell => &mut self.sell_orders,
+        }
+    }
+
+    // Check if an incoming order with limit price can trade with a resting order at resting_price
+    fn crosses(direction: &Direction, limit_price: Price, resting_price: Price) -> bool {
+        match direction {
+            Direction::Buy => resting_price <= limit_price,
+            Direction::Sell => resting_price >= limit_price,
         }
-        None
     }
 
     // Helper function to generate logs for ONE order in a trade: OrderFill and OrderExecuted
@@ -57,12 +192,15 @@ impl OrderBook {
         order_id: OrderId,
         fill_size: Size,
         fill_price: Price,
+        liquidity: Liquidity,
     ) -> Vec<OrderbookLog> {
         vec![
             OrderbookLog::OrderLog(OrderResponse::OrderFill(OrderFillResponse {
                 order_id,
                 fill_size,
                 fill_price,
+                liquidity,
+                fee: Cash::ZERO,
             })),
             OrderbookLog::EventLog(Event::OrderExecuted(OrderExecuted {
                 order_id,
@@ -73,197 +211,731 @@ impl OrderBook {
         ]
     }
 
-    // Handle a new buy order. Could result in multiple trades and/or a new resting buy order and/or dead order for itself/other orders
-    fn handle_new_buy_order(&mut self, req: NewOrderRequest) -> Vec<OrderbookLog> {
+    fn generate_dead_log(order_id: OrderId, reason: DeadReason) -> OrderbookLog {
+        OrderbookLog::OrderLog(OrderResponse::OrderDead(OrderDeadResponse {
+            order_id,
+            reason,
+        }))
+    }
+
+    // Fill a resting order, removing it from the book once it is completely filled
+    fn fill_resting(
+        &mut self,
+        direction: &Direction,
+        order_id: OrderId,
+        resting_size: Size,
+        fill_size: Size,
+        fill_price: Price,
+        liquidity: Liquidity,
+    ) -> Vec<OrderbookLog> {
+        let mut responses = self.generate_trade_log(order_id, fill_size, fill_price, liquidity);
+        self.last_price = Some(fill_price);
+        if fill_size < resting_size {
+            self.side_mut(direction).reduce(&order_id, fill_size);
+        } else if let Some(shown) = self.side_mut(direction).replenish(&order_id) {
+            // iceberg order: the next slice goes to the back of the queue
+            let order = self.side(direction).get(&order_id).unwrap();
+            responses.push(OrderbookLog::EventLog(Event::OrderReplaced(
+                OrderReplaced {
+                    order_id,
+                    ticker: self.ticker.clone(),
+                    direction: direction.clone(),
+                    resting_size: shown,
+                    limit_price: order.price,
+                },
+            )));
+        } else {
+            self.side_mut(direction).remove(&order_id);
+            responses.push(Self::generate_dead_log(order_id, DeadReason::Filled));
+        }
+        responses
+    }
+
+    // Reduce the open size of a resting order to size in place, keeping its time priority
+    // - the hidden reserve of an iceberg order is reduced first
+    // - nothing is published for a hidden order
+    fn shrink_resting(
+        &mut self,
+        direction: &Direction,
+        resting: &RestingOrder,
+        size: Size,
+    ) -> Vec<OrderbookLog> {
+        let hidden_cut = std::cmp::min(
+            resting.hidden_size,
+            resting.size + resting.hidden_size - size,
+        );
+        let shown = std::cmp::min(resting.size, size);
+        self.side_mut(direction)
+            .reduce_hidden(&resting.order_id, hidden_cut);
+        self.side_mut(direction)
+            .reduce(&resting.order_id, resting.size - shown);
+        if resting.is_hidden() {
+            return vec![];
+        }
+        vec![OrderbookLog::EventLog(Event::OrderModified(
+            OrderModified {
+                order_id: resting.order_id,
+                ticker: self.ticker.clone(),
+                direction: direction.clone(),
+                resting_size: shown,
+                limit_price: resting.price,
+            },
+        ))]
+    }
+
+    // Cancel a resting order, e.g. by self-trade prevention
+    fn cancel_resting(
+        &mut self,
+        direction: &Direction,
+        order_id: OrderId,
+        reason: DeadReason,
+    ) -> Vec<OrderbookLog> {
+        let removed = self.side_mut(direction).remove(&order_id);
+        let mut responses = vec![Self::generate_dead_log(order_id, reason)];
+        if !removed.is_some_and(|order| order.is_hidden()) {
+            responses.push(OrderbookLog::EventLog(Event::OrderRemoved(OrderRemoved {
+                order_id,
+            })));
+        }
+        responses
+    }
+
+    // An incoming order meets a resting order of the same investor: apply the self-trade prevention mode
+    // Return the logs and the unfilled size of the incoming order, None if it is cancelled
+    fn prevent_self_trade(
+        &mut self,
+        mode: &SelfTradePrevention,
+        order_id: OrderId,
+        opposite: &Direction,
+        resting_id: OrderId,
+        left_size: Size,
+    ) -> (Vec<OrderbookLog>, Option<Size>) {
+        let resting = self.side(opposite).get(&resting_id).unwrap().clone();
+        let resting_total = resting.size + resting.hidden_size;
+        let (cancel_resting, cancel_incoming) = match mode {
+            SelfTradePrevention::CancelNewest => (false, true),
+            SelfTradePrevention::CancelOldest => (true, false),
+            SelfTradePrevention::CancelBoth => (true, true),
+            SelfTradePrevention::DecrementAndCancel => {
+                (resting_total <= left_size, resting_total >= left_size)
+            }
+        };
+        let decrement = *mode == SelfTradePrevention::DecrementAndCancel;
+        let mut responses = vec![];
+        let mut left_size = left_size;
+        if cancel_resting {
+            responses.extend(self.cancel_resting(opposite, resting_id, DeadReason::SelfTrade));
+        } else if decrement {
+            responses.push(OrderbookLog::OrderLog(OrderResponse::OrderReduced(
+                OrderReducedResponse {
+                    order_id: resting_id,
+                    reduced_size: left_size,
+                },
+            )));
+            responses.extend(self.shrink_resting(opposite, &resting, resting_total - left_size));
+        }
+        if cancel_incoming {
+            responses.push(Self::generate_dead_log(order_id, DeadReason::SelfTrade));
+            return (responses, None);
+        }
+        if decrement {
+            responses.push(OrderbookLog::OrderLog(OrderResponse::OrderReduced(
+                OrderReducedResponse {
+                    order_id,
+                    reduced_size: resting_total,
+                },
+            )));
+            left_size -= resting_total;
+        }
+        (responses, Some(left_size))
+    }
+
+    // Match an incoming order with resting orders on the opposite side. Return the logs and the unfilled size,
+    // None if self-trade prevention cancelled the incoming order
+    // - nothing matches during a call auction
+    // - a trade outside the price bands starts a volatility auction instead, at timestamp
+    // - only a midpoint pegged order trades with levels off the tick, i.e. other midpoint pegged orders
+    fn match_incoming(
+        &mut self,
+        order_id: OrderId,
+        owner: &OrderOwner,
+        direction: &Direction,
+        price: Price,
+        size: Size,
+        timestamp: Timestamp,
+    ) -> (Vec<OrderbookLog>, Option<Size>) {
         let mut responses: Vec<OrderbookLog> = vec![];
-        let mut left_size: Size = req.size;
+        let mut left_size: Size = size;
+        if self.auction_reference.is_some() {
+            return (responses, Some(left_size));
+        }
+        let opposite = direction.opposite();
+        let midpoint = self
+            .pegs
+            .get(&order_id)
+            .is_some_and(|peg| peg.reference == PegReference::Midpoint);
 
-        // try matching with resting sell orders
-        while let Some(best_sell_order) = self.get_best_sell_order() {
-            if best_sell_order.price > req.price || left_size == 0 {
-                self.sell_orders.push(best_sell_order);
+        while left_size > 0 {
+            let best_level = self
+                .side(&opposite)
+                .levels()
+                .find(|level| midpoint || self.on_tick(level.price));
+            let (fill_price, orders, displayed, own_position) = match best_level {
+                Some(level) if Self::crosses(direction, price, level.price) => {
+                    let level_orders = self.side(&opposite).level_orders(level);
+                    let orders: Vec<(OrderId, Size, InvId)> = level_orders
+                        .map(|order| (order.order_id, order.size, order.owner.inv_id))
+                        .collect();
+                    let displayed = self
+                        .side(&opposite)
+                        .level_orders(level)
+                        .take_while(|order| !order.is_hidden())
+                        .count();
+                    let own_position = orders
+                        .iter()
+                        .position(|(_, _, inv_id)| *inv_id == owner.inv_id);
+                    (level.price, orders, displayed, own_position)
+                }
+                _ => break,
+            };
+            if !self.within_bands(fill_price, self.last_price) {
+                responses.push(self.start_volatility_auction(timestamp));
                 break;
             }
-            let fill_size: Size = std::cmp::min(req.size, best_sell_order.size);
-            let fill_price: Price = best_sell_order.price;
+            let mut eligible: Vec<(OrderId, Size)> = orders
+                .iter()
+                .map(|&(resting_id, resting_size, _)| (resting_id, resting_size))
+                .collect();
 
-            // modify resting order
-            responses.extend(self.generate_trade_log(
-                best_sell_order.order_id,
-                fill_size,
-                fill_price,
-            ));
-            if fill_size < best_sell_order.size {
-                self.sell_orders.push(SellOrder {
-                    order_id: best_sell_order.order_id,
-                    size: best_sell_order.size - fill_size,
-                    price: best_sell_order.price,
-                    timestamp: best_sell_order.timestamp,
-                });
-            } else {
-                responses.push(OrderbookLog::OrderLog(OrderResponse::OrderDead(
-                    OrderDeadResponse {
-                        order_id: best_sell_order.order_id,
-                    },
-                )));
+            // self-trade prevention deals with the investor's own orders at this price before the level is
+            // allocated, with price-time priority only once the orders ahead of them have traded
+            if let (Some(position), Some(mode)) = (own_position, &owner.self_trade_prevention) {
+                if position == 0 || self.matching_policy != MatchingPolicy::PriceTime {
+                    let resting_id = orders[position].0;
+                    let (logs, left) =
+                        self.prevent_self_trade(mode, order_id, &opposite, resting_id, left_size);
+                    responses.extend(logs);
+                    match left {
+                        Some(left) => left_size = left,
+                        None => return (responses, None),
+                    }
+                    continue;
+                }
+                eligible.truncate(position);
             }
 
-            // modify incoming order
-            responses.extend(self.generate_trade_log(req.order_id, fill_size, fill_price));
-            left_size -= fill_size;
-        }
-        // deal with remaining active buy order
-        let should_insert = left_size > 0
-            && req.limit_or_market == LimitOrMarket::Limit
-            && req.time_in_force == TimeInForce::Day;
-        if should_insert {
-            self.buy_orders.push(BuyOrder {
-                order_id: req.order_id,
-                size: left_size,
-                price: req.price,
-                timestamp: req.timestamp,
-            });
-            responses.push(OrderbookLog::EventLog(Event::OrderAdded(OrderAdded {
-                order_id: req.order_id,
-                ticker: self.ticker.clone(),
-                direction: req.direction,
-                resting_size: left_size,
-                limit_price: req.price,
-            })))
-        } else {
-            responses.push(OrderbookLog::OrderLog(OrderResponse::OrderDead(
-                OrderDeadResponse {
-                    order_id: req.order_id,
-                },
-            )));
+            // displayed orders are allocated before the hidden orders of the level
+            let (displayed, hidden) = eligible.split_at(displayed.min(eligible.len()));
+            let mut fills = self.matching_policy.allocate(displayed, left_size);
+            let filled: Size = fills.iter().map(|(_, fill_size)| fill_size).sum();
+            fills.extend(self.matching_policy.allocate(hidden, left_size - filled));
+            for (resting_id, fill_size) in fills {
+                let resting_size = eligible
+                    .iter()
+                    .find(|(id, _)| *id == resting_id)
+                    .map_or(0, |(_, size)| *size);
+
+                // modify resting order
+                responses.extend(self.fill_resting(
+                    &opposite,
+                    resting_id,
+                    resting_size,
+                    fill_size,
+                    fill_price,
+                    Liquidity::Maker,
+                ));
+
+                // modify incoming order
+                responses.extend(self.generate_trade_log(
+                    order_id,
+                    fill_size,
+                    fill_price,
+                    Liquidity::Taker,
+                ));
+                left_size -= fill_size;
+            }
         }
+        (responses, Some(left_size))
+    }
 
-        responses
+    // Total resting size on the opposite side an incoming order could trade with, hidden size included
+    fn available_size(&self, direction: &Direction, price: Price) -> Size {
+        self.side(&direction.opposite())
+            .levels()
+            .take_while(|level| Self::crosses(direction, price, level.price))
+            .map(|level| level.total_size + level.hidden_size)
+            .sum()
     }
 
-    // Handle a new sell order. Could result in multiple trades and/or a new resting sell order and/or dead order for itself/other orders
-    fn handle_new_sell_order(&mut self, req: NewOrderRequest) -> Vec<OrderbookLog> {
-        let mut responses: Vec<OrderbookLog> = Vec::new();
-        let mut left_size: Size = req.size;
+    fn on_tick(&self, price: Price) -> bool {
+        price.is_multiple_of(&self.tick_size)
+    }
 
-        // try matching with resting buy orders
-        while let Some(best_buy_order) = self.get_best_buy_order() {
-            if best_buy_order.price < req.price || left_size == 0 {
-                self.buy_orders.push(best_buy_order);
+    // Opposite price levels an incoming order that is not a midpoint pegged order could trade with
+    fn reachable_levels<'a>(
+        &'a self,
+        direction: &'a Direction,
+        price: Price,
+    ) -> impl Iterator<Item = &'a PriceLevel> + 'a {
+        self.side(&direction.opposite())
+            .levels()
+            .take_while(move |level| Self::crosses(direction, price, level.price))
+            .filter(|level| self.on_tick(level.price))
+    }
+
+    // Size an incoming order could execute immediately, hidden size included
+    // - with self-trade prevention the investor's own orders do not count, and nothing after the first
+    //   of them does if it would cancel the incoming order
+    // - nothing counts from the first level outside the price bands, where matching would stop for a
+    //   volatility auction; the dynamic band moves with the levels traded before it
+    fn executable_size(&self, direction: &Direction, price: Price, owner: &OrderOwner) -> Size {
+        let cancels_incoming = matches!(
+            owner.self_trade_prevention,
+            Some(SelfTradePrevention::CancelNewest | SelfTradePrevention::CancelBoth)
+        );
+        let opposite = self.side(&direction.opposite());
+        let (mut size, mut last_price) = (0, self.last_price);
+        for level in self.reachable_levels(direction, price) {
+            if !self.within_bands(level.price, last_price) {
                 break;
             }
-            let fill_size: Size = std::cmp::min(req.size, best_buy_order.size);
-            let fill_price: Price = best_buy_order.price;
-
-            // modify resting order
-            responses.extend(self.generate_trade_log(
-                best_buy_order.order_id,
-                fill_size,
-                fill_price,
-            ));
-            if fill_size < best_buy_order.size {
-                self.buy_orders.push(BuyOrder {
-                    order_id: best_buy_order.order_id,
-                    size: best_buy_order.size - fill_size,
-                    price: best_buy_order.price,
-                    timestamp: best_buy_order.timestamp,
-                });
+            if owner.self_trade_prevention.is_none() {
+                size += level.total_size + level.hidden_size;
             } else {
-                responses.push(OrderbookLog::OrderLog(OrderResponse::OrderDead(
-                    OrderDeadResponse {
-                        order_id: best_buy_order.order_id,
-                    },
-                )));
+                for order in opposite.level_orders(level) {
+                    if order.owner.inv_id != owner.inv_id {
+                        size += order.size + order.hidden_size;
+                    } else if cancels_incoming {
+                        return size;
+                    }
+                }
             }
+            last_price = Some(level.price);
+        }
+        size
+    }
 
-            // modify incoming order
-            responses.extend(self.generate_trade_log(req.order_id, fill_size, fill_price));
-            left_size -= fill_size;
+    // Apply the post-only flag to an order: None if it must be rejected, otherwise its (possibly
+    // repriced) limit price
+    fn post_only_price(&self, req: &NewOrderRequest, post_only: &PostOnly) -> Option<Price> {
+        let best = self.side(&req.direction.opposite()).best_price();
+        let crossing = best.filter(|best| {
+            self.auction_reference.is_none() && Self::crosses(&req.direction, req.price, *best)
+        });
+        let Some(best) = crossing else {
+            return Some(req.price);
+        };
+        match post_only {
+            PostOnly::Reject => None,
+            PostOnly::Reprice => {
+                let price = match req.direction {
+                    Direction::Buy => best - self.tick_size,
+                    Direction::Sell => best + self.tick_size,
+                };
+                (price > Price::ZERO).then_some(price)
+            }
+        }
+    }
+
+    // Handle a new order. Could result in multiple trades and/or a new resting order and/or dead order for itself/other orders
+    // - a pegged order enters at the price derived from its reference, it dies without a reference
+    fn handle_new_order(&mut self, mut req: NewOrderRequest) -> Vec<OrderbookLog> {
+        if let LimitOrMarket::Peg(reference, offset) = &req.limit_or_market {
+            let Some(price) = self.peg_price(&req.direction, reference, *offset, req.price) else {
+                return vec![Self::generate_dead_log(req.order_id, DeadReason::Unfilled)];
+            };
+            let peg = Peg {
+                direction: req.direction.clone(),
+                reference: reference.clone(),
+                offset: *offset,
+                cap: req.price,
+            };
+            self.pegs.insert(req.order_id, peg);
+            req.price = price;
+        }
+        let owner = OrderOwner {
+            inv_id: req.inv_id,
+            self_trade_prevention: req.self_trade_prevention.clone(),
+        };
+        let available_size = self.executable_size(&req.direction, req.price, &owner);
+        // fill or kill: no trade at all unless the whole size can be filled, before any price band stops
+        // the matching
+        if req.time_in_force == TimeInForce::FOK && available_size < req.size {
+            return vec![Self::generate_dead_log(
+                req.order_id,
+                DeadReason::FillOrKill,
+            )];
+        }
+        // minimum quantity: no trade at all unless at least the minimum can be filled
+        if req
+            .min_quantity
+            .is_some_and(|min_quantity| available_size < min_quantity)
+        {
+            return vec![Self::generate_dead_log(
+                req.order_id,
+                DeadReason::MinQuantity,
+            )];
+        }
+        if let Some(post_only) = &req.post_only {
+            match self.post_only_price(&req, post_only) {
+                Some(price) => req.price = price,
+                None => return vec![Self::generate_dead_log(req.order_id, DeadReason::PostOnly)],
+            }
         }
-        // deal with remaining active sell order
+        let (mut responses, left_size) = self.match_incoming(
+            req.order_id,
+            &owner,
+            &req.direction,
+            req.price,
+            req.size,
+            req.timestamp,
+        );
+        let Some(left_size) = left_size else {
+            return responses;
+        };
+
+        // deal with remaining active order
         let should_insert = left_size > 0
-            && req.limit_or_market == LimitOrMarket::Limit
-            && req.time_in_force == TimeInForce::Day;
+            && matches!(
+                req.limit_or_market,
+                LimitOrMarket::Limit | LimitOrMarket::Peg(..)
+            )
+            && req.time_in_force.rests();
         if should_insert {
-            self.sell_orders.push(SellOrder {
-                order_id: req.order_id,
-                size: left_size,
-                price: req.price,
-                timestamp: req.timestamp,
-            });
-            responses.push(OrderbookLog::EventLog(Event::OrderAdded(OrderAdded {
-                order_id: req.order_id,
-                ticker: self.ticker.clone(),
-                direction: req.direction,
-                resting_size: left_size,
-                limit_price: req.price,
-            })))
+            self.side_mut(&req.direction).push_back(
+                req.order_id,
+                owner,
+                left_size,
+                req.display_size,
+                req.price,
+                req.timestamp,
+            );
+            // a hidden order rests without being published
+            let order = self.side(&req.direction).get(&req.order_id).unwrap();
+            if !order.is_hidden() {
+                responses.push(OrderbookLog::EventLog(Event::OrderAdded(OrderAdded {
+                    order_id: req.order_id,
+                    ticker: self.ticker.clone(),
+                    direction: req.direction,
+                    resting_size: order.size,
+                    limit_price: req.price,
+                })))
+            }
+        } else if left_size > 0 {
+            responses.push(Self::generate_dead_log(req.order_id, DeadReason::Unfilled));
+        } else {
+            responses.push(Self::generate_dead_log(req.order_id, DeadReason::Filled));
+        }
+
+        responses
+    }
+
+    // Remove a resting order from the book. Unknown or already dead orders produce no logs
+    fn handle_cancel_order(&mut self, req: CancelOrderRequest) -> Vec<OrderbookLog> {
+        let direction = if self.buy_orders.get(&req.order_id).is_some() {
+            Direction::Buy
+        } else if self.sell_orders.get(&req.order_id).is_some() {
+            Direction::Sell
         } else {
-            responses.push(OrderbookLog::OrderLog(OrderResponse::OrderDead(
-                OrderDeadResponse {
+            return vec![];
+        };
+        self.cancel_resting(&direction, req.order_id, DeadReason::Cancelled)
+    }
+
+    // Amend size and/or price of a resting order, the size of an iceberg order includes its hidden reserve
+    // - size down at the same price keeps time priority: OrderModified, the hidden reserve is reduced first
+    // - price change or size up loses time priority: OrderReplaced, then the order is matched
+    //   again as an incoming order and its remaining size goes to the back of the queue
+    fn handle_amend_order(&mut self, req: AmendOrderRequest) -> Vec<OrderbookLog> {
+        let (direction, resting) = match (
+            self.buy_orders.get(&req.order_id),
+            self.sell_orders.get(&req.order_id),
+        ) {
+            (Some(order), _) => (Direction::Buy, order.clone()),
+            (_, Some(order)) => (Direction::Sell, order.clone()),
+            _ => return vec![],
+        };
+
+        if req.price == resting.price && req.size <= resting.size + resting.hidden_size {
+            return self.shrink_resting(&direction, &resting, req.size);
+        }
+
+        self.side_mut(&direction).remove(&req.order_id);
+        let mut responses = vec![];
+        if !resting.is_hidden() {
+            responses.push(OrderbookLog::EventLog(Event::OrderReplaced(
+                OrderReplaced {
                     order_id: req.order_id,
+                    ticker: self.ticker.clone(),
+                    direction: direction.clone(),
+                    resting_size: resting
+                        .display_size
+                        .map_or(req.size, |display_size| display_size.min(req.size)),
+                    limit_price: req.price,
                 },
             )));
         }
-
+        let (trade_logs, left_size) = self.match_incoming(
+            req.order_id,
+            &resting.owner,
+            &direction,
+            req.price,
+            req.size,
+            req.timestamp,
+        );
+        responses.extend(trade_logs);
+        let Some(left_size) = left_size else {
+            return responses;
+        };
+        if left_size > 0 {
+            self.side_mut(&direction).push_back(
+                req.order_id,
+                resting.owner,
+                left_size,
+                resting.display_size,
+                req.price,
+                req.timestamp,
+            );
+        } else {
+            responses.push(Self::generate_dead_log(req.order_id, DeadReason::Filled));
+        }
         responses
     }
 
-    fn handle_new_order(&mut self, req: NewOrderRequest) -> Vec<OrderbookLog> {
-        match req.direction {
-            Direction::Buy => self.handle_new_buy_order(req),
-            Direction::Sell => self.handle_new_sell_order(req),
+    // Find the price executing the most volume, then leaving the least imbalance, then closest to the reference price
+    fn equilibrium(&self, reference_price: Price) -> Option<Equilibrium> {
+        self.buy_orders
+            .levels()
+            .chain(self.sell_orders.levels())
+            .map(|level| Equilibrium {
+                price: level.price,
+                buy_size: self.available_size(&Direction::Sell, level.price),
+                sell_size: self.available_size(&Direction::Buy, level.price),
+            })
+            .filter(|eq| eq.matched_size() > 0)
+            .min_by_key(|eq| {
+                (
+                    Reverse(eq.matched_size()),
+                    eq.imbalance_size(),
+                    (eq.price - reference_price).abs(),
+                    eq.price,
+                )
+            })
+    }
+
+    fn generate_indicative_log(&self, reference_price: Price) -> OrderbookLog {
+        let eq = self.equilibrium(reference_price);
+        OrderbookLog::EventLog(Event::AuctionIndicative(AuctionIndicative {
+            ticker: self.ticker.clone(),
+            indicative_price: eq.as_ref().map(|eq| eq.price),
+            matched_size: eq.as_ref().map_or(0, |eq| eq.matched_size()),
+            imbalance_size: eq.as_ref().map_or(0, |eq| eq.imbalance_size()),
+            imbalance_direction: eq.as_ref().and_then(|eq| eq.imbalance_direction()),
+        }))
+    }
+
+    // Start a call auction, ties between equilibrium prices go to the one closest to reference_price
+    pub fn start_auction(&mut self, reference_price: Price) {
+        self.auction_reference = Some(reference_price);
+    }
+
+    // End the call auction of the session, a volatility auction included, and resume continuous trading
+    // - a ticker halted or in cancel-only mode by an operator stays so, nothing trades
+    // Return the logs and the clearing price if anything traded
+    pub fn uncross(&mut self) -> (Vec<OrderbookLog>, Option<Price>) {
+        if matches!(self.state, TickerState::Halted | TickerState::CancelOnly) {
+            return (vec![], None);
+        }
+        let (mut responses, clearing_price) = self.uncross_book();
+        if self.state == TickerState::VolatilityAuction {
+            self.resume_at = None;
+            responses.push(self.set_state(TickerState::Trading));
         }
+        responses.extend(self.reprice_pegs());
+        (responses, clearing_price)
     }
 
-    fn handle_cancel_order(&mut self, req: CancelOrderRequest) -> Vec<OrderbookLog> {
-        self.lazy_deleted.insert(req.order_id);
-        vec![
-            OrderbookLog::OrderLog(OrderResponse::OrderDead(OrderDeadResponse {
-                order_id: req.order_id,
-            })),
-            OrderbookLog::EventLog(Event::OrderRemoved(OrderRemoved {
-                order_id: req.order_id,
-            })),
-        ]
+    // All crossing orders trade at the equilibrium price in price-time priority
+    fn uncross_book(&mut self) -> (Vec<OrderbookLog>, Option<Price>) {
+        let Some(reference_price) = self.auction_reference.take() else {
+            return (vec![], None);
+        };
+        let Some(eq) = self.equilibrium(reference_price) else {
+            return (vec![], None);
+        };
+        let mut responses: Vec<OrderbookLog> = vec![];
+        let mut left_size = eq.matched_size();
+        while left_size > 0 {
+            let (Some(buy), Some(sell)) = (self.buy_orders.front(), self.sell_orders.front())
+            else {
+                break;
+            };
+            let (buy_id, buy_size) = (buy.order_id, buy.size);
+            let (sell_id, sell_size) = (sell.order_id, sell.size);
+            let fill_size = left_size.min(buy_size).min(sell_size);
+            responses.extend(self.fill_resting(
+                &Direction::Buy,
+                buy_id,
+                buy_size,
+                fill_size,
+                eq.price,
+                Liquidity::Auction,
+            ));
+            responses.extend(self.fill_resting(
+                &Direction::Sell,
+                sell_id,
+                sell_size,
+                fill_size,
+                eq.price,
+                Liquidity::Auction,
+            ));
+            left_size -= fill_size;
+        }
+        (responses, Some(eq.price))
     }
 
     // Handle a request from the portal
+    // - during a call auction every change of the book publishes the new indicative uncross
     pub fn handle_request(&mut self, req: OrderbookRequest) -> Vec<OrderbookLog> {
-        match req {
-            OrderbookRequest::NewOrder(new_order_req) => self.handle_new_order(new_order_req),
+        let mut logs = match req {
+            OrderbookRequest::NewOrder(new_order_req) => {
+                self.now = self.now.max(new_order_req.timestamp);
+                self.handle_new_order(new_order_req)
+            }
             OrderbookRequest::CancelOrder(cancel_order_req) => {
                 self.handle_cancel_order(cancel_order_req)
             }
+            OrderbookRequest::AmendOrder(amend_order_req) => {
+                self.now = self.now.max(amend_order_req.timestamp);
+                self.handle_amend_order(amend_order_req)
+            }
+        };
+        logs.extend(self.reprice_pegs());
+        if let Some(reference_price) = self.auction_reference {
+            if !logs.is_empty() {
+                logs.push(self.generate_indicative_log(reference_price));
+            }
         }
+        logs
     }
 
-    // Get the best buy price without modifying the orderbook
-    pub fn best_buy_price(&mut self) -> Option<Price> {
-        if let Some(best_buy_order) = self.get_best_buy_order() {
-            let best_price = best_buy_order.price.clone();
-            self.buy_orders.push(best_buy_order);
-            Some(best_price)
-        } else {
-            None
+    // Best price of one side among the orders that are neither pegged nor hidden
+    fn lit_best_price(&self, direction: &Direction) -> Option<Price> {
+        let side = self.side(direction);
+        side.levels()
+            .find(|level| {
+                side.level_orders(level)
+                    .any(|order| !order.is_hidden() && !self.pegs.contains_key(&order.order_id))
+            })
+            .map(|level| level.price)
+    }
+
+    // Price of a pegged order given the current best prices, None if its reference is missing
+    // - the offset moves it away from the opposite side, the cap bounds it
+    pub fn peg_price(
+        &self,
+        direction: &Direction,
+        reference: &PegReference,
+        offset: Price,
+        cap: Price,
+    ) -> Option<Price> {
+        let price = match (reference, direction) {
+            (PegReference::Primary, Direction::Buy) => self.best_buy_price()?,
+            (PegReference::Primary, Direction::Sell) => self.best_sell_price()?,
+            (PegReference::Midpoint, _) => {
+                let sum = self.best_buy_price()? + self.best_sell_price()?;
+                Price::from_units(sum.units() / 2)
+            }
+        };
+        let price = match direction {
+            Direction::Buy => std::cmp::min(price - offset, cap),
+            Direction::Sell => std::cmp::max(price + offset, cap),
+        };
+        (price > Price::ZERO).then_some(price)
+    }
+
+    pub fn is_pegged(&self, order_id: &OrderId) -> bool {
+        self.pegs.contains_key(order_id)
+    }
+
+    // Move the pegged orders whose reference changed, in time priority of their entry
+    // - a moved order is amended to its new price: OrderReplaced, it loses time priority and may trade
+    // - a pegged order keeps its price while its reference is missing, and during a call auction
+    fn reprice_pegs(&mut self) -> Vec<OrderbookLog> {
+        let mut logs = vec![];
+        let order_ids: Vec<OrderId> = self.pegs.keys().copied().collect();
+        for order_id in order_ids {
+            if self.auction_reference.is_some() {
+                break;
+            }
+            let peg = &self.pegs[&order_id];
+            let Some(resting) = self.side(&peg.direction).get(&order_id) else {
+                // dead by now
+                self.pegs.remove(&order_id);
+                continue;
+            };
+            let size = resting.size + resting.hidden_size;
+            let price = self.peg_price(&peg.direction, &peg.reference, peg.offset, peg.cap);
+            if let Some(price) = price.filter(|price| *price != resting.price) {
+                logs.extend(self.handle_amend_order(AmendOrderRequest {
+                    order_id,
+                    size,
+                    price,
+                    timestamp: self.now,
+                }));
+            }
         }
+        logs
     }
 
-    // Get the best sell price without modifying the orderbook
-    pub fn best_sell_price(&mut self) -> Option<Price> {
-        if let Some(best_sell_order) = self.get_best_sell_order() {
-            let best_price = best_sell_order.price.clone();
-            self.sell_orders.push(best_sell_order);
-            Some(best_price)
-        } else {
-            None
+    // Worst price an incoming market order of size reaches sweeping the opposite side, not beyond limit
+    // None if there is nothing it could trade with
+    // - levels off the tick are skipped, only midpoint pegged orders trade there
+    pub fn sweep_price(
+        &self,
+        direction: &Direction,
+        size: Size,
+        limit: Option<Price>,
+    ) -> Option<Price> {
+        let mut left_size = size;
+        let mut worst = None;
+        let levels = self.side(&direction.opposite()).levels();
+        for level in levels.filter(|level| self.on_tick(level.price)) {
+            if left_size == 0
+                || limit.is_some_and(|limit| !Self::crosses(direction, limit, level.price))
+            {
+                break;
+            }
+            worst = Some(level.price);
+            left_size = left_size.saturating_sub(level.total_size + level.hidden_size);
         }
+        worst
+    }
+
+    // Get the best buy price, pegged and hidden orders aside
+    pub fn best_buy_price(&self) -> Option<Price> {
+        self.lit_best_price(&Direction::Buy)
+    }
+
+    // Get the best sell price, pegged and hidden orders aside
+    pub fn best_sell_price(&self) -> Option<Price> {
+        self.lit_best_price(&Direction::Sell)
+    }
+
+    // Iterate the displayed price levels of one side, from the best price to the worst price; levels of
+    // hidden orders only are left out
+    pub fn depth(&self, direction: &Direction) -> impl Iterator<Item = &PriceLevel> + '_ {
+        self.side(direction)
+            .levels()
+            .filter(|level| level.total_size > 0)
     }
 }
 
 #[cfg(test)]
 mod tests {
-    use crate::types::event::{OrderAdded, OrderExecuted, OrderRemoved};
+    use crate::types::event::{
+        AuctionIndicative, OrderAdded, OrderExecuted, OrderModified, OrderRemoved, OrderReplaced,
+    };
 
     use super::*;
 
@@ -288,74 +960,103 @@ mod tests {
         // 104 buy 80 @ 5.0 4
         // cancel 101
         // 105 sell 200 @ 5.0 5
-        let mut order_book = OrderBook::new("AAPL".to_string());
+        let mut order_book = OrderBook::new(
+            "AAPL".to_string(),
+            Price::from_units(100),
+            MatchingPolicy::PriceTime,
+        );
         let req1 = NewOrderRequest {
             order_id: 101,
+            inv_id: 1,
             direction: Direction::Buy,
             size: 100,
-            price: 10.0,
+            price: Price::from_int(10),
             timestamp: 1,
             limit_or_market: LimitOrMarket::Limit,
             time_in_force: TimeInForce::Day,
+            display_size: None,
+            post_only: None,
+            min_quantity: None,
+            self_trade_prevention: None,
         };
         let req2 = NewOrderRequest {
             order_id: 102,
+            inv_id: 1,
             direction: Direction::Buy,
             size: 100,
-            price: 15.0,
+            price: Price::from_int(15),
             timestamp: 2,
             limit_or_market: LimitOrMarket::Limit,
             time_in_force: TimeInForce::Day,
+            display_size: None,
+            post_only: None,
+            min_quantity: None,
+            self_trade_prevention: None,
         };
         let req3 = NewOrderRequest {
             order_id: 103,
+            inv_id: 1,
             direction: Direction::Sell,
             size: 50,
-            price: 5.0,
+            price: Price::from_int(5),
             timestamp: 3,
             limit_or_market: LimitOrMarket::Limit,
             time_in_force: TimeInForce::Day,
+            display_size: None,
+            post_only: None,
+            min_quantity: None,
+            self_trade_prevention: None,
         };
         let req4 = NewOrderRequest {
             order_id: 104,
+            inv_id: 1,
             direction: Direction::Buy,
             size: 80,
-            price: 5.0,
+            price: Price::from_int(5),
             timestamp: 4,
             limit_or_market: LimitOrMarket::Limit,
             time_in_force: TimeInForce::Day,
+            display_size: None,
+            post_only: None,
+            min_quantity: None,
+            self_trade_prevention: None,
         };
         let req5 = CancelOrderRequest { order_id: 101 };
         let req6 = NewOrderRequest {
             order_id: 105,
+            inv_id: 1,
             direction: Direction::Sell,
             size: 200,
-            price: 5.0,
+            price: Price::from_int(5),
             timestamp: 5,
             limit_or_market: LimitOrMarket::Limit,
             time_in_force: TimeInForce::Day,
+            display_size: None,
+            post_only: None,
+            min_quantity: None,
+            self_trade_prevention: None,
         };
         let _ = order_book.handle_new_order(req1);
-        assert!(order_book.best_buy_price().unwrap() == 10.0);
+        assert!(order_book.best_buy_price().unwrap() == Price::from_int(10));
         assert!(order_book.best_sell_price().is_none());
         let _ = order_book.handle_new_order(req2);
-        assert!(order_book.best_buy_price().unwrap() == 15.0);
+        assert!(order_book.best_buy_price().unwrap() == Price::from_int(15));
         assert!(order_book.best_sell_price().is_none());
         let _ = order_book.handle_new_order(req3);
-        assert!(order_book.best_buy_price().unwrap() == 15.0);
+        assert!(order_book.best_buy_price().unwrap() == Price::from_int(15));
         assert!(order_book.best_sell_price().is_none());
 
         let _ = order_book.handle_new_order(req4);
-        assert!(order_book.best_buy_price().unwrap() == 15.0);
+        assert!(order_book.best_buy_price().unwrap() == Price::from_int(15));
         assert!(order_book.best_sell_price().is_none());
 
         let _ = order_book.handle_cancel_order(req5);
-        assert!(order_book.best_buy_price().unwrap() == 15.0);
+        assert!(order_book.best_buy_price().unwrap() == Price::from_int(15));
         assert!(order_book.best_sell_price().is_none());
 
         let _ = order_book.handle_new_order(req6);
         assert!(order_book.best_buy_price().is_none());
-        assert!(order_book.best_sell_price().unwrap() == 5.0);
+        assert!(order_book.best_sell_price().unwrap() == Price::from_int(5));
     }
 
     #[test]
@@ -367,53 +1068,82 @@ mod tests {
         // 104 sell 100 @ 4.0 4
         // 105 buy 100 @ 7.0 5
 
-        let mut order_book = OrderBook::new("AAPL".to_string());
+        let mut order_book = OrderBook::new(
+            "AAPL".to_string(),
+            Price::from_units(100),
+            MatchingPolicy::PriceTime,
+        );
 
         let req1 = NewOrderRequest {
             order_id: 101,
+            inv_id: 1,
             direction: Direction::Buy,
             size: 100,
-            price: 10.0,
+            price: Price::from_int(10),
             timestamp: 1,
             limit_or_market: LimitOrMarket::Limit,
             time_in_force: TimeInForce::Day,
+            display_size: None,
+            post_only: None,
+            min_quantity: None,
+            self_trade_prevention: None,
         };
         let req2 = NewOrderRequest {
             order_id: 102,
+            inv_id: 1,
             direction: Direction::Buy,
             size: 100,
-            price: 6.0,
+            price: Price::from_int(6),
             timestamp: 2,
             limit_or_market: LimitOrMarket::Limit,
             time_in_force: TimeInForce::Day,
+            display_size: None,
+            post_only: None,
+            min_quantity: None,
+            self_trade_prevention: None,
         };
         let req3 = CancelOrderRequest { order_id: 101 };
         let req4 = NewOrderRequest {
             order_id: 103,
+            inv_id: 1,
             direction: Direction::Sell,
             size: 50,
-            price: 5.0,
+            price: Price::from_int(5),
             timestamp: 3,
             limit_or_market: LimitOrMarket::Limit,
             time_in_force: TimeInForce::Day,
+            display_size: None,
+            post_only: None,
+            min_quantity: None,
+            self_trade_prevention: None,
         };
         let req5 = NewOrderRequest {
             order_id: 104,
+            inv_id: 1,
             direction: Direction::Sell,
             size: 100,
-            price: 4.0,
+            price: Price::from_int(4),
             timestamp: 4,
             limit_or_market: LimitOrMarket::Limit,
             time_in_force: TimeInForce::Day,
+            display_size: None,
+            post_only: None,
+            min_quantity: None,
+            self_trade_prevention: None,
         };
         let req6 = NewOrderRequest {
             order_id: 105,
+            inv_id: 1,
             direction: Direction::Buy,
             size: 100,
-            price: 7.0,
+            price: Price::from_int(7),
             timestamp: 5,
             limit_or_market: LimitOrMarket::Limit,
             time_in_force: TimeInForce::Day,
+            display_size: None,
+            post_only: None,
+            min_quantity: None,
+            self_trade_prevention: None,
         };
 
         let resp1: Vec<OrderbookLog> = order_book.handle_new_order(req1);
@@ -422,7 +1152,7 @@ mod tests {
             ticker: "AAPL".to_string(),
             direction: Direction::Buy,
             resting_size: 100,
-            limit_price: 10.0,
+            limit_price: Price::from_int(10),
         }))];
         assert!(same_response_list(resp1, expected_resp1));
 
@@ -432,7 +1162,7 @@ mod tests {
             ticker: "AAPL".to_string(),
             direction: Direction::Buy,
             resting_size: 100,
-            limit_price: 6.0,
+            limit_price: Price::from_int(6),
         }))];
         assert!(same_response_list(resp2, expected_resp2));
 
@@ -440,6 +1170,7 @@ mod tests {
         let expected_resp3 = vec![
             OrderbookLog::OrderLog(OrderResponse::OrderDead(OrderDeadResponse {
                 order_id: 101,
+                reason: DeadReason::Cancelled,
             })),
             OrderbookLog::EventLog(Event::OrderRemoved(OrderRemoved { order_id: 101 })),
         ];
@@ -450,27 +1181,32 @@ mod tests {
             OrderbookLog::OrderLog(OrderResponse::OrderFill(OrderFillResponse {
                 order_id: 102,
                 fill_size: 50,
-                fill_price: 6.0,
+                fill_price: Price::from_int(6),
+                liquidity: Liquidity::Maker,
+                fee: Cash::ZERO,
             })),
             OrderbookLog::EventLog(Event::OrderExecuted(OrderExecuted {
                 order_id: 102,
                 ticker: "AAPL".to_string(),
                 execution_size: 50,
-                execution_price: 6.0,
+                execution_price: Price::from_int(6),
             })),
             OrderbookLog::OrderLog(OrderResponse::OrderFill(OrderFillResponse {
                 order_id: 103,
                 fill_size: 50,
-                fill_price: 6.0,
+                fill_price: Price::from_int(6),
+                liquidity: Liquidity::Taker,
+                fee: Cash::ZERO,
             })),
             OrderbookLog::EventLog(Event::OrderExecuted(OrderExecuted {
                 order_id: 103,
                 ticker: "AAPL".to_string(),
                 execution_size: 50,
-                execution_price: 6.0,
+                execution_price: Price::from_int(6),
             })),
             OrderbookLog::OrderLog(OrderResponse::OrderDead(OrderDeadResponse {
                 order_id: 103,
+                reason: DeadReason::Filled,
             })),
         ];
         assert!(same_response_list(resp4, expected_resp4));
@@ -480,34 +1216,39 @@ mod tests {
             OrderbookLog::OrderLog(OrderResponse::OrderFill(OrderFillResponse {
                 order_id: 102,
                 fill_size: 50,
-                fill_price: 6.0,
+                fill_price: Price::from_int(6),
+                liquidity: Liquidity::Maker,
+                fee: Cash::ZERO,
             })),
             OrderbookLog::EventLog(Event::OrderExecuted(OrderExecuted {
                 order_id: 102,
                 ticker: "AAPL".to_string(),
                 execution_size: 50,
-                execution_price: 6.0,
+                execution_price: Price::from_int(6),
             })),
             OrderbookLog::OrderLog(OrderResponse::OrderDead(OrderDeadResponse {
                 order_id: 102,
+                reason: DeadReason::Filled,
             })),
             OrderbookLog::OrderLog(OrderResponse::OrderFill(OrderFillResponse {
                 order_id: 104,
                 fill_size: 50,
-                fill_price: 6.0,
+                fill_price: Price::from_int(6),
+                liquidity: Liquidity::Taker,
+                fee: Cash::ZERO,
             })),
             OrderbookLog::EventLog(Event::OrderExecuted(OrderExecuted {
                 order_id: 104,
                 ticker: "AAPL".to_string(),
                 execution_size: 50,
-                execution_price: 6.0,
+                execution_price: Price::from_int(6),
             })),
             OrderbookLog::EventLog(Event::OrderAdded(OrderAdded {
                 order_id: 104,
                 ticker: "AAPL".to_string(),
                 direction: Direction::Sell,
                 resting_size: 50,
-                limit_price: 4.0,
+                limit_price: Price::from_int(4),
             })),
         ];
         assert!(same_response_list(resp5, expected_resp5));
@@ -517,34 +1258,39 @@ mod tests {
             OrderbookLog::OrderLog(OrderResponse::OrderFill(OrderFillResponse {
                 order_id: 104,
                 fill_size: 50,
-                fill_price: 4.0,
+                fill_price: Price::from_int(4),
+                liquidity: Liquidity::Maker,
+                fee: Cash::ZERO,
             })),
             OrderbookLog::EventLog(Event::OrderExecuted(OrderExecuted {
                 order_id: 104,
                 ticker: "AAPL".to_string(),
                 execution_size: 50,
-                execution_price: 4.0,
+                execution_price: Price::from_int(4),
             })),
             OrderbookLog::OrderLog(OrderResponse::OrderDead(OrderDeadResponse {
                 order_id: 104,
+                reason: DeadReason::Filled,
             })),
             OrderbookLog::OrderLog(OrderResponse::OrderFill(OrderFillResponse {
                 order_id: 105,
                 fill_size: 50,
-                fill_price: 4.0,
+                fill_price: Price::from_int(4),
+                liquidity: Liquidity::Taker,
+                fee: Cash::ZERO,
             })),
             OrderbookLog::EventLog(Event::OrderExecuted(OrderExecuted {
                 order_id: 105,
                 ticker: "AAPL".to_string(),
                 execution_size: 50,
-                execution_price: 4.0,
+                execution_price: Price::from_int(4),
             })),
             OrderbookLog::EventLog(Event::OrderAdded(OrderAdded {
                 order_id: 105,
                 ticker: "AAPL".to_string(),
                 direction: Direction::Buy,
                 resting_size: 50,
-                limit_price: 7.0,
+                limit_price: Price::from_int(7),
             })),
         ];
         assert!(same_response_list(resp6, expected_resp6));
@@ -556,15 +1302,24 @@ mod tests {
         // 102 sell 50 @ market 2
         // 103 sell 100 @ market 3
 
-        let mut order_book = OrderBook::new("AAPL".to_string());
+        let mut order_book = OrderBook::new(
+            "AAPL".to_string(),
+            Price::from_units(100),
+            MatchingPolicy::PriceTime,
+        );
         let req1 = NewOrderRequest {
             order_id: 101,
+            inv_id: 1,
             direction: Direction::Buy,
             size: 100,
-            price: 10.0,
+            price: Price::from_int(10),
             timestamp: 1,
             limit_or_market: LimitOrMarket::Limit,
             time_in_force: TimeInForce::Day,
+            display_size: None,
+            post_only: None,
+            min_quantity: None,
+            self_trade_prevention: None,
         };
         let resp1: Vec<OrderbookLog> = order_book.handle_new_order(req1);
         let expected_resp1 = vec![OrderbookLog::EventLog(Event::OrderAdded(OrderAdded {
@@ -572,87 +1327,108 @@ mod tests {
             ticker: "AAPL".to_string(),
             direction: Direction::Buy,
             resting_size: 100,
-            limit_price: 10.0,
+            limit_price: Price::from_int(10),
         }))];
         assert!(same_response_list(resp1, expected_resp1));
 
         let req2 = NewOrderRequest {
             order_id: 102,
+            inv_id: 1,
             direction: Direction::Sell,
             size: 50,
             price: order_book.best_buy_price().unwrap(),
             timestamp: 2,
             limit_or_market: LimitOrMarket::Market,
             time_in_force: TimeInForce::Day,
+            display_size: None,
+            post_only: None,
+            min_quantity: None,
+            self_trade_prevention: None,
         };
         let resp2: Vec<OrderbookLog> = order_book.handle_new_order(req2);
         let expected_resp2 = vec![
             OrderbookLog::OrderLog(OrderResponse::OrderFill(OrderFillResponse {
                 order_id: 101,
                 fill_size: 50,
-                fill_price: 10.0,
+                fill_price: Price::from_int(10),
+                liquidity: Liquidity::Maker,
+                fee: Cash::ZERO,
             })),
             OrderbookLog::EventLog(Event::OrderExecuted(OrderExecuted {
                 order_id: 101,
                 ticker: "AAPL".to_string(),
                 execution_size: 50,
-                execution_price: 10.0,
+                execution_price: Price::from_int(10),
             })),
             OrderbookLog::OrderLog(OrderResponse::OrderFill(OrderFillResponse {
                 order_id: 102,
                 fill_size: 50,
-                fill_price: 10.0,
+                fill_price: Price::from_int(10),
+                liquidity: Liquidity::Taker,
+                fee: Cash::ZERO,
             })),
             OrderbookLog::EventLog(Event::OrderExecuted(OrderExecuted {
                 order_id: 102,
                 ticker: "AAPL".to_string(),
                 execution_size: 50,
-                execution_price: 10.0,
+                execution_price: Price::from_int(10),
             })),
             OrderbookLog::OrderLog(OrderResponse::OrderDead(OrderDeadResponse {
                 order_id: 102,
+                reason: DeadReason::Filled,
             })),
         ];
         assert!(same_response_list(resp2, expected_resp2));
 
         let req3 = NewOrderRequest {
             order_id: 103,
+            inv_id: 1,
             direction: Direction::Sell,
             size: 100,
             price: order_book.best_buy_price().unwrap(),
             timestamp: 3,
             limit_or_market: LimitOrMarket::Market,
             time_in_force: TimeInForce::Day,
+            display_size: None,
+            post_only: None,
+            min_quantity: None,
+            self_trade_prevention: None,
         };
         let resp3: Vec<OrderbookLog> = order_book.handle_new_order(req3);
         let expected_resp3 = vec![
             OrderbookLog::OrderLog(OrderResponse::OrderFill(OrderFillResponse {
                 order_id: 101,
                 fill_size: 50,
-                fill_price: 10.0,
+                fill_price: Price::from_int(10),
+                liquidity: Liquidity::Maker,
+                fee: Cash::ZERO,
             })),
             OrderbookLog::EventLog(Event::OrderExecuted(OrderExecuted {
                 order_id: 101,
                 ticker: "AAPL".to_string(),
                 execution_size: 50,
-                execution_price: 10.0,
+                execution_price: Price::from_int(10),
             })),
             OrderbookLog::OrderLog(OrderResponse::OrderDead(OrderDeadResponse {
                 order_id: 101,
+                reason: DeadReason::Filled,
             })),
             OrderbookLog::OrderLog(OrderResponse::OrderFill(OrderFillResponse {
                 order_id: 103,
                 fill_size: 50,
-                fill_price: 10.0,
+                fill_price: Price::from_int(10),
+                liquidity: Liquidity::Taker,
+                fee: Cash::ZERO,
             })),
             OrderbookLog::EventLog(Event::OrderExecuted(OrderExecuted {
                 order_id: 103,
                 ticker: "AAPL".to_string(),
                 execution_size: 50,
-                execution_price: 10.0,
+                execution_price: Price::from_int(10),
             })),
             OrderbookLog::OrderLog(OrderResponse::OrderDead(OrderDeadResponse {
                 order_id: 103,
+                reason: DeadReason::Unfilled,
             })),
         ];
         assert!(same_response_list(resp3, expected_resp3));
@@ -663,15 +1439,24 @@ mod tests {
         // 101 sell 100 @ 10.0 1
         // 102 buy 50 @ market 2
         // 103 buy 100 @ market 3
-        let mut order_book = OrderBook::new("AAPL".to_string());
+        let mut order_book = OrderBook::new(
+            "AAPL".to_string(),
+            Price::from_units(100),
+            MatchingPolicy::PriceTime,
+        );
         let req1 = NewOrderRequest {
             order_id: 101,
+            inv_id: 1,
             direction: Direction::Sell,
             size: 100,
-            price: 10.0,
+            price: Price::from_int(10),
             timestamp: 1,
             limit_or_market: LimitOrMarket::Limit,
             time_in_force: TimeInForce::Day,
+            display_size: None,
+            post_only: None,
+            min_quantity: None,
+            self_trade_prevention: None,
         };
         let resp1: Vec<OrderbookLog> = order_book.handle_new_order(req1);
         let expected_resp1 = vec![OrderbookLog::EventLog(Event::OrderAdded(OrderAdded {
@@ -679,87 +1464,108 @@ mod tests {
             ticker: "AAPL".to_string(),
             direction: Direction::Sell,
             resting_size: 100,
-            limit_price: 10.0,
+            limit_price: Price::from_int(10),
         }))];
         assert!(same_response_list(resp1, expected_resp1));
 
         let req2 = NewOrderRequest {
             order_id: 102,
+            inv_id: 1,
             direction: Direction::Buy,
             size: 50,
             price: order_book.best_sell_price().unwrap(),
             timestamp: 2,
             limit_or_market: LimitOrMarket::Market,
             time_in_force: TimeInForce::Day,
+            display_size: None,
+            post_only: None,
+            min_quantity: None,
+            self_trade_prevention: None,
         };
         let resp2: Vec<OrderbookLog> = order_book.handle_new_order(req2);
         let expected_resp2 = vec![
             OrderbookLog::OrderLog(OrderResponse::OrderFill(OrderFillResponse {
                 order_id: 101,
                 fill_size: 50,
-                fill_price: 10.0,
+                fill_price: Price::from_int(10),
+                liquidity: Liquidity::Maker,
+                fee: Cash::ZERO,
             })),
             OrderbookLog::EventLog(Event::OrderExecuted(OrderExecuted {
                 order_id: 101,
                 ticker: "AAPL".to_string(),
                 execution_size: 50,
-                execution_price: 10.0,
+                execution_price: Price::from_int(10),
             })),
             OrderbookLog::OrderLog(OrderResponse::OrderFill(OrderFillResponse {
                 order_id: 102,
                 fill_size: 50,
-                fill_price: 10.0,
+                fill_price: Price::from_int(10),
+                liquidity: Liquidity::Taker,
+                fee: Cash::ZERO,
             })),
             OrderbookLog::EventLog(Event::OrderExecuted(OrderExecuted {
                 order_id: 102,
                 ticker: "AAPL".to_string(),
                 execution_size: 50,
-                execution_price: 10.0,
+                execution_price: Price::from_int(10),
             })),
             OrderbookLog::OrderLog(OrderResponse::OrderDead(OrderDeadResponse {
                 order_id: 102,
+                reason: DeadReason::Filled,
             })),
         ];
         assert!(same_response_list(resp2, expected_resp2));
 
         let req3 = NewOrderRequest {
             order_id: 103,
+            inv_id: 1,
             direction: Direction::Buy,
             size: 100,
             price: order_book.best_sell_price().unwrap(),
             timestamp: 3,
             limit_or_market: LimitOrMarket::Market,
             time_in_force: TimeInForce::Day,
+            display_size: None,
+            post_only: None,
+            min_quantity: None,
+            self_trade_prevention: None,
         };
         let resp3: Vec<OrderbookLog> = order_book.handle_new_order(req3);
         let expected_resp3 = vec![
             OrderbookLog::OrderLog(OrderResponse::OrderFill(OrderFillResponse {
                 order_id: 101,
                 fill_size: 50,
-                fill_price: 10.0,
+                fill_price: Price::from_int(10),
+                liquidity: Liquidity::Maker,
+                fee: Cash::ZERO,
             })),
             OrderbookLog::EventLog(Event::OrderExecuted(OrderExecuted {
                 order_id: 101,
                 ticker: "AAPL".to_string(),
                 execution_size: 50,
-                execution_price: 10.0,
+                execution_price: Price::from_int(10),
             })),
             OrderbookLog::OrderLog(OrderResponse::OrderDead(OrderDeadResponse {
                 order_id: 101,
+                reason: DeadReason::Filled,
             })),
             OrderbookLog::OrderLog(OrderResponse::OrderFill(OrderFillResponse {
                 order_id: 103,
                 fill_size: 50,
-                fill_price: 10.0,
+                fill_price: Price::from_int(10),
+                liquidity: Liquidity::Taker,
+                fee: Cash::ZERO,
             })),
             OrderbookLog::EventLog(Event::OrderExecuted(OrderExecuted {
                 order_id: 103,
                 ticker: "AAPL".to_string(),
                 execution_size: 50,
-                execution_price: 10.0,
+                execution_price: Price::from_int(10),
             })),
             OrderbookLog::OrderLog(OrderResponse::OrderDead(OrderDeadResponse {
                 order_id: 103,
+                reason: DeadReason::Unfilled,
             })),
         ];
         assert!(same_response_list(resp3, expected_resp3));
@@ -771,15 +1577,24 @@ mod tests {
         // 102 sell 100 @ 15.0 2 IOC
         // 103 sell 100 @ 6.0 3 IOC
         // 104 buy 100 @ 20.0 4
-        let mut order_book = OrderBook::new("AAPL".to_string());
+        let mut order_book = OrderBook::new(
+            "AAPL".to_string(),
+            Price::from_units(100),
+            MatchingPolicy::PriceTime,
+        );
         let req1 = NewOrderRequest {
             order_id: 101,
+            inv_id: 1,
             direction: Direction::Buy,
             size: 50,
-            price: 10.0,
+            price: Price::from_int(10),
             timestamp: 1,
             limit_or_market: LimitOrMarket::Limit,
             time_in_force: TimeInForce::Day,
+            display_size: None,
+            post_only: None,
+            min_quantity: None,
+            self_trade_prevention: None,
         };
         let resp1: Vec<OrderbookLog> = order_book.handle_new_order(req1);
         let expected_resp1 = vec![OrderbookLog::EventLog(Event::OrderAdded(OrderAdded {
@@ -787,65 +1602,1277 @@ mod tests {
             ticker: "AAPL".to_string(),
             direction: Direction::Buy,
             resting_size: 50,
-            limit_price: 10.0,
+            limit_price: Price::from_int(10),
         }))];
         assert!(same_response_list(resp1, expected_resp1));
 
         let req2 = NewOrderRequest {
             order_id: 102,
+            inv_id: 1,
             direction: Direction::Sell,
             size: 100,
-            price: 15.0,
+            price: Price::from_int(15),
             timestamp: 2,
             limit_or_market: LimitOrMarket::Limit,
             time_in_force: TimeInForce::IOC,
+            display_size: None,
+            post_only: None,
+            min_quantity: None,
+            self_trade_prevention: None,
         };
         let resp2: Vec<OrderbookLog> = order_book.handle_new_order(req2);
         let expected_resp2 = vec![OrderbookLog::OrderLog(OrderResponse::OrderDead(
-            OrderDeadResponse { order_id: 102 },
+            OrderDeadResponse {
+                order_id: 102,
+                reason: DeadReason::Unfilled,
+            },
         ))];
         assert!(same_response_list(resp2, expected_resp2));
 
         let req3 = NewOrderRequest {
             order_id: 103,
+            inv_id: 1,
             direction: Direction::Sell,
             size: 100,
-            price: 6.0,
+            price: Price::from_int(6),
             timestamp: 3,
             limit_or_market: LimitOrMarket::Limit,
             time_in_force: TimeInForce::IOC,
+            display_size: None,
+            post_only: None,
+            min_quantity: None,
+            self_trade_prevention: None,
         };
         let resp3: Vec<OrderbookLog> = order_book.handle_new_order(req3);
         let expected_resp3 = vec![
             OrderbookLog::OrderLog(OrderResponse::OrderFill(OrderFillResponse {
                 order_id: 101,
                 fill_size: 50,
-                fill_price: 10.0,
+                fill_price: Price::from_int(10),
+                liquidity: Liquidity::Maker,
+                fee: Cash::ZERO,
             })),
             OrderbookLog::EventLog(Event::OrderExecuted(OrderExecuted {
                 order_id: 101,
                 ticker: "AAPL".to_string(),
                 execution_size: 50,
-                execution_price: 10.0,
+                execution_price: Price::from_int(10),
             })),
             OrderbookLog::OrderLog(OrderResponse::OrderDead(OrderDeadResponse {
                 order_id: 101,
+                reason: DeadReason::Filled,
+            })),
+            OrderbookLog::OrderLog(OrderResponse::OrderFill(OrderFillResponse {
+                order_id: 103,
+                fill_size: 50,
+                fill_price: Price::from_int(10),
+                liquidity: Liquidity::Taker,
+                fee: Cash::ZERO,
+            })),
+            OrderbookLog::EventLog(Event::OrderExecuted(OrderExecuted {
+                order_id: 103,
+                ticker: "AAPL".to_string(),
+                execution_size: 50,
+                execution_price: Price::from_int(10),
+            })),
+            OrderbookLog::OrderLog(OrderResponse::OrderDead(OrderDeadResponse {
+                order_id: 103,
+                reason: DeadReason::Unfilled,
+            })),
+        ];
+        assert!(same_response_list(resp3, expected_resp3));
+    }
+
+    #[test]
+    fn test_cancel_and_depth() {
+        // 101 buy 100 @ 10.0 1
+        // 102 buy 50 @ 10.0 2
+        // 103 buy 30 @ 10.0 3
+        // 104 buy 20 @ 9.0 4
+        // cancel 102
+        // 105 sell 120 @ 10.0 5
+        let mut order_book = OrderBook::new(
+            "AAPL".to_string(),
+            Price::from_units(100),
+            MatchingPolicy::PriceTime,
+        );
+        for (order_id, size, price, timestamp) in [
+            (101, 100, Price::from_int(10), 1),
+            (102, 50, Price::from_int(10), 2),
+            (103, 30, Price::from_int(10), 3),
+            (104, 20, Price::from_int(9), 4),
+        ] {
+            let _ = order_book.handle_new_order(NewOrderRequest {
+                order_id,
+                inv_id: 1,
+                direction: Direction::Buy,
+                size,
+                price,
+                timestamp,
+                limit_or_market: LimitOrMarket::Limit,
+                time_in_force: TimeInForce::Day,
+                display_size: None,
+                post_only: None,
+                min_quantity: None,
+                self_trade_prevention: None,
+            });
+        }
+        let depth: Vec<(Price, Size)> = order_book
+            .depth(&Direction::Buy)
+            .map(|level| (level.price, level.total_size))
+            .collect();
+        assert_eq!(
+            depth,
+            vec![(Price::from_int(10), 180), (Price::from_int(9), 20)]
+        );
+
+        let _ = order_book.handle_cancel_order(CancelOrderRequest { order_id: 102 });
+        let depth: Vec<(Price, Size)> = order_book
+            .depth(&Direction::Buy)
+            .map(|level| (level.price, level.total_size))
+            .collect();
+        assert_eq!(
+            depth,
+            vec![(Price::from_int(10), 130), (Price::from_int(9), 20)]
+        );
+
+        // cancelling a dead order produces no logs
+        let resp = order_book.handle_cancel_order(CancelOrderRequest { order_id: 102 });
+        assert!(resp.is_empty());
+
+        // 102 is skipped: 105 trades with 101 then 103
+        let resp = order_book.handle_new_order(NewOrderRequest {
+            order_id: 105,
+            inv_id: 1,
+            direction: Direction::Sell,
+            size: 120,
+            price: Price::from_int(10),
+            timestamp: 5,
+            limit_or_market: LimitOrMarket::Limit,
+            time_in_force: TimeInForce::Day,
+            display_size: None,
+            post_only: None,
+            min_quantity: None,
+            self_trade_prevention: None,
+        });
+        let filled: Vec<(OrderId, Size)> = resp
+            .iter()
+            .filter_map(|log| match log {
+                OrderbookLog::OrderLog(OrderResponse::OrderFill(fill)) if fill.order_id != 105 => {
+                    Some((fill.order_id, fill.fill_size))
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(filled, vec![(101, 100), (103, 20)]);
+        assert_eq!(order_book.best_buy_price(), Some(Price::from_int(10)));
+        assert!(order_book.best_sell_price().is_none());
+    }
+
+    #[test]
+    fn test_amend_order() {
+        // 101 sell 100 @ 10.0 1
+        // 102 sell 100 @ 10.0 2
+        // amend 101 to 60 @ 10.0: keeps priority
+        // amend 101 to 80 @ 10.0: size up, loses priority to 102
+        // 103 buy 50 @ 9.0 3
+        // amend 101 to 80 @ 9.0: crosses 103
+        let mut order_book = OrderBook::new(
+            "AAPL".to_string(),
+            Price::from_units(100),
+            MatchingPolicy::PriceTime,
+        );
+        for (order_id, timestamp) in [(101, 1), (102, 2)] {
+            let _ = order_book.handle_new_order(NewOrderRequest {
+                order_id,
+                inv_id: 1,
+                direction: Direction::Sell,
+                size: 100,
+                price: Price::from_int(10),
+                timestamp,
+                limit_or_market: LimitOrMarket::Limit,
+                time_in_force: TimeInForce::Day,
+                display_size: None,
+                post_only: None,
+                min_quantity: None,
+                self_trade_prevention: None,
+            });
+        }
+        let amend = |order_id, size, price| AmendOrderRequest {
+            order_id,
+            size,
+            price: Price::from_int(price),
+            timestamp: 10,
+        };
+
+        let resp = order_book.handle_amend_order(amend(101, 60, 10));
+        let expected = vec![OrderbookLog::EventLog(Event::OrderModified(
+            OrderModified {
+                order_id: 101,
+                ticker: "AAPL".to_string(),
+                direction: Direction::Sell,
+                resting_size: 60,
+                limit_price: Price::from_int(10),
+            },
+        ))];
+        assert!(same_response_list(resp, expected));
+        assert_eq!(order_book.sell_orders.front().unwrap().order_id, 101);
+
+        let resp = order_book.handle_amend_order(amend(101, 80, 10));
+        let expected = vec![OrderbookLog::EventLog(Event::OrderReplaced(
+            OrderReplaced {
+                order_id: 101,
+                ticker: "AAPL".to_string(),
+                direction: Direction::Sell,
+                resting_size: 80,
+                limit_price: Price::from_int(10),
+            },
+        ))];
+        assert!(same_response_list(resp, expected));
+        assert_eq!(order_book.sell_orders.front().unwrap().order_id, 102);
+
+        let _ = order_book.handle_new_order(NewOrderRequest {
+            order_id: 103,
+            inv_id: 1,
+            direction: Direction::Buy,
+            size: 50,
+            price: Price::from_int(9),
+            timestamp: 3,
+            limit_or_market: LimitOrMarket::Limit,
+            time_in_force: TimeInForce::Day,
+            display_size: None,
+            post_only: None,
+            min_quantity: None,
+            self_trade_prevention: None,
+        });
+        let resp = order_book.handle_amend_order(amend(101, 80, 9));
+        let expected = vec![
+            OrderbookLog::EventLog(Event::OrderReplaced(OrderReplaced {
+                order_id: 101,
+                ticker: "AAPL".to_string(),
+                direction: Direction::Sell,
+                resting_size: 80,
+                limit_price: Price::from_int(9),
             })),
             OrderbookLog::OrderLog(OrderResponse::OrderFill(OrderFillResponse {
                 order_id: 103,
                 fill_size: 50,
-                fill_price: 10.0,
+                fill_price: Price::from_int(9),
+                liquidity: Liquidity::Maker,
+                fee: Cash::ZERO,
             })),
             OrderbookLog::EventLog(Event::OrderExecuted(OrderExecuted {
                 order_id: 103,
                 ticker: "AAPL".to_string(),
                 execution_size: 50,
-                execution_price: 10.0,
+                execution_price: Price::from_int(9),
             })),
             OrderbookLog::OrderLog(OrderResponse::OrderDead(OrderDeadResponse {
                 order_id: 103,
+                reason: DeadReason::Filled,
+            })),
+            OrderbookLog::OrderLog(OrderResponse::OrderFill(OrderFillResponse {
+                order_id: 101,
+                fill_size: 50,
+                fill_price: Price::from_int(9),
+                liquidity: Liquidity::Taker,
+                fee: Cash::ZERO,
+            })),
+            OrderbookLog::EventLog(Event::OrderExecuted(OrderExecuted {
+                order_id: 101,
+                ticker: "AAPL".to_string(),
+                execution_size: 50,
+                execution_price: Price::from_int(9),
             })),
         ];
+        assert!(same_response_list(resp, expected));
+        assert_eq!(order_book.best_sell_price(), Some(Price::from_int(9)));
+        assert_eq!(order_book.sell_orders.front().unwrap().size, 30);
+        assert!(order_book.best_buy_price().is_none());
+
+        // unknown order
+        assert!(order_book.handle_amend_order(amend(103, 10, 9)).is_empty());
+    }
+
+    #[test]
+    fn test_fok_order() {
+        // 101 sell 50 @ 10.0 1
+        // 102 sell 50 @ 11.0 2
+        // 103 buy 120 @ 11.0 3 FOK: killed, no trade
+        // 104 buy 100 @ 11.0 4 FOK: filled
+        let mut order_book = OrderBook::new(
+            "AAPL".to_string(),
+            Price::from_units(100),
+            MatchingPolicy::PriceTime,
+        );
+        for (order_id, price, timestamp) in [(101, 10, 1), (102, 11, 2)] {
+            let _ = order_book.handle_new_order(NewOrderRequest {
+                order_id,
+                inv_id: 1,
+                direction: Direction::Sell,
+                size: 50,
+                price: Price::from_int(price),
+                timestamp,
+                limit_or_market: LimitOrMarket::Limit,
+                time_in_force: TimeInForce::Day,
+                display_size: None,
+                post_only: None,
+                min_quantity: None,
+                self_trade_prevention: None,
+            });
+        }
+        let req3 = NewOrderRequest {
+            order_id: 103,
+            inv_id: 1,
+            direction: Direction::Buy,
+            size: 120,
+            price: Price::from_int(11),
+            timestamp: 3,
+            limit_or_market: LimitOrMarket::Limit,
+            time_in_force: TimeInForce::FOK,
+            display_size: None,
+            post_only: None,
+            min_quantity: None,
+            self_trade_prevention: None,
+        };
+        let resp3 = order_book.handle_new_order(req3);
+        let expected_resp3 = vec![OrderbookLog::OrderLog(OrderResponse::OrderDead(
+            OrderDeadResponse {
+                order_id: 103,
+                reason: DeadReason::FillOrKill,
+            },
+        ))];
         assert!(same_response_list(resp3, expected_resp3));
+        assert_eq!(order_book.sell_orders.best_level().unwrap().total_size, 50);
+
+        let req4 = NewOrderRequest {
+            order_id: 104,
+            inv_id: 1,
+            direction: Direction::Buy,
+            size: 100,
+            price: Price::from_int(11),
+            timestamp: 4,
+            limit_or_market: LimitOrMarket::Limit,
+            time_in_force: TimeInForce::FOK,
+            display_size: None,
+            post_only: None,
+            min_quantity: None,
+            self_trade_prevention: None,
+        };
+        let resp4 = order_book.handle_new_order(req4);
+        let fills = resp4
+            .iter()
+            .filter(|log| matches!(log, OrderbookLog::OrderLog(OrderResponse::OrderFill(_))))
+            .count();
+        assert_eq!(fills, 4);
+        assert!(order_book.best_sell_price().is_none());
+        assert!(order_book.best_buy_price().is_none());
+    }
+
+    #[test]
+    fn test_call_auction() {
+        // 201 buy 100 @ 11, 202 buy 100 @ 10, 203 sell 150 @ 9, 204 sell 100 @ 10
+        // at 10: buy 200, sell 250 -> 200 matched, the most of any price
+        let mut order_book = OrderBook::new(
+            "AAPL".to_string(),
+            Price::from_units(100),
+            MatchingPolicy::PriceTime,
+        );
+        order_book.start_auction(Price::from_int(10));
+        let mut logs = vec![];
+        for (order_id, direction, size, price) in [
+            (201, Direction::Buy, 100, 11),
+            (202, Direction::Buy, 100, 10),
+            (203, Direction::Sell, 150, 9),
+            (204, Direction::Sell, 100, 10),
+        ] {
+            logs = order_book.handle_request(OrderbookRequest::NewOrder(NewOrderRequest {
+                order_id,
+                inv_id: 1,
+                direction,
+                size,
+                price: Price::from_int(price),
+                timestamp: order_id,
+                limit_or_market: LimitOrMarket::Limit,
+                time_in_force: TimeInForce::Day,
+                display_size: None,
+                post_only: None,
+                min_quantity: None,
+                self_trade_prevention: None,
+            }));
+        }
+        assert_eq!(
+            logs[1],
+            OrderbookLog::EventLog(Event::AuctionIndicative(AuctionIndicative {
+                ticker: "AAPL".to_string(),
+                indicative_price: Some(Price::from_int(10)),
+                matched_size: 200,
+                imbalance_size: 50,
+                imbalance_direction: Some(Direction::Sell),
+            }))
+        );
+        assert_eq!(order_book.best_buy_price(), Some(Price::from_int(11)));
+        assert_eq!(order_book.best_sell_price(), Some(Price::from_int(9)));
+
+        let (logs, clearing_price) = order_book.uncross();
+        assert_eq!(clearing_price, Some(Price::from_int(10)));
+        let fills: Vec<(OrderId, Size)> = logs
+            .iter()
+            .filter_map(|log| match log {
+                OrderbookLog::OrderLog(OrderResponse::OrderFill(fill)) => {
+                    assert_eq!(fill.fill_price, Price::from_int(10));
+                    Some((fill.order_id, fill.fill_size))
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            fills,
+            vec![
+                (201, 100),
+                (203, 100),
+                (202, 50),
+                (203, 50),
+                (202, 50),
+                (204, 50)
+            ]
+        );
+        assert_eq!(order_book.best_buy_price(), None);
+        let depth: Vec<(Price, Size)> = order_book
+            .depth(&Direction::Sell)
+            .map(|level| (level.price, level.total_size))
+            .collect();
+        assert_eq!(depth, vec![(Price::from_int(10), 50)]);
+
+        // back to continuous matching
+        let (logs, clearing_price) = order_book.uncross();
+        assert!(logs.is_empty() && clearing_price.is_none());
+    }
+
+    #[test]
+    fn test_iceberg_order() {
+        // 301 sell 300 @ 10 showing 100, 302 sell 50 @ 10
+        // 303 buy 150 @ 10: takes the first slice of 301, then 302 as 301's next slice lost priority
+        let mut order_book = OrderBook::new(
+            "AAPL".to_string(),
+            Price::from_units(100),
+            MatchingPolicy::PriceTime,
+        );
+        let logs = order_book.handle_new_order(NewOrderRequest {
+            order_id: 301,
+            inv_id: 1,
+            direction: Direction::Sell,
+            size: 300,
+            price: Price::from_int(10),
+            timestamp: 1,
+            limit_or_market: LimitOrMarket::Limit,
+            time_in_force: TimeInForce::Day,
+            display_size: Some(100),
+            post_only: None,
+            min_quantity: None,
+            self_trade_prevention: None,
+        });
+        assert_eq!(
+            logs,
+            vec![OrderbookLog::EventLog(Event::OrderAdded(OrderAdded {
+                order_id: 301,
+                ticker: "AAPL".to_string(),
+                direction: Direction::Sell,
+                resting_size: 100,
+                limit_price: Price::from_int(10),
+            }))]
+        );
+        let _ = order_book.handle_new_order(NewOrderRequest {
+            order_id: 302,
+            inv_id: 1,
+            direction: Direction::Sell,
+            size: 50,
+            price: Price::from_int(10),
+            timestamp: 2,
+            limit_or_market: LimitOrMarket::Limit,
+            time_in_force: TimeInForce::Day,
+            display_size: None,
+            post_only: None,
+            min_quantity: None,
+            self_trade_prevention: None,
+        });
+        let logs = order_book.handle_new_order(NewOrderRequest {
+            order_id: 303,
+            inv_id: 1,
+            direction: Direction::Buy,
+            size: 150,
+            price: Price::from_int(10),
+            timestamp: 3,
+            limit_or_market: LimitOrMarket::Limit,
+            time_in_force: TimeInForce::Day,
+            display_size: None,
+            post_only: None,
+            min_quantity: None,
+            self_trade_prevention: None,
+        });
+        let mut expected =
+            order_book.generate_trade_log(301, 100, Price::from_int(10), Liquidity::Maker);
+        expected.push(OrderbookLog::EventLog(Event::OrderReplaced(
+            OrderReplaced {
+                order_id: 301,
+                ticker: "AAPL".to_string(),
+                direction: Direction::Sell,
+                resting_size: 100,
+                limit_price: Price::from_int(10),
+            },
+        )));
+        expected.extend(order_book.generate_trade_log(
+            303,
+            100,
+            Price::from_int(10),
+            Liquidity::Taker,
+        ));
+        expected.extend(order_book.generate_trade_log(
+            302,
+            50,
+            Price::from_int(10),
+            Liquidity::Maker,
+        ));
+        expected.push(OrderbookLog::OrderLog(OrderResponse::OrderDead(
+            OrderDeadResponse {
+                order_id: 302,
+                reason: DeadReason::Filled,
+            },
+        )));
+        expected.extend(order_book.generate_trade_log(
+            303,
+            50,
+            Price::from_int(10),
+            Liquidity::Taker,
+        ));
+        expected.push(OrderbookLog::OrderLog(OrderResponse::OrderDead(
+            OrderDeadResponse {
+                order_id: 303,
+                reason: DeadReason::Filled,
+            },
+        )));
+        assert!(same_response_list(logs, expected));
+
+        // only the displayed slice is in the depth, FOK can fill against the hidden reserve
+        let depth: Vec<(Price, Size)> = order_book
+            .depth(&Direction::Sell)
+            .map(|level| (level.price, level.total_size))
+            .collect();
+        assert_eq!(depth, vec![(Price::from_int(10), 100)]);
+        let logs = order_book.handle_new_order(NewOrderRequest {
+            order_id: 304,
+            inv_id: 1,
+            direction: Direction::Buy,
+            size: 200,
+            price: Price::from_int(10),
+            timestamp: 4,
+            limit_or_market: LimitOrMarket::Limit,
+            time_in_force: TimeInForce::FOK,
+            display_size: None,
+            post_only: None,
+            min_quantity: None,
+            self_trade_prevention: None,
+        });
+        let filled: Size = logs
+            .iter()
+            .filter_map(|log| match log {
+                OrderbookLog::OrderLog(OrderResponse::OrderFill(fill)) if fill.order_id == 304 => {
+                    Some(fill.fill_size)
+                }
+                _ => None,
+            })
+            .sum();
+        assert_eq!(filled, 200);
+        assert_eq!(order_book.best_sell_price(), None);
+    }
+
+    #[test]
+    fn test_post_only_and_min_quantity() {
+        // 401 sell 100 @ 10
+        // 402 buy 50 @ 10 post-only reject: killed
+        // 403 buy 50 @ 10.5 post-only reprice: rests at 9.99
+        // 404 buy 150 @ 10 IOC min 120: killed, 405 buy 150 @ 10 IOC min 100: fills 100
+        let mut order_book = OrderBook::new(
+            "AAPL".to_string(),
+            Price::from_units(100),
+            MatchingPolicy::PriceTime,
+        );
+        let order = |order_id, direction, size, price: &str, time_in_force| NewOrderRequest {
+            order_id,
+            inv_id: 1,
+            direction,
+            size,
+            price: price.parse().unwrap(),
+            timestamp: order_id as Timestamp,
+            limit_or_market: LimitOrMarket::Limit,
+            time_in_force,
+            display_size: None,
+            post_only: None,
+            min_quantity: None,
+            self_trade_prevention: None,
+        };
+        let _ =
+            order_book.handle_new_order(order(401, Direction::Sell, 100, "10", TimeInForce::Day));
+
+        let logs = order_book.handle_new_order(NewOrderRequest {
+            post_only: Some(PostOnly::Reject),
+            ..order(402, Direction::Buy, 50, "10", TimeInForce::Day)
+        });
+        assert_eq!(
+            logs,
+            vec![OrderBook::generate_dead_log(402, DeadReason::PostOnly)]
+        );
+
+        let logs = order_book.handle_new_order(NewOrderRequest {
+            post_only: Some(PostOnly::Reprice),
+            ..order(403, Direction::Buy, 50, "10.5", TimeInForce::Day)
+        });
+        assert_eq!(
+            logs,
+            vec![OrderbookLog::EventLog(Event::OrderAdded(OrderAdded {
+                order_id: 403,
+                ticker: "AAPL".to_string(),
+                direction: Direction::Buy,
+                resting_size: 50,
+                limit_price: "9.99".parse().unwrap(),
+            }))]
+        );
+
+        let logs = order_book.handle_new_order(NewOrderRequest {
+            min_quantity: Some(120),
+            ..order(404, Direction::Buy, 150, "10", TimeInForce::IOC)
+        });
+        assert_eq!(
+            logs,
+            vec![OrderBook::generate_dead_log(404, DeadReason::MinQuantity)]
+        );
+
+        let logs = order_book.handle_new_order(NewOrderRequest {
+            min_quantity: Some(100),
+            ..order(405, Direction::Buy, 150, "10", TimeInForce::IOC)
+        });
+        let mut expected =
+            order_book.generate_trade_log(401, 100, Price::from_int(10), Liquidity::Maker);
+        expected.push(OrderBook::generate_dead_log(401, DeadReason::Filled));
+        expected.extend(order_book.generate_trade_log(
+            405,
+            100,
+            Price::from_int(10),
+            Liquidity::Taker,
+        ));
+        expected.push(OrderBook::generate_dead_log(405, DeadReason::Unfilled));
+        assert!(same_response_list(logs, expected));
+    }
+
+    #[test]
+    fn test_self_trade_prevention() {
+        // 501 sell 100 @ 10 by investor 2, 502 sell 100 @ 10 by investor 3
+        // 503 buy 150 @ 10 by investor 2, in each self-trade prevention mode
+        let setup = || {
+            let mut order_book = OrderBook::new(
+                "AAPL".to_string(),
+                Price::from_units(100),
+                MatchingPolicy::PriceTime,
+            );
+            for (order_id, inv_id) in [(501, 2), (502, 3)] {
+                let _ = order_book.handle_new_order(NewOrderRequest {
+                    order_id,
+                    inv_id,
+                    direction: Direction::Sell,
+                    size: 100,
+                    price: Price::from_int(10),
+                    timestamp: order_id - 500,
+                    limit_or_market: LimitOrMarket::Limit,
+                    time_in_force: TimeInForce::Day,
+                    display_size: None,
+                    post_only: None,
+                    min_quantity: None,
+                    self_trade_prevention: None,
+                });
+            }
+            order_book
+        };
+        let buy = |size, mode| NewOrderRequest {
+            order_id: 503,
+            inv_id: 2,
+            direction: Direction::Buy,
+            size,
+            price: Price::from_int(10),
+            timestamp: 3,
+            limit_or_market: LimitOrMarket::Limit,
+            time_in_force: TimeInForce::Day,
+            display_size: None,
+            post_only: None,
+            min_quantity: None,
+            self_trade_prevention: Some(mode),
+        };
+        let removed = |order_id| {
+            vec![
+                OrderBook::generate_dead_log(order_id, DeadReason::SelfTrade),
+                OrderbookLog::EventLog(Event::OrderRemoved(OrderRemoved { order_id })),
+            ]
+        };
+        let reduced = |order_id, reduced_size| {
+            OrderbookLog::OrderLog(OrderResponse::OrderReduced(OrderReducedResponse {
+                order_id,
+                reduced_size,
+            }))
+        };
+
+        // cancel newest: 503 is cancelled before it trades
+        let mut order_book = setup();
+        let logs = order_book.handle_new_order(buy(150, SelfTradePrevention::CancelNewest));
+        assert_eq!(
+            logs,
+            vec![OrderBook::generate_dead_log(503, DeadReason::SelfTrade)]
+        );
+        assert_eq!(order_book.sell_orders.best_level().unwrap().total_size, 200);
+
+        // cancel oldest: 501 is cancelled, 503 trades with 502 and rests
+        let mut order_book = setup();
+        let logs = order_book.handle_new_order(buy(150, SelfTradePrevention::CancelOldest));
+        let mut expected = removed(501);
+        expected.extend(order_book.generate_trade_log(
+            502,
+            100,
+            Price::from_int(10),
+            Liquidity::Maker,
+        ));
+        expected.push(OrderBook::generate_dead_log(502, DeadReason::Filled));
+        expected.extend(order_book.generate_trade_log(
+            503,
+            100,
+            Price::from_int(10),
+            Liquidity::Taker,
+        ));
+        expected.push(OrderbookLog::EventLog(Event::OrderAdded(OrderAdded {
+            order_id: 503,
+            ticker: "AAPL".to_string(),
+            direction: Direction::Buy,
+            resting_size: 50,
+            limit_price: Price::from_int(10),
+        })));
+        assert!(same_response_list(logs, expected));
+
+        // cancel both
+        let mut order_book = setup();
+        let logs = order_book.handle_new_order(buy(150, SelfTradePrevention::CancelBoth));
+        let mut expected = removed(501);
+        expected.push(OrderBook::generate_dead_log(503, DeadReason::SelfTrade));
+        assert!(same_response_list(logs, expected));
+        assert_eq!(order_book.sell_orders.best_level().unwrap().total_size, 100);
+
+        // decrement and cancel: 501 is smaller and cancelled, 503 is reduced by 100 and trades 50 with 502
+        let mut order_book = setup();
+        let logs = order_book.handle_new_order(buy(150, SelfTradePrevention::DecrementAndCancel));
+        let mut expected = removed(501);
+        expected.push(reduced(503, 100));
+        expected.extend(order_book.generate_trade_log(
+            502,
+            50,
+            Price::from_int(10),
+            Liquidity::Maker,
+        ));
+        expected.extend(order_book.generate_trade_log(
+            503,
+            50,
+            Price::from_int(10),
+            Liquidity::Taker,
+        ));
+        expected.push(OrderBook::generate_dead_log(503, DeadReason::Filled));
+        assert!(same_response_list(logs, expected));
+
+        // decrement and cancel: 503 is smaller and cancelled, 501 is reduced by 40 and keeps its priority
+        let mut order_book = setup();
+        let logs = order_book.handle_new_order(buy(40, SelfTradePrevention::DecrementAndCancel));
+        let expected = vec![
+            reduced(501, 40),
+            OrderbookLog::EventLog(Event::OrderModified(OrderModified {
+                order_id: 501,
+                ticker: "AAPL".to_string(),
+                direction: Direction::Sell,
+                resting_size: 60,
+                limit_price: Price::from_int(10),
+            })),
+            OrderBook::generate_dead_log(503, DeadReason::SelfTrade),
+        ];
+        assert!(same_response_list(logs, expected));
+        assert_eq!(order_book.sell_orders.front().unwrap().order_id, 501);
+
+        // FOK only counts the size of other investors
+        let mut order_book = setup();
+        let logs = order_book.handle_new_order(NewOrderRequest {
+            time_in_force: TimeInForce::FOK,
+            ..buy(150, SelfTradePrevention::CancelOldest)
+        });
+        assert_eq!(
+            logs,
+            vec![OrderBook::generate_dead_log(503, DeadReason::FillOrKill)]
+        );
+    }
+
+    #[test]
+    fn test_pro_rata_matching() {
+        // 601 sell 100 @ 10, 602 sell 300 @ 10
+        // 603 buy 200 @ 10: 601 gets 50 and 602 gets 150
+        let mut order_book = OrderBook::new(
+            "AAPL".to_string(),
+            Price::from_units(100),
+            MatchingPolicy::ProRata,
+        );
+        for (order_id, size) in [(601, 100), (602, 300)] {
+            let _ = order_book.handle_new_order(NewOrderRequest {
+                order_id,
+                inv_id: 1,
+                direction: Direction::Sell,
+                size,
+                price: Price::from_int(10),
+                timestamp: order_id - 600,
+                limit_or_market: LimitOrMarket::Limit,
+                time_in_force: TimeInForce::Day,
+                display_size: None,
+                post_only: None,
+                min_quantity: None,
+                self_trade_prevention: None,
+            });
+        }
+        let logs = order_book.handle_new_order(NewOrderRequest {
+            order_id: 603,
+            inv_id: 2,
+            direction: Direction::Buy,
+            size: 200,
+            price: Price::from_int(10),
+            timestamp: 3,
+            limit_or_market: LimitOrMarket::Limit,
+            time_in_force: TimeInForce::Day,
+            display_size: None,
+            post_only: None,
+            min_quantity: None,
+            self_trade_prevention: None,
+        });
+        let mut expected =
+            order_book.generate_trade_log(601, 50, Price::from_int(10), Liquidity::Maker);
+        expected.extend(order_book.generate_trade_log(
+            603,
+            50,
+            Price::from_int(10),
+            Liquidity::Taker,
+        ));
+        expected.extend(order_book.generate_trade_log(
+            602,
+            150,
+            Price::from_int(10),
+            Liquidity::Maker,
+        ));
+        expected.extend(order_book.generate_trade_log(
+            603,
+            150,
+            Price::from_int(10),
+            Liquidity::Taker,
+        ));
+        expected.push(OrderBook::generate_dead_log(603, DeadReason::Filled));
+        assert!(same_response_list(logs, expected));
+        assert_eq!(order_book.sell_orders.best_level().unwrap().total_size, 200);
+    }
+
+    #[test]
+    fn test_sweep_price() {
+        // 701 sell 100 @ 10, 702 sell 100 @ 11, 703 sell 100 @ 12
+        let mut order_book = OrderBook::new(
+            "AAPL".to_string(),
+            Price::from_units(100),
+            MatchingPolicy::PriceTime,
+        );
+        assert_eq!(order_book.sweep_price(&Direction::Buy, 100, None), None);
+        for (order_id, price) in [(701, 10), (702, 11), (703, 12)] {
+            let _ = order_book.handle_new_order(NewOrderRequest {
+                order_id,
+                inv_id: 1,
+                direction: Direction::Sell,
+                size: 100,
+                price: Price::from_int(price),
+                timestamp: order_id - 700,
+                limit_or_market: LimitOrMarket::Limit,
+                time_in_force: TimeInForce::Day,
+                display_size: None,
+                post_only: None,
+                min_quantity: None,
+                self_trade_prevention: None,
+            });
+        }
+        let sweep = |size, limit: Option<i64>| {
+            order_book.sweep_price(&Direction::Buy, size, limit.map(Price::from_int))
+        };
+        assert_eq!(sweep(100, None), Some(Price::from_int(10)));
+        assert_eq!(sweep(150, None), Some(Price::from_int(11)));
+        assert_eq!(sweep(500, None), Some(Price::from_int(12)));
+        assert_eq!(sweep(500, Some(11)), Some(Price::from_int(11)));
+        assert_eq!(sweep(100, Some(9)), None);
+        assert_eq!(order_book.sweep_price(&Direction::Sell, 100, None), None);
+    }
+
+    #[test]
+    fn test_volatility_auction() {
+        // static band 20% around 10, dynamic band 2% around the last trade, 60s auctions
+        // 801 sell 100 @ 10, 802 sell 100 @ 10.5, 803 sell 100 @ 11.5
+        // 804 buy 250 @ 12 at 100: trades 100 @ 10, then 10.5 is more than 2% away from 10
+        let mut order_book = OrderBook::new(
+            "AAPL".to_string(),
+            Price::from_units(100),
+            MatchingPolicy::PriceTime,
+        );
+        let bands = PriceBands {
+            static_band: Some(Price::from_int(20)),
+            dynamic_band: Some(Price::from_int(2)),
+            auction_duration: 60,
+        };
+        order_book.set_price_bands(bands, Price::from_int(10));
+        let order = |order_id, direction, size, price: &str| NewOrderRequest {
+            order_id,
+            inv_id: 1,
+            direction,
+            size,
+            price: price.parse().unwrap(),
+            timestamp: 100,
+            limit_or_market: LimitOrMarket::Limit,
+            time_in_force: TimeInForce::Day,
+            display_size: None,
+            post_only: None,
+            min_quantity: None,
+            self_trade_prevention: None,
+        };
+        for (order_id, price) in [(801, "10"), (802, "10.5"), (803, "11.5")] {
+            let _ = order_book.handle_new_order(order(order_id, Direction::Sell, 100, price));
+        }
+        let logs = order_book.handle_new_order(order(804, Direction::Buy, 250, "12"));
+        let mut expected =
+            order_book.generate_trade_log(801, 100, Price::from_int(10), Liquidity::Maker);
+        expected.push(OrderBook::generate_dead_log(801, DeadReason::Filled));
+        expected.extend(order_book.generate_trade_log(
+            804,
+            100,
+            Price::from_int(10),
+            Liquidity::Taker,
+        ));
+        expected.push(OrderbookLog::EventLog(Event::TickerStatus(TickerStatus {
+            ticker: "AAPL".to_string(),
+            state: TickerState::VolatilityAuction,
+        })));
+        expected.push(OrderbookLog::EventLog(Event::OrderAdded(OrderAdded {
+            order_id: 804,
+            ticker: "AAPL".to_string(),
+            direction: Direction::Buy,
+            resting_size: 150,
+            limit_price: Price::from_int(12),
+        })));
+        assert!(same_response_list(logs, expected));
+
+        // the auction uncrosses at 11.5, the price closest to the last trade for the most volume
+        assert!(order_book.resume_trading(159).is_empty());
+        let logs = order_book.resume_trading(160);
+        assert_eq!(
+            logs.last(),
+            Some(&OrderbookLog::EventLog(Event::TickerStatus(TickerStatus {
+                ticker: "AAPL".to_string(),
+                state: TickerState::Trading,
+            })))
+        );
+        assert_eq!(order_book.last_price, Some("11.5".parse().unwrap()));
+        assert_eq!(order_book.sell_orders.best_level().unwrap().total_size, 50);
+
+        // 806 takes the 50 left @ 11.5, then 12.5 is outside both bands
+        let _ = order_book.handle_new_order(order(805, Direction::Sell, 50, "12.5"));
+        let logs = order_book.handle_new_order(order(806, Direction::Buy, 100, "12.5"));
+        assert_eq!(order_book.last_price, Some("11.5".parse().unwrap()));
+        assert!(
+            logs.contains(&OrderbookLog::EventLog(Event::TickerStatus(TickerStatus {
+                ticker: "AAPL".to_string(),
+                state: TickerState::VolatilityAuction,
+            })))
+        );
+    }
+
+    #[test]
+    fn test_fok_within_bands() {
+        // dynamic band 2% around the last trade: 801 sell 100 @ 10, 802 sell 100 @ 10.1, 803 sell 100 @ 10.5
+        // 804 buy 250 @ 11 FOK: 10.5 is more than 2% away from 10.1, killed without a trade or an auction
+        // 805 buy 200 @ 11 FOK: filled @ 10 and 10.1
+        let mut order_book = OrderBook::new(
+            "AAPL".to_string(),
+            Price::from_units(100),
+            MatchingPolicy::PriceTime,
+        );
+        let bands = PriceBands {
+            static_band: None,
+            dynamic_band: Some(Price::from_int(2)),
+            auction_duration: 60,
+        };
+        order_book.set_price_bands(bands, Price::from_int(10));
+        let order = |order_id, direction, size, time_in_force| NewOrderRequest {
+            order_id,
+            inv_id: 1,
+            direction,
+            size,
+            price: Price::from_int(11),
+            timestamp: 100,
+            limit_or_market: LimitOrMarket::Limit,
+            time_in_force,
+            display_size: None,
+            post_only: None,
+            min_quantity: None,
+            self_trade_prevention: None,
+        };
+        for (order_id, price) in [(801, "10"), (802, "10.1"), (803, "10.5")] {
+            let _ = order_book.handle_new_order(NewOrderRequest {
+                price: price.parse().unwrap(),
+                ..order(order_id, Direction::Sell, 100, TimeInForce::Day)
+            });
+        }
+        let logs = order_book.handle_new_order(order(804, Direction::Buy, 250, TimeInForce::FOK));
+        assert_eq!(
+            logs,
+            vec![OrderBook::generate_dead_log(804, DeadReason::FillOrKill)]
+        );
+        assert_eq!(order_book.state(), &TickerState::Trading);
+
+        let logs = order_book.handle_new_order(order(805, Direction::Buy, 200, TimeInForce::FOK));
+        let filled: Size = logs
+            .iter()
+            .filter_map(|log| match log {
+                OrderbookLog::OrderLog(OrderResponse::OrderFill(fill)) if fill.order_id == 805 => {
+                    Some(fill.fill_size)
+                }
+                _ => None,
+            })
+            .sum();
+        assert_eq!(filled, 200);
+        assert_eq!(order_book.state(), &TickerState::Trading);
+        assert_eq!(order_book.last_price, Some("10.1".parse().unwrap()));
+    }
+
+    #[test]
+    fn test_halt_and_resume() {
+        let mut order_book = OrderBook::new(
+            "AAPL".to_string(),
+            Price::from_units(100),
+            MatchingPolicy::PriceTime,
+        );
+        let order = |order_id, direction, price| {
+            OrderbookRequest::NewOrder(NewOrderRequest {
+                order_id,
+                inv_id: 1,
+                direction,
+                size: 100,
+                price: Price::from_int(price),
+                timestamp: 0,
+                limit_or_market: LimitOrMarket::Limit,
+                time_in_force: TimeInForce::Day,
+                display_size: None,
+                post_only: None,
+                min_quantity: None,
+                self_trade_prevention: None,
+            })
+        };
+        let status = |state| {
+            OrderbookLog::EventLog(Event::TickerStatus(TickerStatus {
+                ticker: "AAPL".to_string(),
+                state,
+            }))
+        };
+        assert_eq!(
+            order_book.halt(TickerState::Halted),
+            status(TickerState::Halted)
+        );
+        let _ = order_book.handle_request(order(901, Direction::Sell, 10));
+        let _ = order_book.handle_request(order(902, Direction::Buy, 11));
+        assert_eq!(order_book.best_buy_price(), Some(Price::from_int(11)));
+
+        // the session uncross leaves a halted book alone
+        assert_eq!(order_book.uncross(), (vec![], None));
+        let logs = order_book.resume(false);
+        assert_eq!(logs.last(), Some(&status(TickerState::Trading)));
+        // 10 and 11 both match 100, 10 is closer to the reference price
+        assert_eq!(order_book.last_price, Some(Price::from_int(10)));
+        assert_eq!(order_book.best_buy_price(), None);
+        assert_eq!(order_book.state(), &TickerState::Trading);
+    }
+
+    // Test pegged orders: midpoint pegs at half a tick only trade with each other, pegs follow the best
+    // prices of the other orders and lose time priority when they move
+    #[test]
+    fn test_pegged_orders() {
+        let mut order_book = OrderBook::new(
+            "AAPL".to_string(),
+            Price::from_units(100),
+            MatchingPolicy::PriceTime,
+        );
+        let order = |order_id, direction, size, price: &str, limit_or_market| {
+            OrderbookRequest::NewOrder(NewOrderRequest {
+                order_id,
+                inv_id: order_id,
+                direction,
+                size,
+                price: price.parse().unwrap(),
+                timestamp: order_id,
+                limit_or_market,
+                time_in_force: TimeInForce::Day,
+                display_size: None,
+                post_only: None,
+                min_quantity: None,
+                self_trade_prevention: None,
+            })
+        };
+        let price = |price: &str| -> Price { price.parse().unwrap() };
+        let midpoint = LimitOrMarket::Peg(PegReference::Midpoint, Price::ZERO);
+        let primary = |offset: &str| LimitOrMarket::Peg(PegReference::Primary, price(offset));
+        order_book.handle_request(order(1, Direction::Buy, 100, "10", LimitOrMarket::Limit));
+        order_book.handle_request(order(
+            2,
+            Direction::Sell,
+            100,
+            "10.01",
+            LimitOrMarket::Limit,
+        ));
+
+        // 3 rests at the midpoint 10.005, the best sell price leaves it aside
+        let logs = order_book.handle_request(order(3, Direction::Sell, 30, "0", midpoint.clone()));
+        assert!(same_response_list(
+            logs,
+            vec![OrderbookLog::EventLog(Event::OrderAdded(OrderAdded {
+                order_id: 3,
+                ticker: "AAPL".to_string(),
+                direction: Direction::Sell,
+                resting_size: 30,
+                limit_price: price("10.005"),
+            }))]
+        ));
+        assert_eq!(order_book.best_sell_price(), Some(price("10.01")));
+
+        // a buy that is not a midpoint peg cannot trade at half a tick: it trades with 2
+        let logs =
+            order_book.handle_request(order(4, Direction::Buy, 10, "10.01", LimitOrMarket::Limit));
+        assert_eq!(
+            logs[..2],
+            order_book.generate_trade_log(2, 10, price("10.01"), Liquidity::Maker)[..]
+        );
+        // a midpoint buy trades with 3 at 10.005
+        let logs = order_book.handle_request(order(5, Direction::Buy, 20, "11", midpoint));
+        assert_eq!(
+            logs[..2],
+            order_book.generate_trade_log(3, 20, price("10.005"), Liquidity::Maker)[..]
+        );
+
+        // without a sell price 3 stays, then it follows the midpoint of 10 and 10.03
+        order_book.handle_request(OrderbookRequest::CancelOrder(CancelOrderRequest {
+            order_id: 2,
+        }));
+        assert_eq!(order_book.sell_orders.best_price(), Some(price("10.005")));
+        let logs = order_book.handle_request(order(
+            6,
+            Direction::Sell,
+            100,
+            "10.03",
+            LimitOrMarket::Limit,
+        ));
+        assert_eq!(
+            logs.last(),
+            Some(&OrderbookLog::EventLog(Event::OrderReplaced(
+                OrderReplaced {
+                    order_id: 3,
+                    ticker: "AAPL".to_string(),
+                    direction: Direction::Sell,
+                    resting_size: 10,
+                    limit_price: price("10.015"),
+                }
+            )))
+        );
+
+        // primary pegs follow the best buy price to the back of the queue in the order they came,
+        // 8 is capped at 10.01 and 9 stays 0.01 below the best buy price
+        order_book.handle_request(order(7, Direction::Buy, 10, "20", primary("0")));
+        order_book.handle_request(order(8, Direction::Buy, 10, "10.01", primary("0")));
+        order_book.handle_request(order(9, Direction::Buy, 10, "20", primary("0.01")));
+        order_book.handle_request(order(10, Direction::Buy, 10, "10.02", LimitOrMarket::Limit));
+        let level_ids: Vec<Vec<OrderId>> = order_book
+            .buy_orders
+            .levels()
+            .map(|level| {
+                order_book
+                    .buy_orders
+                    .level_orders(level)
+                    .map(|order| order.order_id)
+                    .collect()
+            })
+            .collect();
+        assert_eq!(level_ids, vec![vec![10, 7], vec![8, 9], vec![1]]);
+        assert_eq!(order_book.sell_orders.best_price(), Some(price("10.025")));
+    }
+
+    // Test hidden orders: never published, matched after the displayed orders at the same price
+    #[test]
+    fn test_hidden_orders() {
+        let mut order_book = OrderBook::new(
+            "AAPL".to_string(),
+            Price::from_units(100),
+            MatchingPolicy::PriceTime,
+        );
+        let order = |order_id, direction, size, display_size| {
+            OrderbookRequest::NewOrder(NewOrderRequest {
+                order_id,
+                inv_id: order_id,
+                direction,
+                size,
+                price: Price::from_int(10),
+                timestamp: order_id,
+                limit_or_market: LimitOrMarket::Limit,
+                time_in_force: TimeInForce::Day,
+                display_size,
+                post_only: None,
+                min_quantity: None,
+                self_trade_prevention: None,
+            })
+        };
+        let logs = order_book.handle_request(order(1, Direction::Sell, 100, Some(0)));
+        assert!(logs.is_empty());
+        assert_eq!(order_book.best_sell_price(), None);
+        assert!(order_book.depth(&Direction::Sell).next().is_none());
+        order_book.handle_request(order(2, Direction::Sell, 50, None));
+        let level = order_book.depth(&Direction::Sell).next().unwrap();
+        assert_eq!((level.total_size, level.hidden_size), (50, 100));
+
+        // 2 came later but is displayed: it trades first
+        let logs = order_book.handle_request(order(3, Direction::Buy, 80, None));
+        let mut expected =
+            order_book.generate_trade_log(2, 50, Price::from_int(10), Liquidity::Maker);
+        expected.push(OrderBook::generate_dead_log(2, DeadReason::Filled));
+        expected.extend(order_book.generate_trade_log(
+            3,
+            50,
+            Price::from_int(10),
+            Liquidity::Taker,
+        ));
+        expected.extend(order_book.generate_trade_log(
+            1,
+            30,
+            Price::from_int(10),
+            Liquidity::Maker,
+        ));
+        expected.extend(order_book.generate_trade_log(
+            3,
+            30,
+            Price::from_int(10),
+            Liquidity::Taker,
+        ));
+        expected.push(OrderBook::generate_dead_log(3, DeadReason::Filled));
+        assert!(same_response_list(logs, expected));
+
+        // the cancellation of a hidden order is not published either
+        let logs = order_book.handle_request(OrderbookRequest::CancelOrder(CancelOrderRequest {
+            order_id: 1,
+        }));
+        assert!(same_response_list(
+            logs,
+            vec![OrderBook::generate_dead_log(1, DeadReason::Cancelled)]
+        ));
     }
 }
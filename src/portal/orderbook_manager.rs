@@ -2,8 +2,11 @@
 
 use super::orderbook::OrderBook;
 use crate::types::{
-    common::{Price, Ticker},
-    orderbook::{OrderbookLog, OrderbookRequest},
+    common::{
+        Direction, MatchingPolicy, OrderId, PegReference, Price, Size, Ticker, TickerState,
+        Timestamp,
+    },
+    orderbook::{OrderbookLog, OrderbookRequest, PriceBands},
 };
 use std::collections::HashMap;
 
@@ -18,8 +21,16 @@ impl OrderbookManager {
         }
     }
     // Initialize an orderbook for a ticker
-    pub fn add_orderbook(&mut self, ticker: Ticker) {
-        self.bind.insert(ticker.clone(), OrderBook::new(ticker));
+    pub fn add_orderbook(
+        &mut self,
+        ticker: Ticker,
+        tick_size: Price,
+        matching_policy: MatchingPolicy,
+    ) {
+        self.bind.insert(
+            ticker.clone(),
+            OrderBook::new(ticker, tick_size, matching_policy),
+        );
     }
 
     // Handle orderbook request, return orderbook logs
@@ -33,17 +44,104 @@ impl OrderbookManager {
             .map_or(vec![], |orderbook| orderbook.handle_request(req))
     }
 
-    // Get best buy price of orderbook
-    pub fn best_buy_price(&mut self, ticker: &Ticker) -> Option<Price> {
+    // Set the price bands of the orderbook of a ticker, the static band is around close_price
+    pub fn set_price_bands(
+        &mut self,
+        ticker: &Ticker,
+        price_bands: PriceBands,
+        close_price: Price,
+    ) {
+        if let Some(orderbook) = self.bind.get_mut(ticker) {
+            orderbook.set_price_bands(price_bands, close_price);
+        }
+    }
+
+    pub fn set_close_price(&mut self, ticker: &Ticker, close_price: Price) {
+        if let Some(orderbook) = self.bind.get_mut(ticker) {
+            orderbook.set_close_price(close_price);
+        }
+    }
+
+    // Resume trading of a ticker whose volatility auction is over, return orderbook logs
+    pub fn resume_trading(&mut self, ticker: &Ticker, now: Timestamp) -> Vec<OrderbookLog> {
         self.bind
             .get_mut(ticker)
-            .map_or(None, |orderbook| orderbook.best_buy_price())
+            .map_or(vec![], |orderbook| orderbook.resume_trading(now))
     }
 
-    // Get best sell price of orderbook
-    pub fn best_sell_price(&mut self, ticker: &Ticker) -> Option<Price> {
+    pub fn ticker_state(&self, ticker: &Ticker) -> Option<&TickerState> {
+        self.bind.get(ticker).map(|orderbook| orderbook.state())
+    }
+
+    // Halt a ticker or put it in cancel-only mode, return orderbook logs
+    pub fn halt(&mut self, ticker: &Ticker, state: TickerState) -> Vec<OrderbookLog> {
+        self.bind
+            .get_mut(ticker)
+            .map_or(vec![], |orderbook| vec![orderbook.halt(state)])
+    }
+
+    // Resume continuous trading of a ticker, see OrderBook::resume, return orderbook logs
+    pub fn resume(&mut self, ticker: &Ticker, in_session_auction: bool) -> Vec<OrderbookLog> {
         self.bind
             .get_mut(ticker)
-            .map_or(None, |orderbook| orderbook.best_sell_price())
+            .map_or(vec![], |orderbook| orderbook.resume(in_session_auction))
+    }
+
+    // Start a call auction on the orderbook of a ticker
+    pub fn start_auction(&mut self, ticker: &Ticker, reference_price: Price) {
+        if let Some(orderbook) = self.bind.get_mut(ticker) {
+            orderbook.start_auction(reference_price);
+        }
+    }
+
+    // Uncross the call auction of a ticker, return orderbook logs and the clearing price
+    pub fn uncross(&mut self, ticker: &Ticker) -> (Vec<OrderbookLog>, Option<Price>) {
+        self.bind
+            .get_mut(ticker)
+            .map_or((vec![], None), |orderbook| orderbook.uncross())
+    }
+
+    // Worst price a market order reaches sweeping the orderbook of a ticker, see OrderBook::sweep_price
+    pub fn sweep_price(
+        &self,
+        ticker: &Ticker,
+        direction: &Direction,
+        size: Size,
+        limit: Option<Price>,
+    ) -> Option<Price> {
+        self.bind
+            .get(ticker)
+            .and_then(|orderbook| orderbook.sweep_price(direction, size, limit))
+    }
+
+    // Price a pegged order would rest at in the orderbook of a ticker, see OrderBook::peg_price
+    pub fn peg_price(
+        &self,
+        ticker: &Ticker,
+        direction: &Direction,
+        reference: &PegReference,
+        offset: Price,
+        cap: Price,
+    ) -> Option<Price> {
+        self.bind
+            .get(ticker)
+            .and_then(|orderbook| orderbook.peg_price(direction, reference, offset, cap))
+    }
+
+    pub fn is_pegged(&self, ticker: &Ticker, order_id: &OrderId) -> bool {
+        self.bind
+            .get(ticker)
+            .is_some_and(|orderbook| orderbook.is_pegged(order_id))
+    }
+
+    // Get (price, aggregate size) of each price level of one side, from the best price to the worst price
+    // - levels holding nothing but hidden orders are left out
+    pub fn depth(&self, ticker: &Ticker, direction: &Direction) -> Vec<(Price, Size)> {
+        self.bind.get(ticker).map_or(vec![], |orderbook| {
+            orderbook
+                .depth(direction)
+                .map(|level| (level.price, level.total_size))
+                .collect()
+        })
     }
 }
@@ -0,0 +1,294 @@
+// RiskManager: pre-trade risk checks of new and amended orders against the limits of the investor and of
+// the ticker
+// - the limits of an investor cover all its tickers, the limits of a ticker cover each investor in it
+// - positions are valued at the reference price, as if the open orders of the side that weighs more
+//   traded; an order that brings a position over its limit closer to it is accepted
+// - the daily notional adds up the orders accepted since midnight (UTC), an amendment only adds the
+//   notional it grows the order by
+
+use super::account::market_value;
+use crate::types::common::{Cash, Direction, InvId, Position, Price, Size, Ticker, Timestamp};
+use crate::types::config::RiskLimits;
+use std::collections::HashMap;
+
+const SECS_PER_DAY: Timestamp = 86_400;
+
+// A new or amended order as the risk checks see it
+pub struct RiskOrder {
+    pub ticker: Ticker,
+    pub direction: Direction,
+    pub size: Size,
+    pub price: Price,             // the price it may trade at
+    pub reference: Option<Price>, // reference price of the ticker
+    pub replaced: Cash, // open notional of the order it amends, already in the daily notional
+}
+
+// Position and open orders of an investor in one ticker
+pub struct Holding {
+    pub ticker: Ticker,
+    pub mark: Price,
+    pub position: Position,
+    pub buys: Size,  // open size of buy orders
+    pub sells: Size, // open size of sell orders
+    pub orders: usize,
+}
+
+pub struct RiskManager {
+    day: Timestamp,
+    daily_notional: HashMap<InvId, HashMap<Ticker, Cash>>, // notional accepted in the day
+}
+
+// Gross and net value of the holdings, with the order added to the open orders of its ticker
+fn position_values(holdings: &[&Holding], order: Option<&RiskOrder>) -> (Cash, Cash) {
+    let (mut gross, mut net_long, mut net_short) = (Cash::ZERO, Cash::ZERO, Cash::ZERO);
+    for holding in holdings {
+        let (mut buys, mut sells) = (holding.buys, holding.sells);
+        match order {
+            Some(order) if order.ticker == holding.ticker => match order.direction {
+                Direction::Buy => buys += order.size,
+                Direction::Sell => sells += order.size,
+            },
+            _ => {}
+        }
+        let long = market_value(holding.mark, holding.position + buys as Position);
+        let short = market_value(holding.mark, holding.position - sells as Position);
+        gross += std::cmp::max(long.abs(), short.abs());
+        net_long += long;
+        net_short += short;
+    }
+    (gross, std::cmp::max(net_long.abs(), net_short.abs()))
+}
+
+impl RiskManager {
+    pub fn new() -> Self {
+        RiskManager {
+            day: 0,
+            daily_notional: HashMap::new(),
+        }
+    }
+
+    // Check an order of an investor, with its holdings in every ticker (without the order it amends),
+    // return the reason of a reject
+    pub fn check(
+        &self,
+        inv_id: InvId,
+        order: &RiskOrder,
+        investor_limits: &RiskLimits,
+        ticker_limits: &RiskLimits,
+        holdings: &[Holding],
+        now: Timestamp,
+    ) -> Result<(), &'static str> {
+        let all: Vec<&Holding> = holdings.iter().collect();
+        let in_ticker: Vec<&Holding> = holdings
+            .iter()
+            .filter(|holding| holding.ticker == order.ticker)
+            .collect();
+        let daily_notional = self
+            .daily_notional
+            .get(&inv_id)
+            .filter(|_| self.day == now / SECS_PER_DAY);
+        let in_ticker_notional = daily_notional
+            .and_then(|notional| notional.get(&order.ticker))
+            .copied()
+            .unwrap_or(Cash::ZERO);
+        let all_notional = daily_notional
+            .into_iter()
+            .flat_map(|notional| notional.values())
+            .fold(Cash::ZERO, |total, notional| total + *notional);
+        Self::check_limits(order, ticker_limits, &in_ticker, in_ticker_notional)?;
+        Self::check_limits(order, investor_limits, &all, all_notional)
+    }
+
+    // Check an order against one set of limits, with the holdings and daily notional they cover
+    fn check_limits(
+        order: &RiskOrder,
+        limits: &RiskLimits,
+        holdings: &[&Holding],
+        daily_notional: Cash,
+    ) -> Result<(), &'static str> {
+        let notional = order.price * order.size;
+        if limits.max_order_size.is_some_and(|max| order.size > max) {
+            return Err("Order size above the limit");
+        }
+        if limits.max_order_notional.is_some_and(|max| notional > max) {
+            return Err("Order notional above the limit");
+        }
+        if let (Some(max), Some(reference)) = (limits.max_price_deviation, order.reference) {
+            if (order.price - reference).abs() > reference.percent(max) {
+                return Err("Price too far from the reference price");
+            }
+        }
+        let open_orders: usize = holdings.iter().map(|holding| holding.orders).sum();
+        if limits.max_open_orders.is_some_and(|max| open_orders >= max) {
+            return Err("Too many open orders");
+        }
+        let (gross_before, net_before) = position_values(holdings, None);
+        let (gross, net) = position_values(holdings, Some(order));
+        if limits
+            .max_gross_position
+            .is_some_and(|max| gross > max && gross > gross_before)
+        {
+            return Err("Gross position above the limit");
+        }
+        if limits
+            .max_net_position
+            .is_some_and(|max| net > max && net > net_before)
+        {
+            return Err("Net position above the limit");
+        }
+        if limits
+            .max_daily_notional
+            .is_some_and(|max| daily_notional + notional - order.replaced > max)
+        {
+            return Err("Daily notional above the limit");
+        }
+        Ok(())
+    }
+
+    // An order passed the checks and was accepted: it counts towards the daily notional
+    pub fn record(&mut self, inv_id: InvId, order: &RiskOrder, now: Timestamp) {
+        let day = now / SECS_PER_DAY;
+        if day != self.day {
+            self.day = day;
+            self.daily_notional.clear();
+        }
+        *self
+            .daily_notional
+            .entry(inv_id)
+            .or_default()
+            .entry(order.ticker.clone())
+            .or_insert(Cash::ZERO) +=
+            std::cmp::max(order.price * order.size - order.replaced, Cash::ZERO);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn order(direction: Direction, size: Size, price: i64) -> RiskOrder {
+        RiskOrder {
+            ticker: "AAPL".to_string(),
+            direction,
+            size,
+            price: Price::from_int(price),
+            reference: Some(Price::from_int(100)),
+            replaced: Cash::ZERO,
+        }
+    }
+
+    fn holding(ticker: &str, position: Position, buys: Size, sells: Size) -> Holding {
+        Holding {
+            ticker: ticker.to_string(),
+            mark: Price::from_int(100),
+            position,
+            buys,
+            sells,
+            orders: (buys > 0) as usize + (sells > 0) as usize,
+        }
+    }
+
+    #[test]
+    fn test_order_limits() {
+        let risk_manager = RiskManager::new();
+        let limits = RiskLimits {
+            max_order_size: Some(1_000),
+            max_order_notional: Some(Cash::from_int(50_000)),
+            max_price_deviation: Some(Price::from_int(10)),
+            max_open_orders: Some(2),
+            ..Default::default()
+        };
+        let check = |order: &RiskOrder, holdings: &[Holding]| {
+            risk_manager.check(1, order, &RiskLimits::default(), &limits, holdings, 0)
+        };
+        let no_holdings = [holding("AAPL", 0, 0, 0)];
+        assert_eq!(
+            check(&order(Direction::Buy, 400, 105), &no_holdings),
+            Ok(())
+        );
+        assert_eq!(
+            check(&order(Direction::Buy, 1_100, 10), &no_holdings),
+            Err("Order size above the limit")
+        );
+        assert_eq!(
+            check(&order(Direction::Buy, 600, 100), &no_holdings),
+            Err("Order notional above the limit")
+        );
+        assert_eq!(
+            check(&order(Direction::Sell, 100, 89), &no_holdings),
+            Err("Price too far from the reference price")
+        );
+        // ticker limits only count the orders in the ticker
+        let holdings = [holding("AAPL", 0, 100, 0), holding("MSFT", 0, 100, 100)];
+        assert_eq!(check(&order(Direction::Buy, 100, 100), &holdings), Ok(()));
+        let holdings = [holding("AAPL", 0, 100, 100)];
+        assert_eq!(
+            check(&order(Direction::Buy, 100, 100), &holdings),
+            Err("Too many open orders")
+        );
+    }
+
+    #[test]
+    fn test_position_limits() {
+        let mut risk_manager = RiskManager::new();
+        let limits = RiskLimits {
+            max_gross_position: Some(Cash::from_int(100_000)),
+            max_net_position: Some(Cash::from_int(50_000)),
+            max_daily_notional: Some(Cash::from_int(150_000)),
+            ..Default::default()
+        };
+        let check = |risk_manager: &RiskManager, order: &RiskOrder, holdings: &[Holding]| {
+            risk_manager.check(1, order, &limits, &RiskLimits::default(), holdings, 0)
+        };
+        // long 400 AAPL and short 400 MSFT: gross 80000, net 0
+        let holdings = [holding("AAPL", 400, 0, 0), holding("MSFT", -400, 0, 0)];
+        assert_eq!(
+            check(&risk_manager, &order(Direction::Buy, 300, 100), &holdings),
+            Err("Gross position above the limit")
+        );
+        // selling out of the long position lowers the gross position, but leaves a net short
+        assert_eq!(
+            check(&risk_manager, &order(Direction::Sell, 400, 100), &holdings),
+            Ok(())
+        );
+        // short 600 is over the net limit, selling more makes it worse, buying makes it better
+        let holdings = [holding("AAPL", -600, 0, 0)];
+        assert_eq!(
+            check(&risk_manager, &order(Direction::Sell, 10, 100), &holdings),
+            Err("Net position above the limit")
+        );
+        assert_eq!(
+            check(&risk_manager, &order(Direction::Buy, 10, 100), &holdings),
+            Ok(())
+        );
+
+        // the daily notional starts again every day
+        let no_holdings = [holding("AAPL", 0, 0, 0)];
+        risk_manager.record(1, &order(Direction::Buy, 1_000, 100), 0);
+        assert_eq!(
+            check(
+                &risk_manager,
+                &order(Direction::Buy, 500, 101),
+                &no_holdings
+            ),
+            Err("Daily notional above the limit")
+        );
+        // an amendment only adds what it grows the order by
+        let amended = RiskOrder {
+            replaced: Cash::from_int(40_000),
+            ..order(Direction::Buy, 500, 101)
+        };
+        assert_eq!(check(&risk_manager, &amended, &no_holdings), Ok(()));
+        assert_eq!(
+            risk_manager.check(
+                1,
+                &order(Direction::Buy, 500, 101),
+                &limits,
+                &RiskLimits::default(),
+                &no_holdings,
+                SECS_PER_DAY
+            ),
+            Ok(())
+        );
+    }
+}
@@ -0,0 +1,73 @@
+// SessionManager: tracks the trading phase of the exchange from its daily session schedule
+// - without a schedule the exchange trades continuously at any time
+
+use crate::types::common::{SessionPhase, Timestamp};
+
+const SECONDS_PER_DAY: Timestamp = 24 * 60 * 60;
+
+// Daily schedule in seconds since midnight (UTC)
+// - pre_open to open is the opening call auction, closing_auction to close is the closing call auction
+#[derive(Debug)]
+pub struct SessionSchedule {
+    pub pre_open: Timestamp,
+    pub open: Timestamp,
+    pub closing_auction: Option<Timestamp>,
+    pub close: Timestamp,
+}
+
+impl SessionSchedule {
+    pub fn phase_at(&self, now: Timestamp) -> SessionPhase {
+        let time_of_day = now % SECONDS_PER_DAY;
+        if time_of_day >= self.open && time_of_day < self.close {
+            match self.closing_auction {
+                Some(closing_auction) if time_of_day >= closing_auction => {
+                    SessionPhase::ClosingAuction
+                }
+                _ => SessionPhase::Continuous,
+            }
+        } else if time_of_day >= self.pre_open && time_of_day < self.open {
+            SessionPhase::PreOpen
+        } else {
+            SessionPhase::Closed
+        }
+    }
+}
+
+pub struct SessionManager {
+    schedule: Option<SessionSchedule>,
+    phase: SessionPhase,
+}
+
+impl SessionManager {
+    pub fn new(schedule: Option<SessionSchedule>) -> Self {
+        // a scheduled exchange stays closed until the first clock update
+        let phase = match schedule {
+            Some(_) => SessionPhase::Closed,
+            None => SessionPhase::Continuous,
+        };
+        SessionManager { schedule, phase }
+    }
+
+    pub fn phase(&self) -> &SessionPhase {
+        &self.phase
+    }
+
+    // Move to the phase of the schedule at now, return the previous phase if it changed
+    pub fn update(&mut self, now: Timestamp) -> Option<SessionPhase> {
+        let new_phase = self.schedule.as_ref()?.phase_at(now);
+        if new_phase == self.phase {
+            None
+        } else {
+            Some(std::mem::replace(&mut self.phase, new_phase))
+        }
+    }
+
+    // New orders and amends are accepted in the auctions and the continuous session
+    pub fn accepts_orders(&self) -> bool {
+        self.phase != SessionPhase::Closed
+    }
+
+    pub fn in_auction(&self) -> bool {
+        self.phase.is_auction()
+    }
+}
@@ -1,6 +1,9 @@
 // StockManager: store all static information of stocks: e.g. close price, lot size, mpf, etc.
 
-use crate::types::common::{Price, Size, StockName, Ticker};
+use crate::types::account_manager::MarginRates;
+use crate::types::common::{Direction, MatchingPolicy, Price, Size, Ticker};
+use crate::types::config::RiskLimits;
+use crate::types::orderbook::PriceBands;
 use std::collections::HashMap;
 
 #[derive(Debug)]
@@ -8,7 +11,13 @@ pub struct StockRecord {
     pub close_price: Price,
     pub lot_size: Size,
     pub mpf: Price,
-    pub name: StockName,
+    pub last_price: Option<Price>, // price of the last trade
+    pub matching_policy: MatchingPolicy,
+    pub market_protection: Option<Price>, // band around the reference price for market orders, in percent
+    pub price_bands: Option<PriceBands>,
+    pub initial_margin: Price, // in percent of the value of a margin position
+    pub maintenance_margin: Price, // in percent of the value of a margin position
+    pub risk_limits: RiskLimits,
 }
 
 pub struct StockManager {
@@ -29,22 +38,86 @@ impl StockManager {
     }
 
     fn check_valid_price(p: &Price, mpf: &Price) -> bool {
-        let ratio = p / mpf;
-        let epsilon = std::f32::EPSILON;
-        (ratio - ratio.floor()).abs() < epsilon
+        p.is_multiple_of(mpf)
     }
     fn check_valid_size(size: &Size, lot_size: &Size) -> bool {
-        size % lot_size == 0 && size > &0
+        size.is_multiple_of(*lot_size) && size > &0
     }
 
     pub fn check_valid_order(&self, ticker: &Ticker, price: &Price, size: &Size) -> bool {
-        self.bind.get(ticker).map_or(false, |stock_rec| {
+        self.bind.get(ticker).is_some_and(|stock_rec| {
             Self::check_valid_price(price, &stock_rec.mpf)
                 && Self::check_valid_size(size, &stock_rec.lot_size)
         })
     }
 
+    pub fn set_close_price(&mut self, ticker: &Ticker, price: Price) {
+        if let Some(stock_rec) = self.bind.get_mut(ticker) {
+            stock_rec.close_price = price;
+        }
+    }
+
+    // All tickers, sorted
+    pub fn tickers(&self) -> Vec<Ticker> {
+        let mut tickers: Vec<Ticker> = self.bind.keys().cloned().collect();
+        tickers.sort();
+        tickers
+    }
+
+    pub fn set_last_price(&mut self, ticker: &Ticker, price: Price) {
+        if let Some(stock_rec) = self.bind.get_mut(ticker) {
+            stock_rec.last_price = Some(price);
+        }
+    }
+
+    pub fn get_last_price(&self, ticker: &Ticker) -> Option<Price> {
+        self.bind
+            .get(ticker)
+            .and_then(|stock_rec| stock_rec.last_price)
+    }
+
     pub fn get_close_price(&self, ticker: &Ticker) -> Option<Price> {
         self.bind.get(ticker).map(|stock_rec| stock_rec.close_price)
     }
+
+    pub fn get_risk_limits(&self, ticker: &Ticker) -> Option<&RiskLimits> {
+        self.bind
+            .get(ticker)
+            .map(|stock_rec| &stock_rec.risk_limits)
+    }
+
+    pub fn get_mpf(&self, ticker: &Ticker) -> Option<Price> {
+        self.bind.get(ticker).map(|stock_rec| stock_rec.mpf)
+    }
+
+    // The last trade price, else the close price
+    pub fn get_reference_price(&self, ticker: &Ticker) -> Option<Price> {
+        self.bind
+            .get(ticker)
+            .map(|stock_rec| stock_rec.last_price.unwrap_or(stock_rec.close_price))
+    }
+
+    // Margin rates of a ticker, its positions marked at the reference price
+    pub fn get_margin_rates(&self, ticker: &Ticker) -> Option<MarginRates> {
+        self.bind.get(ticker).map(|stock_rec| MarginRates {
+            mark: stock_rec.last_price.unwrap_or(stock_rec.close_price),
+            initial: stock_rec.initial_margin,
+            maintenance: stock_rec.maintenance_margin,
+        })
+    }
+
+    // Worst price a market order may trade at: the protection band around the reference price (the last
+    // trade price, else the close price), rounded inwards to the minimum price fluctuation
+    // None if the stock has no band
+    pub fn market_protection_limit(&self, ticker: &Ticker, direction: &Direction) -> Option<Price> {
+        let stock_rec = self.bind.get(ticker)?;
+        let band = stock_rec.market_protection?;
+        let reference = stock_rec.last_price.unwrap_or(stock_rec.close_price);
+        let offset = reference.percent(band).units();
+        let offset = Price::from_units(offset - offset % stock_rec.mpf.units());
+        match direction {
+            Direction::Buy => Some(reference + offset),
+            Direction::Sell => Some(reference - offset),
+        }
+    }
 }
@@ -0,0 +1,212 @@
+// StopManager: holds stop orders off-book until the last trade price reaches their trigger price
+// - a buy stop triggers when the last price rises to its trigger, a sell stop when it falls to its trigger
+// - pending stop orders are not in the orderbook, so they are not visible in the market data feed
+// - the trigger price of a trailing stop follows every trade that moves the market away from it
+
+use crate::types::common::{Direction, LimitOrMarket, OrderId, Price, Ticker};
+use crate::types::portal::PortalNewOrderRequest;
+use std::collections::{BTreeSet, HashMap, HashSet};
+
+#[derive(Default)]
+struct StopTriggers {
+    buys: BTreeSet<(Price, OrderId)>,
+    sells: BTreeSet<(Price, OrderId)>,
+    trailing: HashSet<OrderId>,
+}
+
+pub struct StopManager {
+    pending: HashMap<OrderId, (Price, PortalNewOrderRequest)>, // trigger price and the held request
+    triggers: HashMap<Ticker, StopTriggers>,
+}
+
+impl StopManager {
+    pub fn new() -> Self {
+        StopManager {
+            pending: HashMap::new(),
+            triggers: HashMap::new(),
+        }
+    }
+
+    // Hold a stop order until it is triggered
+    pub fn add_stop(
+        &mut self,
+        order_id: OrderId,
+        trigger_price: Price,
+        req: PortalNewOrderRequest,
+    ) {
+        let triggers = self.triggers.entry(req.ticker.clone()).or_default();
+        match req.direction {
+            Direction::Buy => triggers.buys.insert((trigger_price, order_id)),
+            Direction::Sell => triggers.sells.insert((trigger_price, order_id)),
+        };
+        if matches!(req.limit_or_market, LimitOrMarket::TrailingStop(..)) {
+            triggers.trailing.insert(order_id);
+        }
+        self.pending.insert(order_id, (trigger_price, req));
+    }
+
+    pub fn contains(&self, order_id: &OrderId) -> bool {
+        self.pending.contains_key(order_id)
+    }
+
+    // Remove a pending stop order, e.g. when it is cancelled, return its trigger price and request
+    pub fn remove(&mut self, order_id: &OrderId) -> Option<(Price, PortalNewOrderRequest)> {
+        let (trigger_price, req) = self.pending.remove(order_id)?;
+        if let Some(triggers) = self.triggers.get_mut(&req.ticker) {
+            match req.direction {
+                Direction::Buy => triggers.buys.remove(&(trigger_price, *order_id)),
+                Direction::Sell => triggers.sells.remove(&(trigger_price, *order_id)),
+            };
+            triggers.trailing.remove(order_id);
+        }
+        Some((trigger_price, req))
+    }
+
+    // Move the trigger prices of the trailing stops of a ticker after a trade at last_price, only ever
+    // towards the market: a buy trigger falls, a sell trigger rises
+    pub fn trail(&mut self, ticker: &Ticker, last_price: Price, tick: Price) {
+        let Some(triggers) = self.triggers.get_mut(ticker) else {
+            return;
+        };
+        for order_id in &triggers.trailing {
+            let Some((trigger_price, req)) = self.pending.get_mut(order_id) else {
+                continue;
+            };
+            let LimitOrMarket::TrailingStop(trail, _) = &req.limit_or_market else {
+                continue;
+            };
+            let trailed = trail.trigger_price(&req.direction, last_price, tick);
+            let (set, moves) = match req.direction {
+                Direction::Buy => (&mut triggers.buys, trailed < *trigger_price),
+                Direction::Sell => (&mut triggers.sells, trailed > *trigger_price),
+            };
+            if moves {
+                set.remove(&(*trigger_price, *order_id));
+                set.insert((trailed, *order_id));
+                *trigger_price = trailed;
+            }
+        }
+    }
+
+    // Get all stop orders of a ticker triggered by the last trade price, in the order they were added
+    // - they stay pending until they are removed
+    pub fn triggered(&self, ticker: &Ticker, last_price: Price) -> Vec<OrderId> {
+        let Some(triggers) = self.triggers.get(ticker) else {
+            return vec![];
+        };
+        let mut triggered: Vec<OrderId> = triggers
+            .buys
+            .range(..=(last_price, OrderId::MAX))
+            .chain(triggers.sells.range((last_price, 0)..))
+            .map(|(_, order_id)| *order_id)
+            .collect();
+        triggered.sort_unstable();
+        triggered
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::types::common::{Size, TimeInForce, Trail};
+
+    fn stop_order(direction: Direction, size: Size, trigger: i64) -> PortalNewOrderRequest {
+        PortalNewOrderRequest {
+            ticker: "AAPL".to_string(),
+            direction,
+            size,
+            price: Price::from_int(trigger),
+            limit_or_market: LimitOrMarket::StopMarket(Price::from_int(trigger)),
+            time_in_force: TimeInForce::Day,
+            timestamp: 0,
+            display_size: None,
+            hidden: false,
+            post_only: None,
+            min_quantity: None,
+            self_trade_prevention: None,
+            contingency: None,
+        }
+    }
+
+    #[test]
+    fn test_triggered() {
+        let mut stop_manager = StopManager::new();
+        for (order_id, direction, trigger) in [
+            (1, Direction::Buy, 12),
+            (2, Direction::Buy, 11),
+            (3, Direction::Sell, 9),
+            (4, Direction::Sell, 8),
+            (5, Direction::Buy, 11),
+        ] {
+            stop_manager.add_stop(
+                order_id,
+                Price::from_int(trigger),
+                stop_order(direction, 100, trigger),
+            );
+        }
+        let ticker = "AAPL".to_string();
+        let take_triggered = |stop_manager: &mut StopManager, last_price: i64| -> Vec<OrderId> {
+            let triggered = stop_manager.triggered(&ticker, Price::from_int(last_price));
+            for order_id in &triggered {
+                stop_manager.remove(order_id);
+            }
+            triggered
+        };
+
+        assert!(take_triggered(&mut stop_manager, 10).is_empty());
+        assert!(stop_manager.remove(&5).is_some());
+        assert_eq!(take_triggered(&mut stop_manager, 11), vec![2]);
+        assert_eq!(take_triggered(&mut stop_manager, 8), vec![3, 4]);
+        assert!(stop_manager.contains(&1));
+        assert!(!stop_manager.contains(&2));
+        // triggered orders stay pending until they are removed
+        assert_eq!(
+            stop_manager.triggered(&ticker, Price::from_int(12)),
+            vec![1]
+        );
+        assert!(stop_manager.contains(&1));
+        assert!(stop_manager
+            .triggered(&"XYZ".to_string(), Price::from_int(20))
+            .is_empty());
+    }
+
+    #[test]
+    fn test_trail() {
+        let mut stop_manager = StopManager::new();
+        let trailing = |direction, trail| PortalNewOrderRequest {
+            limit_or_market: LimitOrMarket::TrailingStop(trail, None),
+            ..stop_order(direction, 100, 0)
+        };
+        let price = |price: &str| -> Price { price.parse().unwrap() };
+        let tick = price("0.01");
+        // 1: sell trailing 1 below 10, 2: buy trailing 5% above 10, rounded up to 10.50
+        stop_manager.add_stop(
+            1,
+            price("9"),
+            trailing(Direction::Sell, Trail::Amount(price("1"))),
+        );
+        stop_manager.add_stop(
+            2,
+            price("10.5"),
+            trailing(Direction::Buy, Trail::Percent(price("5"))),
+        );
+        assert_eq!(
+            Trail::Percent(price("5")).trigger_price(&Direction::Buy, price("9.99"), tick),
+            price("10.49")
+        );
+
+        // a rise moves the sell trigger up, not the buy trigger
+        let ticker = "AAPL".to_string();
+        stop_manager.trail(&ticker, price("11"), tick);
+        assert!(stop_manager.triggered(&ticker, price("10.01")).is_empty());
+        assert_eq!(stop_manager.triggered(&ticker, price("10")), vec![1]);
+
+        // a fall moves the buy trigger down to 8.40, never back up
+        stop_manager.trail(&ticker, price("8"), tick);
+        stop_manager.trail(&ticker, price("9"), tick);
+        assert_eq!(stop_manager.triggered(&ticker, price("8.4")), vec![1, 2]);
+        let (trigger_price, _) = stop_manager.remove(&2).unwrap();
+        assert_eq!(trigger_price, price("8.4"));
+        assert!(stop_manager.triggered(&ticker, price("10.2")).is_empty());
+    }
+}
@@ -1,18 +1,44 @@
-use super::{account::Account, order_info::OrderRecord, stock_manager::StockRecord};
+use super::{
+    account::Account, order_info::OrderRecord, session_manager::SessionSchedule,
+    stock_manager::StockRecord,
+};
 use crate::types::{
     account_manager::AccountUpdate,
-    common::{Direction, Size},
-    config::{InvestorList, StockList},
-    portal::OrderResponse,
+    common::{Direction, Price, Size, Timestamp},
+    config::{FeeTier, InvestorList, StockList},
+    orderbook::PriceBands,
+    portal::{OrderResponse, PortalTask},
 };
 use std::{fs::File, io::Read};
 
-// Generate AccountUpdate instructions from one OrderResponse
+// Default length of the call auction after a price band is breached, in seconds
+const VOLATILITY_AUCTION_SECS: Timestamp = 5 * 60;
+
+// Price of a trailing stop at trigger: beyond the trigger price by the limit offset for a limit order
+pub fn trailing_limit_price(
+    direction: &Direction,
+    trigger: Price,
+    limit_offset: Option<Price>,
+) -> Price {
+    let limit_offset = limit_offset.unwrap_or(Price::ZERO);
+    match direction {
+        Direction::Buy => trigger + limit_offset,
+        Direction::Sell => trigger - limit_offset,
+    }
+}
+
+// Generate AccountUpdate instructions from one OrderResponse, a buy order of a cash account reserved its
+// open size at reserve_price, which covers its fees
 pub fn orderresponse_to_acc_update(
     orderbook_log: OrderResponse,
     order_rec: &OrderRecord,
+    reserve_price: Price,
     resting_size: Option<Size>,
+    margin: bool,
 ) -> Vec<AccountUpdate> {
+    if margin {
+        return margin_order_acc_update(orderbook_log, order_rec, resting_size);
+    }
     // convert orderbook log to account update
     let mut acc_updates: Vec<AccountUpdate> = vec![];
     match orderbook_log {
@@ -20,7 +46,7 @@ pub fn orderresponse_to_acc_update(
             Direction::Buy => {
                 acc_updates.push(AccountUpdate::UpdCash(
                     order_rec.inv_id,
-                    (order_fill.fill_size as f32) * (order_rec.limit_price - order_fill.fill_price),
+                    (reserve_price - order_fill.fill_price) * order_fill.fill_size - order_fill.fee,
                 ));
                 acc_updates.push(AccountUpdate::AddPos(
                     order_rec.inv_id,
@@ -31,7 +57,7 @@ pub fn orderresponse_to_acc_update(
             Direction::Sell => {
                 acc_updates.push(AccountUpdate::UpdCash(
                     order_rec.inv_id,
-                    (order_fill.fill_size as f32) * order_fill.fill_price,
+                    order_fill.fill_price * order_fill.fill_size - order_fill.fee,
                 ));
             }
         },
@@ -40,7 +66,7 @@ pub fn orderresponse_to_acc_update(
                 if let Some(resting_size) = resting_size {
                     acc_updates.push(AccountUpdate::UpdCash(
                         order_rec.inv_id,
-                        (resting_size as f32) * order_rec.limit_price,
+                        reserve_price * resting_size,
                     ));
                 }
             }
@@ -54,10 +80,73 @@ pub fn orderresponse_to_acc_update(
                 }
             }
         },
+        // the reduced size is released like the open size of a dead order
+        OrderResponse::OrderReduced(order_reduced) => match order_rec.direction {
+            Direction::Buy => {
+                acc_updates.push(AccountUpdate::UpdCash(
+                    order_rec.inv_id,
+                    reserve_price * order_reduced.reduced_size,
+                ));
+            }
+            Direction::Sell => {
+                acc_updates.push(AccountUpdate::AddPos(
+                    order_rec.inv_id,
+                    order_rec.ticker.clone(),
+                    order_reduced.reduced_size,
+                ));
+            }
+        },
+        // a child order reserves when it is accepted, like a new order
+        OrderResponse::OrderActivated(_) => {}
     }
     acc_updates
 }
 
+// Whether tasks hold a fill, which moves positions, cash and the mark price of its ticker
+pub fn has_fills(tasks: &[PortalTask]) -> bool {
+    tasks.iter().any(|task| {
+        matches!(
+            task,
+            PortalTask::OrderResponse(_, OrderResponse::OrderFill(_))
+        )
+    })
+}
+
+// An order of a margin account holds its open size instead of cash or positions: a trade settles in
+// full and the open size is released as the order trades or dies
+fn margin_order_acc_update(
+    orderbook_log: OrderResponse,
+    order_rec: &OrderRecord,
+    resting_size: Option<Size>,
+) -> Vec<AccountUpdate> {
+    let (inv_id, ticker) = (order_rec.inv_id, order_rec.ticker.clone());
+    let open_update =
+        |size| AccountUpdate::MinusOpen(inv_id, ticker.clone(), order_rec.direction.clone(), size);
+    match orderbook_log {
+        OrderResponse::OrderFill(order_fill) => {
+            let value = order_fill.fill_price * order_fill.fill_size;
+            let (cash, position) = match order_rec.direction {
+                Direction::Buy => (
+                    -value,
+                    AccountUpdate::AddPos(inv_id, ticker.clone(), order_fill.fill_size),
+                ),
+                Direction::Sell => (
+                    value,
+                    AccountUpdate::MinusPos(inv_id, ticker.clone(), order_fill.fill_size),
+                ),
+            };
+            vec![
+                AccountUpdate::UpdCash(inv_id, cash - order_fill.fee),
+                position,
+                open_update(order_fill.fill_size),
+            ]
+        }
+        OrderResponse::OrderDead(_) => resting_size.map(open_update).into_iter().collect(),
+        OrderResponse::OrderReduced(order_reduced) => vec![open_update(order_reduced.reduced_size)],
+        OrderResponse::OrderActivated(_) => vec![],
+    }
+}
+
 pub fn load_investors_from_config(investor_config_file: String) -> Vec<Account> {
     let cur_dir = std::env::current_dir().unwrap();
     let path = cur_dir.join(investor_config_file);
@@ -72,10 +161,13 @@ pub fn load_investors_from_config(investor_config_file: String) -> Vec<Account>
     for investor in investors.investors {
         let mut acc = Account::new(
             investor.inv_id,
-            investor.account_name,
             investor.password,
             investor.cash_amount,
+            investor.margin,
+            investor.risk_limits,
+            investor.throttle,
         );
+        acc.cancel_on_disconnect = investor.cancel_on_disconnect;
         for (ticker, size) in investor.stocks {
             acc.add_position(ticker, size.as_f64().unwrap() as Size);
         }
@@ -84,26 +176,108 @@ pub fn load_investors_from_config(investor_config_file: String) -> Vec<Account>
     accounts
 }
 
-pub fn load_stocks_from_config(stock_config_file: String) -> Vec<(String, StockRecord)> {
+fn read_stock_list(stock_config_file: String) -> StockList {
     let cur_dir = std::env::current_dir().unwrap();
     let path = cur_dir.join(stock_config_file);
     let mut file = File::open(path).expect("Unable to open file");
     let mut contents = String::new();
     file.read_to_string(&mut contents)
         .expect("Unable to read file");
-    let stocks: StockList = serde_json::from_str(&contents).expect("Unable to load stock config");
+    serde_json::from_str(&contents).expect("Unable to load stock config")
+}
+
+pub fn load_stocks_from_config(stock_config_file: String) -> Vec<(String, StockRecord)> {
+    let stocks = read_stock_list(stock_config_file);
 
     let mut stock_records: Vec<(String, StockRecord)> = vec![];
 
     for stock_config in stocks.stocks {
+        // a band narrower than a tick would halt the ticker on any trade away from its reference
+        let bands = [stock_config.static_band, stock_config.dynamic_band];
+        assert!(
+            bands
+                .into_iter()
+                .flatten()
+                .all(|band| stock_config.close_price.percent(band) >= stock_config.mpf),
+            "Price bands of {} must be at least one tick wide at its close price",
+            stock_config.ticker
+        );
+        let has_bands = stock_config.static_band.is_some() || stock_config.dynamic_band.is_some();
+        let price_bands = has_bands.then(|| PriceBands {
+            static_band: stock_config.static_band,
+            dynamic_band: stock_config.dynamic_band,
+            auction_duration: stock_config
+                .volatility_auction
+                .unwrap_or(VOLATILITY_AUCTION_SECS),
+        });
+        let initial_margin = stock_config.initial_margin.unwrap_or(Price::from_int(100));
+        let maintenance_margin = stock_config.maintenance_margin.unwrap_or(initial_margin);
+        assert!(
+            Price::ZERO < maintenance_margin
+                && maintenance_margin <= initial_margin
+                && initial_margin <= Price::from_int(100),
+            "Margins must satisfy 0 < maintenance_margin <= initial_margin <= 100"
+        );
         let stock_record = StockRecord {
             close_price: stock_config.close_price,
             lot_size: stock_config.lot_size,
             mpf: stock_config.mpf,
-            name: stock_config.name,
+            last_price: None,
+            matching_policy: stock_config.matching_policy,
+            market_protection: stock_config.market_protection,
+            price_bands,
+            initial_margin,
+            maintenance_margin,
+            risk_limits: stock_config.risk_limits,
         };
         stock_records.push((stock_config.ticker, stock_record));
     }
 
     stock_records
 }
+
+// Parse "HH:MM:SS" into seconds since midnight
+fn parse_time_of_day(time: &str) -> Option<Timestamp> {
+    let parts: Vec<Timestamp> = time
+        .split(':')
+        .map(|part| part.parse().ok())
+        .collect::<Option<_>>()?;
+    match parts[..] {
+        [h, m, s] if h < 24 && m < 60 && s < 60 => Some(h * 3600 + m * 60 + s),
+        _ => None,
+    }
+}
+
+// Load the fee tiers of the stock list, by minimum volume
+pub fn load_fees_from_config(stock_config_file: String) -> Vec<FeeTier> {
+    let mut tiers = read_stock_list(stock_config_file).fee_tiers;
+    tiers.sort_by_key(|tier| tier.min_volume);
+    let valid_minimums = tiers.iter().all(|tier| {
+        [Some(&tier.maker), Some(&tier.taker), tier.auction.as_ref()]
+            .into_iter()
+            .flatten()
+            .all(|rate| rate.minimum >= Price::ZERO)
+    });
+    assert!(valid_minimums, "Fee minimums must not be negative");
+    tiers
+}
+
+// Load the optional session schedule of the stock list
+pub fn load_session_from_config(stock_config_file: String) -> Option<SessionSchedule> {
+    let session = read_stock_list(stock_config_file).session?;
+    let time_of = |time: &str| parse_time_of_day(time).expect("Invalid session time");
+    let schedule = SessionSchedule {
+        pre_open: time_of(&session.pre_open),
+        open: time_of(&session.open),
+        closing_auction: session.closing_auction.as_deref().map(time_of),
+        close: time_of(&session.close),
+    };
+    let closing_auction = schedule.closing_auction.unwrap_or(schedule.open);
+    assert!(
+        schedule.pre_open <= schedule.open
+            && schedule.open <= closing_auction
+            && closing_auction < schedule.close,
+        "Session times must satisfy pre_open <= open <= closing_auction < close"
+    );
+    Some(schedule)
+}
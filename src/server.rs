@@ -1,22 +1,29 @@
 // The server module handles all rpc communication functionalities with investor clients and subscriber clients.
 
+use self::stock_exchange::stock_exchange_admin_service_server::StockExchangeAdminService;
 use self::stock_exchange::stock_exchange_service_server::StockExchangeService;
-use crate::server::stock_exchange::rpc_order_response::{LoginAck, LoginRej};
+use crate::server::stock_exchange::rpc_order_response::{LoginAck, LogoutAck};
 use crate::server::stock_exchange::RpcOrderResponse;
 use crate::types::common::{InvId, SeqNum, SubId};
+use crate::types::config::ThrottleConfig;
 use crate::types::portal::PortalTask;
 use crate::utils::{
-    parse_order_request, parse_seqnum, parse_subscribe_request, wrap_cancel_reject, wrap_event,
-    wrap_order_ack, wrap_order_reject, wrap_order_response,
+    make_timer_request, parse_order_request, parse_seqnum, parse_set_ticker_state_request,
+    parse_subscribe_request, wrap_amend_ack, wrap_amend_reject, wrap_cancel_reject, wrap_event,
+    wrap_login_reject, wrap_margin_call, wrap_order_ack, wrap_order_reject, wrap_order_response,
+    wrap_set_ticker_state_response,
 };
 use crate::{portal::Portal, types::portal::PortalRequest};
+use journal::OrderJournal;
 use std::collections::HashMap;
 use std::pin::Pin;
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
+use std::time::{Duration, Instant};
 use stock_exchange::{
-    rpc_order_request, rpc_order_response, RpcOrderRequest, RpcSubscribeRequest,
-    RpcSubscribeResponse,
+    rpc_order_request, rpc_order_response, RpcOrderRequest, RpcSetTickerStateRequest,
+    RpcSetTickerStateResponse, RpcSubscribeRequest, RpcSubscribeResponse,
 };
+use throttle::Throttle;
 use tokio::sync::{mpsc, Mutex};
 use tokio_stream::wrappers::ReceiverStream;
 use tonic::{Status, Streaming};
@@ -24,27 +31,45 @@ use tonic::{Status, Streaming};
 type Stream<T> =
     Pin<Box<dyn tokio_stream::Stream<Item = std::result::Result<T, Status>> + Send + 'static>>;
 
+mod journal;
+mod throttle;
+
+// Order responses kept for replay per investor
+const JOURNAL_CAPACITY: usize = 10_000;
+
 pub mod stock_exchange {
+    // generated code: a new order is much larger than the other requests
+    #![allow(clippy::large_enum_variant)]
     tonic::include_proto!("stockexchange");
 }
 
 pub struct StockExchangeServer {
     portal: Arc<Mutex<Portal>>,
     order_channels: Mutex<HashMap<InvId, mpsc::Sender<RpcOrderResponse>>>,
+    order_journals: Mutex<HashMap<InvId, OrderJournal>>,
     market_id_counter: Mutex<SubId>,
     market_channels: Mutex<HashMap<SubId, mpsc::Sender<RpcSubscribeResponse>>>,
+    this: Weak<Self>, // the Arc the server lives in, shared with the tasks of its streams
 }
 
-unsafe impl Send for StockExchangeServer {}
-unsafe impl Sync for StockExchangeServer {}
-
 impl StockExchangeServer {
-    pub fn new(investor_config: String, stock_config: String) -> Self {
-        StockExchangeServer {
+    pub fn new(investor_config: String, stock_config: String) -> Arc<Self> {
+        Arc::new_cyclic(|this| StockExchangeServer {
+            this: this.clone(),
             portal: Arc::new(Mutex::new(Portal::new(investor_config, stock_config))),
             order_channels: Mutex::new(HashMap::new()),
+            order_journals: Mutex::new(HashMap::new()),
             market_id_counter: Mutex::new(0),
             market_channels: Mutex::new(HashMap::new()),
+        })
+    }
+
+    // drive the periodic work of the portal (e.g. order expiry) once per second
+    pub async fn run_timer(self: Arc<Self>) {
+        let mut interval = tokio::time::interval(Duration::from_secs(1));
+        loop {
+            interval.tick().await;
+            self.dispatch_request(0, make_timer_request()).await;
         }
     }
 
@@ -89,19 +114,121 @@ impl StockExchangeServer {
                 self.dispatch_to_order_channel(inv_id, wrap_cancel_reject(seqnum, reason))
                     .await
             }
+            PortalTask::AmendAck(inv_id, seqnum, order_id) => {
+                self.dispatch_to_order_channel(inv_id, wrap_amend_ack(seqnum, order_id))
+                    .await
+            }
+            PortalTask::AmendReject(inv_id, seqnum, reason) => {
+                self.dispatch_to_order_channel(inv_id, wrap_amend_reject(seqnum, reason))
+                    .await
+            }
 
             PortalTask::OrderResponse(inv_id, r) => {
                 self.dispatch_to_order_channel(inv_id, wrap_order_response(r))
                     .await
             }
+            PortalTask::MarginCall(inv_id, call) => {
+                self.dispatch_to_order_channel(inv_id, wrap_margin_call(call))
+                    .await
+            }
         }
     }
 
-    // dispatch order response to corresponding order channel
+    // handle an order request of a logged in investor: dispatch it to the portal, or reject it if it is
+    // invalid or throttled. Return false if the session was throttled too many times in a row
+    async fn handle_order_request(
+        &self,
+        inv_id: InvId,
+        seqnum: SeqNum,
+        event: RpcOrderRequest,
+        throttle: &mut Throttle,
+        now: Instant,
+    ) -> bool {
+        let portal_req = match parse_order_request(inv_id, event) {
+            Ok(portal_req) => portal_req,
+            Err(reason) => {
+                self.process_task(PortalTask::OrderReject(inv_id, seqnum, reason))
+                    .await;
+                return true;
+            }
+        };
+        let Err(reason) = throttle.check(&portal_req, now) else {
+            self.dispatch_request(seqnum, portal_req).await;
+            return true;
+        };
+        let task = match portal_req {
+            PortalRequest::CancelOrder(..) => PortalTask::CancelReject(inv_id, seqnum, reason),
+            PortalRequest::AmendOrder(..) => PortalTask::AmendReject(inv_id, seqnum, reason),
+            _ => PortalTask::OrderReject(inv_id, seqnum, reason),
+        };
+        self.process_task(task).await;
+        !throttle.should_disconnect()
+    }
+
+    // number an order response and keep it for replay, then dispatch it to corresponding order channel, if
+    // the investor has a session
     async fn dispatch_to_order_channel(&self, inv_id: InvId, event: RpcOrderResponse) -> () {
-        let channels = self.order_channels.lock().await;
-        let tx = channels.get(&inv_id).unwrap();
-        tx.send(event).await.unwrap();
+        let (event, tx) = {
+            let mut journals = self.order_journals.lock().await;
+            let journal = journals
+                .entry(inv_id)
+                .or_insert_with(|| OrderJournal::new(JOURNAL_CAPACITY));
+            let event = journal.push(event);
+            let tx = self.order_channels.lock().await.get(&inv_id).cloned();
+            (event, tx)
+        };
+        if let Some(tx) = tx {
+            let _ = tx.send(event).await;
+        }
+    }
+
+    // check that the order responses of an investor after last_seqnum can be replayed, an investor without
+    // a journal was sent none
+    async fn check_replay(&self, inv_id: InvId, last_seqnum: SeqNum) -> Result<(), String> {
+        let journals = self.order_journals.lock().await;
+        let empty = OrderJournal::new(JOURNAL_CAPACITY);
+        journals.get(&inv_id).unwrap_or(&empty).retains(last_seqnum)
+    }
+
+    // replay the order responses after last_seqnum to a new session, if set, then add its channel to
+    // order_channels. The journals are not held while sending: the channel is added once a check under
+    // them finds nothing left to replay, so that live responses follow in order
+    async fn resume_session(
+        &self,
+        inv_id: InvId,
+        mut last_seqnum: Option<SeqNum>,
+        tx: &mpsc::Sender<RpcOrderResponse>,
+    ) -> Result<(), String> {
+        loop {
+            let missed = {
+                let journals = self.order_journals.lock().await;
+                let missed = match (last_seqnum, journals.get(&inv_id)) {
+                    (Some(last_seqnum), Some(journal)) => journal.since(last_seqnum)?,
+                    _ => vec![],
+                };
+                if missed.is_empty() {
+                    let mut channels = self.order_channels.lock().await;
+                    channels.insert(inv_id, tx.clone());
+                    return Ok(());
+                }
+                missed
+            };
+            for response in missed {
+                last_seqnum = Some(response.out_seqnum);
+                let _ = tx.send(response).await;
+            }
+        }
+    }
+
+    // end the session of an investor: drop its order channel and release its login, cancelling its open
+    // orders if asked to
+    async fn end_session(&self, inv_id: InvId, cancel_orders: bool) {
+        self.order_channels.lock().await.remove(&inv_id);
+        let mut portal = self.portal.lock().await;
+        for task in portal.logout(&inv_id, cancel_orders) {
+            self.process_task(task).await;
+        }
+        println!("[Logout] investor_id={}", inv_id);
     }
 
     // dispatch market response to corresponding subscriber channel
@@ -119,7 +246,8 @@ impl StockExchangeService for StockExchangeServer {
         &self,
         request: tonic::Request<Streaming<RpcOrderRequest>>,
     ) -> Result<tonic::Response<Self::SendOrderStream>, tonic::Status> {
-        let shared_self = unsafe { Arc::from_raw(self as *const Self) };
+        // the server outlives the request it serves
+        let shared_self = self.this.upgrade().unwrap();
         let mut in_stream = request.into_inner();
         let (tx, mut rx) = mpsc::channel::<RpcOrderResponse>(128);
         let (recv_tx, recv_rx) = mpsc::channel::<Result<RpcOrderResponse, Status>>(128);
@@ -127,72 +255,110 @@ impl StockExchangeService for StockExchangeServer {
         // spawn a thread to process order request
         tokio::spawn(async move {
             let mut inv_id = Box::<InvId>::new(0);
+            let throttle_config: ThrottleConfig;
+            let cancel_on_disconnect: bool;
+            let mut logged_out = false;
 
             // we require each session to login first
             if let Ok(Some(RpcOrderRequest {
                 request: Some(rpc_order_request::Request::Login(login)),
             })) = in_stream.message().await
             {
-                let seqnum = login.seqnum.clone();
+                let seqnum = login.seqnum;
                 let login_success = {
                     let mut portal = shared_self.portal.lock().await;
+                    throttle_config = portal.get_throttle(&login.investor_id);
+                    cancel_on_disconnect = login.cancel_on_disconnect
+                        || portal.cancel_on_disconnect(&login.investor_id);
                     portal.try_login(login.investor_id, &login.password)
                 };
+                // a session cannot resume from responses that are no longer retained
+                let replay = match (login_success, login.last_seqnum) {
+                    (true, Some(last_seqnum)) => {
+                        shared_self
+                            .check_replay(login.investor_id, last_seqnum)
+                            .await
+                    }
+                    _ => Ok(()),
+                };
+                if let Err(reason) = replay {
+                    let _ = tx.send(wrap_login_reject(seqnum, reason)).await;
+                    shared_self.end_session(login.investor_id, false).await;
+                    return;
+                }
                 if login_success {
                     // login success
                     println!("[Login] investor_id={}", login.investor_id);
                     *inv_id = login.investor_id;
 
-                    {
-                        // add channel to order_channels
-                        let mut channels = shared_self.order_channels.lock().await;
-                        channels.insert(login.investor_id, tx.clone());
-                    }
                     let response = RpcOrderResponse {
-                        response: Some(rpc_order_response::Response::LoginAck(LoginAck {
-                            seqnum: seqnum,
-                        })),
+                        response: Some(rpc_order_response::Response::LoginAck(LoginAck { seqnum })),
+                        out_seqnum: 0,
                     };
-                    tx.send(response).await.unwrap();
+                    let _ = tx.send(response).await;
+                    let resumed = shared_self
+                        .resume_session(login.investor_id, login.last_seqnum, &tx)
+                        .await;
+                    // responses dropped while replaying, after the check above
+                    if let Err(reason) = resumed {
+                        let _ = tx.send(wrap_login_reject(seqnum, reason)).await;
+                        shared_self.end_session(login.investor_id, false).await;
+                        return;
+                    }
                 } else {
                     // login failed
-                    let response = RpcOrderResponse {
-                        response: Some(rpc_order_response::Response::LoginRej(LoginRej {
-                            seqnum: seqnum,
-                            reason: "login failed".to_string(),
-                        })),
-                    };
-                    tx.send(response).await.unwrap();
+                    let _ = tx
+                        .send(wrap_login_reject(seqnum, "login failed".to_string()))
+                        .await;
                     return;
                 }
             } else {
                 // first request is not login
-                let response = RpcOrderResponse {
-                    response: Some(rpc_order_response::Response::LoginRej(LoginRej {
-                        seqnum: 0,
-                        reason: "invalid first request".to_string(),
-                    })),
-                };
-                tx.send(response).await.unwrap();
+                let _ = tx
+                    .send(wrap_login_reject(0, "invalid first request".to_string()))
+                    .await;
                 return;
             }
 
-            // after login, we can process other requests
+            // after login, we can process other requests, throttled before they reach the portal
+            let mut throttle = Throttle::new(throttle_config, Instant::now());
             while let Ok(Some(event)) = in_stream.message().await {
                 println!(
                     "[Order Request] received order request from inv_id={}",
                     inv_id
                 );
-                let seqnum = parse_seqnum(&event);
-                let portal_req = parse_order_request(*inv_id, event);
-                shared_self.dispatch_request(seqnum, portal_req).await;
+                // an empty request has no seqnum, it is rejected as 0
+                let seqnum = parse_seqnum(&event).unwrap_or(0);
+                if let Some(rpc_order_request::Request::Logout(_)) = event.request {
+                    let response = RpcOrderResponse {
+                        response: Some(rpc_order_response::Response::LogoutAck(LogoutAck {
+                            seqnum,
+                        })),
+                        out_seqnum: 0,
+                    };
+                    let _ = tx.send(response).await;
+                    logged_out = true;
+                    break;
+                }
+                let keep_session = shared_self
+                    .handle_order_request(*inv_id, seqnum, event, &mut throttle, Instant::now())
+                    .await;
+                if !keep_session {
+                    break;
+                }
             }
+            // logout, or the session dropped: throttled out, or the inbound stream ended or failed
+            shared_self
+                .end_session(*inv_id, cancel_on_disconnect && !logged_out)
+                .await;
         });
 
-        // spawn a thread to process order response
+        // spawn a thread to process order response, until the session ends or the client is gone
         tokio::spawn(async move {
             while let Some(r) = rx.recv().await {
-                recv_tx.send(Ok(r)).await.unwrap();
+                if recv_tx.send(Ok(r)).await.is_err() {
+                    break;
+                }
             }
         });
 
@@ -206,7 +372,8 @@ impl StockExchangeService for StockExchangeServer {
         &self,
         request: tonic::Request<Streaming<RpcSubscribeRequest>>,
     ) -> Result<tonic::Response<Self::SubscribeStream>, Status> {
-        let shared_self = unsafe { Arc::from_raw(self as *const Self) };
+        // the server outlives the request it serves
+        let shared_self = self.this.upgrade().unwrap();
         let mut in_stream = request.into_inner();
         let (tx, mut rx) = mpsc::channel::<RpcSubscribeResponse>(128);
         let (recv_tx, recv_rx) = mpsc::channel::<Result<RpcSubscribeResponse, Status>>(128);
@@ -243,3 +410,156 @@ impl StockExchangeService for StockExchangeServer {
         Ok(tonic::Response::new(Box::pin(out_stream)))
     }
 }
+
+#[tonic::async_trait]
+impl StockExchangeAdminService for StockExchangeServer {
+    async fn set_ticker_state(
+        &self,
+        request: tonic::Request<RpcSetTickerStateRequest>,
+    ) -> Result<tonic::Response<RpcSetTickerStateResponse>, Status> {
+        let (ticker, state) = parse_set_ticker_state_request(request.into_inner())
+            .map_err(Status::invalid_argument)?;
+        println!("[Admin] set ticker={} state={:?}", ticker, state);
+        let mut portal = self.portal.lock().await;
+        let tasks = portal
+            .set_ticker_state(&ticker, state.clone())
+            .map_err(Status::failed_precondition)?;
+        for task in tasks {
+            self.process_task(task).await;
+        }
+        Ok(tonic::Response::new(wrap_set_ticker_state_response(
+            ticker, state,
+        )))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::test_support::{write_config, INVESTORS, STOCKS};
+    use stock_exchange::rpc_order_request::{AmendOrder, CancelOrder, NewOrder};
+    use stock_exchange::rpc_order_response::Response;
+
+    // Build a server from config contents written to temporary files, with an order channel for investor 1
+    async fn make_server(
+        name: &str,
+    ) -> (Arc<StockExchangeServer>, mpsc::Receiver<RpcOrderResponse>) {
+        let (investor_path, stock_path) = write_config(name, INVESTORS, STOCKS);
+        let server = StockExchangeServer::new(investor_path, stock_path);
+        let (tx, rx) = mpsc::channel(128);
+        server.order_channels.lock().await.insert(1, tx);
+        (server, rx)
+    }
+
+    fn request(request: rpc_order_request::Request) -> RpcOrderRequest {
+        RpcOrderRequest {
+            request: Some(request),
+        }
+    }
+
+    // Reason of the reject an order channel received last
+    fn last_reject(rx: &mut mpsc::Receiver<RpcOrderResponse>) -> Option<String> {
+        let mut reason = None;
+        while let Ok(response) = rx.try_recv() {
+            reason = match response.response {
+                Some(Response::Rej(rej)) => Some(rej.reason),
+                Some(Response::CancelRej(rej)) => Some(rej.reason),
+                Some(Response::AmendRej(rej)) => Some(rej.reason),
+                _ => None,
+            };
+        }
+        reason
+    }
+
+    fn out_seqnums(rx: &mut mpsc::Receiver<RpcOrderResponse>) -> Vec<SeqNum> {
+        let mut out_seqnums = vec![];
+        while let Ok(response) = rx.try_recv() {
+            out_seqnums.push(response.out_seqnum);
+        }
+        out_seqnums
+    }
+
+    #[tokio::test]
+    async fn test_session_resume() {
+        let (server, mut rx) = make_server("resume").await;
+        // responses are numbered across sessions, also while the investor has none
+        server
+            .dispatch_to_order_channel(1, wrap_order_ack(1, 1))
+            .await;
+        assert_eq!(out_seqnums(&mut rx), vec![1]);
+        server.order_channels.lock().await.remove(&1);
+        for seqnum in 2..=3 {
+            server
+                .dispatch_to_order_channel(1, wrap_order_ack(seqnum, seqnum))
+                .await;
+        }
+
+        // a session resuming after 1 gets 2 and 3 replayed before the live responses
+        let (tx, mut rx) = mpsc::channel(128);
+        assert_eq!(server.resume_session(1, Some(1), &tx).await, Ok(()));
+        server
+            .dispatch_to_order_channel(1, wrap_order_ack(4, 4))
+            .await;
+        assert_eq!(out_seqnums(&mut rx), vec![2, 3, 4]);
+
+        // without last_seqnum nothing is replayed
+        server.order_channels.lock().await.remove(&1);
+        let (tx, mut rx) = mpsc::channel(128);
+        assert_eq!(server.resume_session(1, None, &tx).await, Ok(()));
+        server
+            .dispatch_to_order_channel(1, wrap_order_ack(5, 5))
+            .await;
+        assert_eq!(out_seqnums(&mut rx), vec![5]);
+
+        // a session cannot resume after a response that was never sent
+        assert_eq!(server.check_replay(1, 5).await, Ok(()));
+        assert!(server.check_replay(1, 6).await.is_err());
+        assert!(server.check_replay(1, SeqNum::MAX).await.is_err());
+        assert!(server.check_replay(2, 1).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_throttled_requests() {
+        let (server, mut rx) = make_server("throttle").await;
+        let mut throttle = Throttle::new(
+            ThrottleConfig {
+                orders_per_sec: Some(1),
+                cancels_per_sec: Some(1),
+                disconnect_after: Some(3),
+            },
+            Instant::now(),
+        );
+        let now = Instant::now();
+        let new_order = || request(rpc_order_request::Request::NewOrder(NewOrder::default()));
+        let amend = || request(rpc_order_request::Request::AmendOrder(AmendOrder::default()));
+        let cancel = || {
+            request(rpc_order_request::Request::CancelOrder(
+                CancelOrder::default(),
+            ))
+        };
+
+        // the first order and cancel reach the portal, which rejects them on their own merits
+        for (seqnum, event) in [(1, new_order()), (2, cancel())] {
+            assert!(
+                server
+                    .handle_order_request(1, seqnum, event, &mut throttle, now)
+                    .await
+            );
+            assert!(!last_reject(&mut rx).unwrap().starts_with("Throttled"));
+        }
+        // amendments share the bucket of new orders
+        let throttled = [
+            (3, new_order(), "Throttled: Too many orders per second"),
+            (4, amend(), "Throttled: Too many orders per second"),
+            (5, cancel(), "Throttled: Too many cancels per second"),
+        ];
+        for (seqnum, event, reason) in throttled {
+            let keep_session = server
+                .handle_order_request(1, seqnum, event, &mut throttle, now)
+                .await;
+            assert_eq!(last_reject(&mut rx), Some(reason.to_string()));
+            // the third violation in a row closes the session
+            assert_eq!(keep_session, seqnum < 5);
+        }
+    }
+}
@@ -0,0 +1,106 @@
+// OrderJournal: numbers the order responses to an investor and keeps the latest for replay on login
+// - out_seqnum counts from 1 across all the sessions of the investor
+// - only the last capacity responses are retained, older ones cannot be replayed anymore
+
+use super::stock_exchange::RpcOrderResponse;
+use crate::types::common::SeqNum;
+use std::collections::VecDeque;
+
+pub struct OrderJournal {
+    capacity: usize,
+    first_seqnum: SeqNum, // out_seqnum of the oldest retained response
+    responses: VecDeque<RpcOrderResponse>,
+}
+
+impl OrderJournal {
+    pub fn new(capacity: usize) -> Self {
+        OrderJournal {
+            capacity,
+            first_seqnum: 1,
+            responses: VecDeque::new(),
+        }
+    }
+
+    // Number a response and keep it, dropping the oldest one when full; return the numbered response
+    pub fn push(&mut self, mut response: RpcOrderResponse) -> RpcOrderResponse {
+        response.out_seqnum = self.first_seqnum + self.responses.len() as SeqNum;
+        if self.responses.len() == self.capacity {
+            self.responses.pop_front();
+            self.first_seqnum += 1;
+        }
+        self.responses.push_back(response.clone());
+        response
+    }
+
+    // out_seqnum of the last response, 0 if none was sent
+    pub fn last_seqnum(&self) -> SeqNum {
+        self.first_seqnum - 1 + self.responses.len() as SeqNum
+    }
+
+    // Check that last_seqnum was sent and the responses after it are all retained
+    pub fn retains(&self, last_seqnum: SeqNum) -> Result<(), String> {
+        if last_seqnum > self.last_seqnum() {
+            return Err(format!(
+                "response {} was never sent, the last is {}",
+                last_seqnum,
+                self.last_seqnum()
+            ));
+        }
+        match last_seqnum < self.first_seqnum - 1 {
+            true => Err(format!(
+                "responses after {} are no longer retained, the oldest is {}",
+                last_seqnum, self.first_seqnum
+            )),
+            false => Ok(()),
+        }
+    }
+
+    // Responses after last_seqnum, Err if it was never sent or some of them are no longer retained
+    pub fn since(&self, last_seqnum: SeqNum) -> Result<Vec<RpcOrderResponse>, String> {
+        self.retains(last_seqnum)?;
+        let skip = (last_seqnum - (self.first_seqnum - 1)) as usize;
+        Ok(self.responses.iter().skip(skip).cloned().collect())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn out_seqnums(responses: Result<Vec<RpcOrderResponse>, String>) -> Vec<SeqNum> {
+        responses
+            .unwrap()
+            .iter()
+            .map(|response| response.out_seqnum)
+            .collect()
+    }
+
+    #[test]
+    fn test_journal() {
+        let mut journal = OrderJournal::new(3);
+        assert_eq!(out_seqnums(journal.since(0)), Vec::<SeqNum>::new());
+        assert_eq!(
+            journal.since(SeqNum::MAX).err(),
+            Some(format!(
+                "response {} was never sent, the last is 0",
+                SeqNum::MAX
+            ))
+        );
+        for out_seqnum in 1..=4 {
+            assert_eq!(
+                journal.push(RpcOrderResponse::default()).out_seqnum,
+                out_seqnum
+            );
+        }
+        // 1 was dropped for 4, so everything after 0 cannot be replayed anymore
+        assert_eq!(out_seqnums(journal.since(1)), vec![2, 3, 4]);
+        assert_eq!(out_seqnums(journal.since(3)), vec![4]);
+        assert_eq!(out_seqnums(journal.since(4)), Vec::<SeqNum>::new());
+        assert_eq!(
+            journal.since(0).err(),
+            Some("responses after 0 are no longer retained, the oldest is 2".to_string())
+        );
+        assert!(journal.since(5).is_err());
+        assert!(journal.retains(SeqNum::MAX).is_err());
+    }
+}
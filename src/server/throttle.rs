@@ -0,0 +1,124 @@
+// Throttle: limits the message rate of an investor session before its requests reach the portal
+// - new orders and amendments share one token bucket, cancels have their own
+// - a bucket holds one second worth of tokens and refills continuously, every request takes one token
+// - after disconnect_after throttled requests in a row, the session should be closed
+
+use crate::types::config::ThrottleConfig;
+use crate::types::portal::PortalRequest;
+use std::time::Instant;
+
+struct TokenBucket {
+    rate: f64, // tokens per second, also the capacity
+    tokens: f64,
+    last: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: u32, now: Instant) -> Self {
+        TokenBucket {
+            rate: rate as f64,
+            tokens: rate as f64,
+            last: now,
+        }
+    }
+
+    // Take a token if there is one, after refilling for the time since the last request
+    fn try_take(&mut self, now: Instant) -> bool {
+        let elapsed = now.saturating_duration_since(self.last).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate);
+        self.last = now;
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            true
+        } else {
+            false
+        }
+    }
+}
+
+pub struct Throttle {
+    orders: Option<TokenBucket>,
+    cancels: Option<TokenBucket>,
+    disconnect_after: Option<u32>,
+    violations: u32, // throttled requests in a row
+}
+
+impl Throttle {
+    pub fn new(config: ThrottleConfig, now: Instant) -> Self {
+        Throttle {
+            orders: config
+                .orders_per_sec
+                .map(|rate| TokenBucket::new(rate, now)),
+            cancels: config
+                .cancels_per_sec
+                .map(|rate| TokenBucket::new(rate, now)),
+            disconnect_after: config.disconnect_after,
+            violations: 0,
+        }
+    }
+
+    // Check if a request may go to the portal, return the reason if it is throttled
+    pub fn check(&mut self, request: &PortalRequest, now: Instant) -> Result<(), String> {
+        let (bucket, reason) = match request {
+            PortalRequest::CancelOrder(..) => (&mut self.cancels, "Too many cancels per second"),
+            _ => (&mut self.orders, "Too many orders per second"),
+        };
+        if bucket.as_mut().is_none_or(|bucket| bucket.try_take(now)) {
+            self.violations = 0;
+            Ok(())
+        } else {
+            self.violations += 1;
+            Err(format!("Throttled: {}", reason))
+        }
+    }
+
+    // Check if the session has been throttled too many times in a row
+    pub fn should_disconnect(&self) -> bool {
+        self.disconnect_after
+            .is_some_and(|disconnect_after| self.violations >= disconnect_after)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    fn cancel() -> PortalRequest {
+        PortalRequest::CancelOrder(1, 1)
+    }
+
+    #[test]
+    fn test_token_bucket() {
+        let start = Instant::now();
+        let mut throttle = Throttle::new(
+            ThrottleConfig {
+                orders_per_sec: None,
+                cancels_per_sec: Some(2),
+                disconnect_after: Some(2),
+            },
+            start,
+        );
+        // a full bucket allows a burst of one second worth of requests
+        assert!(throttle.check(&cancel(), start).is_ok());
+        assert!(throttle.check(&cancel(), start).is_ok());
+        assert_eq!(
+            throttle.check(&cancel(), start),
+            Err("Throttled: Too many cancels per second".to_string())
+        );
+        assert!(!throttle.should_disconnect());
+
+        // half a second refills one token, a passing request resets the violations
+        let later = start + Duration::from_millis(500);
+        assert!(throttle.check(&cancel(), later).is_ok());
+        assert!(throttle.check(&cancel(), later).is_err());
+        assert!(throttle.check(&cancel(), later).is_err());
+        assert!(throttle.should_disconnect());
+
+        // the bucket never holds more than its rate
+        let much_later = later + Duration::from_secs(60);
+        assert!(throttle.check(&cancel(), much_later).is_ok());
+        assert!(throttle.check(&cancel(), much_later).is_ok());
+        assert!(throttle.check(&cancel(), much_later).is_err());
+    }
+}
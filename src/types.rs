@@ -2,6 +2,7 @@ pub mod account_manager;
 pub mod common;
 pub mod config;
 pub mod event;
+pub mod fixed;
 pub mod order;
 pub mod orderbook;
 pub mod portal;
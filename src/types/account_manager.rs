@@ -1,17 +1,29 @@
-use crate::types::common::{Cash, InvId, Price, Size, Ticker};
+use crate::types::common::{Cash, Direction, InvId, Price, Size, Ticker};
 
 // Potential order: required cash or required positions for new order
 //                  used to check if an order is valid
+// - an order of a margin account requires margin for its open size instead
 #[derive(Debug)]
 pub enum PotentialOrder {
-    PotentialBuy(Price),
+    PotentialBuy(Cash),
     PotentialSell(Size, Ticker),
+    PotentialMargin(Ticker, Direction, Size),
 }
 
 // AccountUpdate: used to update account's cash or positions
+// - or the open size of the orders of a margin account
 #[derive(Debug)]
 pub enum AccountUpdate {
     UpdCash(InvId, Cash),
     AddPos(InvId, Ticker, Size),
     MinusPos(InvId, Ticker, Size),
+    AddOpen(InvId, Ticker, Direction, Size),
+    MinusOpen(InvId, Ticker, Direction, Size),
+}
+
+// Mark price of a ticker and the margin rates of its positions, in percent of their value
+pub struct MarginRates {
+    pub mark: Price,
+    pub initial: Price,
+    pub maintenance: Price,
 }
@@ -1,12 +1,16 @@
+use super::fixed::Fixed;
 use serde::Deserialize;
 
 pub type OrderId = u64;
 pub type Size = u32;
 pub type Ticker = String;
 pub type Timestamp = u64;
-pub type Price = f32;
+// number of decimal places kept by prices and cash
+pub const DECIMALS: u32 = 4;
+pub type Price = Fixed<DECIMALS>;
 
-pub type Cash = f32;
+pub type Cash = Fixed<DECIMALS>;
+pub type Position = i64; // shares held, negative for a short position
 pub type InvId = u64;
 pub type AccountName = String;
 pub type Password = String;
@@ -15,16 +19,156 @@ pub type StockName = String;
 pub type SeqNum = u64;
 pub type SubId = u64;
 
-#[derive(Debug, PartialEq, Deserialize)]
+#[derive(Debug, PartialEq, Clone, Deserialize)]
 pub enum LimitOrMarket {
     Limit,
     Market,
+    StopMarket(Price), // held off-book until the trigger price trades, then a market order
+    StopLimit(Price),  // held off-book until the trigger price trades, then a limit order
+    Peg(PegReference, Price), // rests at its reference price moved away from the opposite side by an offset
+    // held off-book until its trigger price, trailing the last trade price, trades; then a limit order
+    // offset from the trigger price if there is a limit offset, else a market order
+    TrailingStop(Trail, Option<Price>),
+}
+
+impl LimitOrMarket {
+    pub fn trigger_price(&self) -> Option<Price> {
+        match self {
+            LimitOrMarket::StopMarket(price) | LimitOrMarket::StopLimit(price) => Some(*price),
+            _ => None,
+        }
+    }
+
+    // Check if the order is held off-book until it is triggered
+    pub fn is_stop(&self) -> bool {
+        self.trigger_price().is_some() || matches!(self, LimitOrMarket::TrailingStop(..))
+    }
+}
+
+// Distance of the trigger price of a trailing stop from the last trade price
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+pub enum Trail {
+    Amount(Price),
+    Percent(Price),
+}
+
+impl Trail {
+    // Trigger price trailing reference: above it for a buy, below it for a sell, rounded away from it
+    // to a multiple of tick
+    pub fn trigger_price(&self, direction: &Direction, reference: Price, tick: Price) -> Price {
+        let distance = match self {
+            Trail::Amount(amount) => *amount,
+            Trail::Percent(percent) => reference.percent(*percent),
+        };
+        let tick = tick.units();
+        match direction {
+            Direction::Buy => {
+                let units = (reference + distance).units();
+                Price::from_units(units + (tick - units.rem_euclid(tick)) % tick)
+            }
+            Direction::Sell => {
+                let units = (reference - distance).units();
+                Price::from_units(units - units.rem_euclid(tick))
+            }
+        }
+    }
+}
+
+// Reference price of a pegged order, derived from the best prices of orders that are not pegged
+// - the limit price of a pegged order caps it: a buy never rests above it, a sell never below it
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+pub enum PegReference {
+    Primary,  // best price on the side of the order
+    Midpoint, // midpoint of the best buy and sell prices, possibly half a tick
+}
+
+// Post-only: an order that would take liquidity on arrival is either rejected, or repriced one tick
+// away from the opposite best price so that it rests
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+pub enum PostOnly {
+    Reject,
+    Reprice,
+}
+
+// Trading state of a single ticker within the continuous session
+#[derive(Debug, PartialEq, Clone)]
+pub enum TickerState {
+    Trading,
+    VolatilityAuction, // a trade outside the price bands interrupted trading, a call auction re-opens it
+    Halted,            // halted by an operator, orders accumulate until trading resumes
+    CancelOnly,        // set by an operator, only cancellations are accepted
+}
+
+impl TickerState {
+    // Orders accumulate without matching until the ticker re-opens
+    pub fn is_auction(&self) -> bool {
+        matches!(self, TickerState::VolatilityAuction | TickerState::Halted)
+    }
+}
+
+// Matching policy of an orderbook: how orders at the same price share an incoming order
+#[derive(Debug, Default, PartialEq, Clone, Copy, Deserialize)]
+pub enum MatchingPolicy {
+    #[default]
+    PriceTime, // first in, first out
+    ProRata,         // in proportion to order size
+    ProRataTopOrder, // the oldest order first, then in proportion to order size
+    SizeTime,        // larger orders first, then first in, first out
+}
+
+// Self-trade prevention: what happens when an incoming order would trade with a resting order of the
+// same investor, the mode of the incoming order applies
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+pub enum SelfTradePrevention {
+    CancelNewest,       // cancel the incoming order
+    CancelOldest,       // cancel the resting order, the incoming order keeps matching
+    CancelBoth,         // cancel both orders
+    DecrementAndCancel, // reduce both orders by the smaller size, cancelling the smaller one
+}
+
+// Orders linked to a new order
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+pub enum Contingency {
+    Oco(OrderId), // one-cancels-other with a live order of the same investor and ticker
+    // take-profit limit and stop-loss stop-market children, activated once the entry order is done
+    Bracket {
+        take_profit: Option<Price>,
+        stop_loss: Option<Price>,
+    },
 }
 
-#[derive(Debug, PartialEq, Deserialize)]
+#[derive(Debug, PartialEq, Clone, Deserialize)]
 pub enum TimeInForce {
     Day,
-    IOC,
+    IOC,            // immediate or cancel: unfilled size is killed
+    FOK,            // fill or kill: fill the whole size immediately or nothing
+    GTC,            // good till cancel
+    GTD(Timestamp), // good till date: rests until the expiry timestamp
+}
+
+impl TimeInForce {
+    // Whether the unfilled size of an order rests in the orderbook
+    pub fn rests(&self) -> bool {
+        matches!(
+            self,
+            TimeInForce::Day | TimeInForce::GTC | TimeInForce::GTD(_)
+        )
+    }
+}
+
+#[derive(Debug, PartialEq, Clone)]
+pub enum SessionPhase {
+    PreOpen, // opening call auction
+    Continuous,
+    ClosingAuction, // closing call auction
+    Closed,
+}
+
+impl SessionPhase {
+    // Orders accumulate without matching until the uncross
+    pub fn is_auction(&self) -> bool {
+        matches!(self, SessionPhase::PreOpen | SessionPhase::ClosingAuction)
+    }
 }
 
 #[derive(Debug, PartialEq, Clone, Deserialize)]
@@ -32,3 +176,12 @@ pub enum Direction {
     Buy,
     Sell,
 }
+
+impl Direction {
+    pub fn opposite(&self) -> Direction {
+        match self {
+            Direction::Buy => Direction::Sell,
+            Direction::Sell => Direction::Buy,
+        }
+    }
+}
@@ -1,3 +1,4 @@
+use super::common::{Cash, MatchingPolicy, Price, Size, Timestamp};
 use serde::Deserialize;
 
 // Config struct for Investor config file
@@ -7,7 +8,23 @@ pub struct InvestorConfig {
     pub account_name: String,
     pub password: String,
     pub stocks: serde_json::Map<String, serde_json::Value>,
-    pub cash_amount: f32,
+    pub cash_amount: Cash,
+    #[serde(default)]
+    pub margin: bool, // a margin account may borrow cash and sell short
+    #[serde(default)]
+    pub risk_limits: RiskLimits, // across all tickers
+    #[serde(default)]
+    pub throttle: ThrottleConfig,
+    #[serde(default)]
+    pub cancel_on_disconnect: bool, // cancel all open orders when a session drops
+}
+
+// Message-rate throttle of an investor session, None for no limit
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct ThrottleConfig {
+    pub orders_per_sec: Option<u32>, // new orders and amendments
+    pub cancels_per_sec: Option<u32>,
+    pub disconnect_after: Option<u32>, // throttled requests in a row that close the session
 }
 #[derive(Debug, Deserialize)]
 pub struct InvestorList {
@@ -18,12 +35,67 @@ pub struct InvestorList {
 #[derive(Debug, Deserialize)]
 pub struct StockConfig {
     pub ticker: String,
-    pub close_price: f32,
+    pub close_price: Price,
     pub lot_size: u32,
-    pub mpf: f32,
+    pub mpf: Price,
     pub name: String,
+    #[serde(default)]
+    pub matching_policy: MatchingPolicy,
+    pub market_protection: Option<Price>, // percent from the reference price, None for no band
+    pub static_band: Option<Price>,       // percent from the close price, None for no band
+    pub dynamic_band: Option<Price>,      // percent from the last trade price, None for no band
+    pub volatility_auction: Option<Timestamp>, // seconds of the auction after a band is breached
+    pub initial_margin: Option<Price>, // percent of the value of a margin position, 100 by default
+    pub maintenance_margin: Option<Price>, // percent, the initial margin by default
+    #[serde(default)]
+    pub risk_limits: RiskLimits, // for the orders of each investor in the stock
+}
+
+// Pre-trade risk limits, None for no limit
+#[derive(Debug, Default, Clone, Deserialize)]
+pub struct RiskLimits {
+    pub max_order_size: Option<Size>,
+    pub max_order_notional: Option<Cash>,
+    pub max_price_deviation: Option<Price>, // percent from the reference price
+    pub max_open_orders: Option<usize>,
+    pub max_gross_position: Option<Cash>, // value of the long and short positions added up
+    pub max_net_position: Option<Cash>,   // value of the long positions net of the short positions
+    pub max_daily_notional: Option<Cash>, // value of the orders accepted in the day
+}
+// Daily session schedule of the exchange, times are "HH:MM:SS" in UTC
+#[derive(Debug, Deserialize)]
+pub struct SessionConfig {
+    pub pre_open: String,
+    pub open: String,
+    pub closing_auction: Option<String>,
+    pub close: String,
+}
+// Fee of one fill: per share plus basis points of its value, at least the minimum if it is a charge
+// - negative rates are rebates
+#[derive(Debug, Default, Deserialize)]
+pub struct FeeRate {
+    #[serde(default)]
+    pub per_share: Price,
+    #[serde(default)]
+    pub bps: Price,
+    #[serde(default)]
+    pub minimum: Cash,
+}
+// Rates of the investors who traded at least min_volume shares earlier in the month
+#[derive(Debug, Deserialize)]
+pub struct FeeTier {
+    #[serde(default)]
+    pub min_volume: u64,
+    #[serde(default)]
+    pub maker: FeeRate,
+    #[serde(default)]
+    pub taker: FeeRate,
+    pub auction: Option<FeeRate>, // the taker rate by default
 }
 #[derive(Debug, Deserialize)]
 pub struct StockList {
     pub stocks: Vec<StockConfig>,
+    pub session: Option<SessionConfig>,
+    #[serde(default)]
+    pub fee_tiers: Vec<FeeTier>, // no fees without tiers
 }
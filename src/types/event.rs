@@ -1,10 +1,15 @@
-use crate::types::common::{Direction, OrderId, Price, Size, Ticker};
+use crate::types::common::{Direction, OrderId, Price, SessionPhase, Size, Ticker, TickerState};
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum Event {
     OrderAdded(OrderAdded),
     OrderExecuted(OrderExecuted),
     OrderRemoved(OrderRemoved),
+    OrderModified(OrderModified),
+    OrderReplaced(OrderReplaced),
+    MarketStatus(MarketStatus),
+    AuctionIndicative(AuctionIndicative),
+    TickerStatus(TickerStatus),
 }
 
 #[derive(Debug, PartialEq, Clone)]
@@ -28,3 +33,47 @@ pub struct OrderExecuted {
 pub struct OrderRemoved {
     pub order_id: OrderId,
 }
+
+// Resting order amended in place, keeps its time priority
+#[derive(Debug, PartialEq, Clone)]
+pub struct OrderModified {
+    pub order_id: OrderId,
+    pub ticker: Ticker,
+    pub direction: Direction,
+    pub resting_size: Size,
+    pub limit_price: Price,
+}
+
+// Resting order amended with loss of time priority: moved to the back of the queue at its new price
+#[derive(Debug, PartialEq, Clone)]
+pub struct OrderReplaced {
+    pub order_id: OrderId,
+    pub ticker: Ticker,
+    pub direction: Direction,
+    pub resting_size: Size,
+    pub limit_price: Price,
+}
+
+// The exchange moved to a new trading phase
+#[derive(Debug, PartialEq, Clone)]
+pub struct MarketStatus {
+    pub phase: SessionPhase,
+}
+
+// A ticker was halted or resumed, e.g. by a volatility interruption
+#[derive(Debug, PartialEq, Clone)]
+pub struct TickerStatus {
+    pub ticker: Ticker,
+    pub state: TickerState,
+}
+
+// Indicative uncross of a ticker in a call auction: the price that would execute the most volume now
+// - indicative_price is None when the book does not cross
+#[derive(Debug, PartialEq, Clone)]
+pub struct AuctionIndicative {
+    pub ticker: Ticker,
+    pub indicative_price: Option<Price>,
+    pub matched_size: Size,
+    pub imbalance_size: Size,
+    pub imbalance_direction: Option<Direction>,
+}
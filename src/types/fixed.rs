@@ -0,0 +1,302 @@
+// Fixed: a fixed-point decimal stored as an integer number of units of 10^-DECIMALS
+// - used for prices and cash so that arithmetic on them is exact
+
+use serde::de::{self, Deserializer, Visitor};
+use serde::Deserialize;
+use std::fmt;
+use std::iter::Sum;
+use std::ops::{Add, AddAssign, Mul, Neg, Sub, SubAssign};
+use std::str::FromStr;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Fixed<const DECIMALS: u32>(i64);
+
+impl<const DECIMALS: u32> Fixed<DECIMALS> {
+    pub const ZERO: Self = Fixed(0);
+    pub const SCALE: i64 = 10_i64.pow(DECIMALS);
+
+    // Build from a raw number of units
+    pub const fn from_units(units: i64) -> Self {
+        Fixed(units)
+    }
+
+    // Build from an integer value, e.g. from_int(5) == 5.0
+    pub const fn from_int(value: i64) -> Self {
+        Fixed(value * Self::SCALE)
+    }
+
+    // Build from `units / 10^scale`, None if it cannot be represented exactly
+    pub fn from_scaled(units: i64, scale: u32) -> Option<Self> {
+        if scale <= DECIMALS {
+            units
+                .checked_mul(10_i64.checked_pow(DECIMALS - scale)?)
+                .map(Fixed)
+        } else {
+            let divisor = 10_i64.checked_pow(scale - DECIMALS)?;
+            (units % divisor == 0).then(|| Fixed(units / divisor))
+        }
+    }
+
+    pub fn units(&self) -> i64 {
+        self.0
+    }
+
+    pub fn abs(&self) -> Self {
+        Fixed(self.0.abs())
+    }
+
+    // self + other, None if it overflows
+    pub fn checked_add(&self, other: Self) -> Option<Self> {
+        self.0.checked_add(other.0).map(Fixed)
+    }
+
+    // price * size, None if it overflows
+    pub fn checked_mul(&self, size: u32) -> Option<Self> {
+        self.0.checked_mul(size as i64).map(Fixed)
+    }
+
+    pub fn is_multiple_of(&self, other: &Self) -> bool {
+        other.0 != 0 && self.0 % other.0 == 0
+    }
+
+    // percent % of self, rounded towards zero
+    pub fn percent(&self, percent: Self) -> Self {
+        Fixed((self.0 as i128 * percent.0 as i128 / (100 * Self::SCALE as i128)) as i64)
+    }
+
+    // bps basis points of self, rounded towards zero
+    pub fn basis_points(&self, bps: Self) -> Self {
+        Fixed((self.0 as i128 * bps.0 as i128 / (10_000 * Self::SCALE as i128)) as i64)
+    }
+
+    // bps basis points of self, rounded away from zero
+    pub fn basis_points_up(&self, bps: Self) -> Self {
+        let (value, scale) = (self.0 as i128 * bps.0 as i128, 10_000 * Self::SCALE as i128);
+        let rounding = (value % scale != 0) as i128 * value.signum();
+        Fixed((value / scale + rounding) as i64)
+    }
+}
+
+impl<const DECIMALS: u32> Add for Fixed<DECIMALS> {
+    type Output = Self;
+    fn add(self, rhs: Self) -> Self {
+        Fixed(self.0 + rhs.0)
+    }
+}
+
+impl<const DECIMALS: u32> Sub for Fixed<DECIMALS> {
+    type Output = Self;
+    fn sub(self, rhs: Self) -> Self {
+        Fixed(self.0 - rhs.0)
+    }
+}
+
+impl<const DECIMALS: u32> Neg for Fixed<DECIMALS> {
+    type Output = Self;
+    fn neg(self) -> Self {
+        Fixed(-self.0)
+    }
+}
+
+impl<const DECIMALS: u32> AddAssign for Fixed<DECIMALS> {
+    fn add_assign(&mut self, rhs: Self) {
+        self.0 += rhs.0;
+    }
+}
+
+impl<const DECIMALS: u32> SubAssign for Fixed<DECIMALS> {
+    fn sub_assign(&mut self, rhs: Self) {
+        self.0 -= rhs.0;
+    }
+}
+
+// price * size
+impl<const DECIMALS: u32> Mul<u32> for Fixed<DECIMALS> {
+    type Output = Self;
+    fn mul(self, rhs: u32) -> Self {
+        Fixed(self.0 * rhs as i64)
+    }
+}
+
+impl<const DECIMALS: u32> Sum for Fixed<DECIMALS> {
+    fn sum<I: Iterator<Item = Self>>(iter: I) -> Self {
+        iter.fold(Self::ZERO, |acc, x| acc + x)
+    }
+}
+
+impl<const DECIMALS: u32> fmt::Display for Fixed<DECIMALS> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let sign = if self.0 < 0 { "-" } else { "" };
+        let abs = self.0.unsigned_abs();
+        let scale = Self::SCALE as u64;
+        if DECIMALS == 0 {
+            write!(f, "{}{}", sign, abs)
+        } else {
+            write!(
+                f,
+                "{}{}.{:0width$}",
+                sign,
+                abs / scale,
+                abs % scale,
+                width = DECIMALS as usize
+            )
+        }
+    }
+}
+
+// Parse a plain decimal string such as "-12.345", rejecting digits beyond DECIMALS
+impl<const DECIMALS: u32> FromStr for Fixed<DECIMALS> {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = || format!("invalid decimal: {}", s);
+        let (negative, digits) = match s.strip_prefix('-') {
+            Some(rest) => (true, rest),
+            None => (false, s),
+        };
+        let (int_part, frac_part) = digits.split_once('.').unwrap_or((digits, ""));
+        let all_digits = |part: &str| part.chars().all(|c| c.is_ascii_digit());
+        if int_part.is_empty() || !all_digits(int_part) || !all_digits(frac_part) {
+            return Err(invalid());
+        }
+        let frac_part = frac_part.trim_end_matches('0');
+        let scale = frac_part.len() as u32;
+        let units: i64 = format!("{}{}", int_part, frac_part)
+            .parse()
+            .map_err(|_| invalid())?;
+        let value = Self::from_scaled(units, scale).ok_or_else(invalid)?;
+        Ok(if negative { -value } else { value })
+    }
+}
+
+// Accept both JSON numbers (e.g. 150.0) and strings (e.g. "150.25") in config files
+impl<'de, const DECIMALS: u32> Deserialize<'de> for Fixed<DECIMALS> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        struct FixedVisitor<const DECIMALS: u32>;
+
+        impl<'de, const DECIMALS: u32> Visitor<'de> for FixedVisitor<DECIMALS> {
+            type Value = Fixed<DECIMALS>;
+
+            fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+                write!(f, "a decimal with at most {} fractional digits", DECIMALS)
+            }
+            fn visit_i64<E: de::Error>(self, v: i64) -> Result<Self::Value, E> {
+                Ok(Fixed::from_int(v))
+            }
+            fn visit_u64<E: de::Error>(self, v: u64) -> Result<Self::Value, E> {
+                Ok(Fixed::from_int(v as i64))
+            }
+            fn visit_f64<E: de::Error>(self, v: f64) -> Result<Self::Value, E> {
+                // the shortest representation of an f64 round-trips the literal in the file
+                v.to_string().parse().map_err(E::custom)
+            }
+            fn visit_str<E: de::Error>(self, v: &str) -> Result<Self::Value, E> {
+                v.parse().map_err(E::custom)
+            }
+        }
+
+        deserializer.deserialize_any(FixedVisitor::<DECIMALS>)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Price = Fixed<4>;
+
+    #[test]
+    fn test_parse_and_display() {
+        assert_eq!("150".parse::<Price>().unwrap(), Price::from_int(150));
+        assert_eq!("0.01".parse::<Price>().unwrap(), Price::from_units(100));
+        assert_eq!("-2.5".parse::<Price>().unwrap(), Price::from_units(-25_000));
+        assert_eq!(
+            "1.23450".parse::<Price>().unwrap(),
+            Price::from_units(12_345)
+        );
+        assert!("1.00001".parse::<Price>().is_err());
+        assert!("abc".parse::<Price>().is_err());
+        assert!(".5".parse::<Price>().is_err());
+        assert_eq!(Price::from_units(12_345).to_string(), "1.2345");
+        assert_eq!(Price::from_units(-500).to_string(), "-0.0500");
+    }
+
+    #[test]
+    fn test_from_scaled() {
+        assert_eq!(Price::from_scaled(15, 1), Some(Price::from_units(15_000)));
+        assert_eq!(
+            Price::from_scaled(150_000, 6),
+            Some(Price::from_units(1_500))
+        );
+        assert_eq!(Price::from_scaled(150_001, 6), None);
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let prices: Vec<Price> = serde_json::from_str(r#"[150, 0.1, "2.05", 1e2]"#).unwrap();
+        assert_eq!(
+            prices,
+            vec![
+                Price::from_int(150),
+                Price::from_units(1_000),
+                Price::from_units(20_500),
+                Price::from_int(100)
+            ]
+        );
+        assert!(serde_json::from_str::<Price>("0.00001").is_err());
+    }
+
+    #[test]
+    fn test_checked() {
+        let max = Price::from_units(i64::MAX);
+        assert_eq!(
+            Price::from_int(10).checked_mul(3),
+            Some(Price::from_int(30))
+        );
+        assert_eq!(
+            Price::from_int(100_000_000).checked_mul(4_000_000_000),
+            None
+        );
+        assert_eq!(
+            max.checked_add(Price::from_units(-1)),
+            Some(Price::from_units(i64::MAX - 1))
+        );
+        assert_eq!(max.checked_add(Price::from_units(1)), None);
+    }
+
+    // 0.1 added ten thousand times is exactly 1000
+    #[test]
+    fn test_exact_accumulation() {
+        let tick = Price::from_units(1_000);
+        let total: Price = (0..10_000).map(|_| tick).sum();
+        assert_eq!(total, Price::from_int(1_000));
+        assert_eq!(tick * 3 - Price::from_units(3_000), Price::ZERO);
+    }
+
+    #[test]
+    fn test_percent() {
+        let price = Price::from_int(150);
+        assert_eq!(price.percent(Price::from_int(10)), Price::from_int(15));
+        assert_eq!(
+            price.percent("0.5".parse().unwrap()),
+            Price::from_units(7_500)
+        );
+        assert_eq!(
+            Price::from_units(3).percent(Price::from_int(50)),
+            Price::from_units(1)
+        );
+        let price = Price::from_units(100_001);
+        assert_eq!(
+            price.basis_points(Price::from_int(10)),
+            Price::from_units(100)
+        );
+        assert_eq!(
+            price.basis_points_up(Price::from_int(10)),
+            Price::from_units(101)
+        );
+        assert_eq!(
+            Price::from_int(10).basis_points_up(Price::from_int(10)),
+            Price::from_units(100)
+        );
+    }
+}
@@ -1,169 +1,72 @@
-use super::common::{OrderId, Price, Size, Timestamp};
-use std::cmp::Ordering;
+use super::common::{InvId, OrderId, Price, SelfTradePrevention, Size, Timestamp};
 
-#[derive(Debug)]
-pub struct BuyOrder {
-    pub order_id: OrderId,
-    pub size: Size,
-    pub price: Price,
-    pub timestamp: Timestamp,
-}
-
-impl Ord for BuyOrder {
-    fn cmp(&self, other: &Self) -> Ordering {
-        match self.price.partial_cmp(&other.price).unwrap() {
-            Ordering::Equal => match self.timestamp.cmp(&other.timestamp) {
-                Ordering::Equal => Ordering::Equal,
-                Ordering::Greater => Ordering::Less,
-                Ordering::Less => Ordering::Greater,
-            },
-            Ordering::Greater => Ordering::Greater,
-            Ordering::Less => Ordering::Less,
-        }
-    }
-}
-
-impl PartialOrd for BuyOrder {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
-    }
-}
-
-impl PartialEq for BuyOrder {
-    fn eq(&self, other: &Self) -> bool {
-        self.order_id == other.order_id
-    }
+// OrderOwner: the investor of an order and its self-trade prevention mode
+#[derive(Debug, Clone, PartialEq)]
+pub struct OrderOwner {
+    pub inv_id: InvId,
+    pub self_trade_prevention: Option<SelfTradePrevention>,
 }
 
-impl Eq for BuyOrder {}
-
-#[derive(Debug)]
-pub struct SellOrder {
+// RestingOrder: an order resting in the orderbook
+// - linked to its neighbours in the same price level, so it can be removed without scanning the level
+// - an iceberg order only displays a slice of display_size, the rest is kept in hidden_size
+// - a display_size of 0 is a hidden order: it displays nothing and its whole size is matchable
+#[derive(Debug, Clone, PartialEq)]
+pub struct RestingOrder {
     pub order_id: OrderId,
-    pub size: Size,
+    pub owner: OrderOwner,
+    pub size: Size, // displayed size, the whole size of a hidden order
+    pub hidden_size: Size,
+    pub display_size: Option<Size>,
     pub price: Price,
     pub timestamp: Timestamp,
+    pub prev: Option<OrderId>,
+    pub next: Option<OrderId>,
 }
 
-impl Ord for SellOrder {
-    fn cmp(&self, other: &Self) -> Ordering {
-        match self.price.partial_cmp(&other.price).unwrap() {
-            Ordering::Equal => match self.timestamp.cmp(&other.timestamp) {
-                Ordering::Equal => Ordering::Equal,
-                Ordering::Greater => Ordering::Less,
-                Ordering::Less => Ordering::Greater,
-            },
-            Ordering::Greater => Ordering::Less,
-            Ordering::Less => Ordering::Greater,
-        }
+impl RestingOrder {
+    pub fn is_hidden(&self) -> bool {
+        self.display_size == Some(0)
     }
 }
 
-impl PartialOrd for SellOrder {
-    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
-        Some(self.cmp(other))
-    }
+// PriceLevel: FIFO queue of all resting orders at the same price on one side of the orderbook
+// - hidden orders queue after all displayed orders, in a FIFO queue of their own
+#[derive(Debug, Clone, PartialEq)]
+pub struct PriceLevel {
+    pub price: Price,
+    pub total_size: Size,  // displayed size
+    pub hidden_size: Size, // hidden reserve of iceberg orders and size of hidden orders
+    pub order_count: usize,
+    pub head: Option<OrderId>,
+    pub tail: Option<OrderId>,
+    pub hidden_head: Option<OrderId>,
+    pub hidden_tail: Option<OrderId>,
 }
 
-impl PartialEq for SellOrder {
-    fn eq(&self, other: &Self) -> bool {
-        self.order_id == other.order_id
+impl PriceLevel {
+    pub fn new(price: Price) -> Self {
+        PriceLevel {
+            price,
+            total_size: 0,
+            hidden_size: 0,
+            order_count: 0,
+            head: None,
+            tail: None,
+            hidden_head: None,
+            hidden_tail: None,
+        }
     }
-}
-
-impl Eq for SellOrder {}
-
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::collections::BinaryHeap;
 
-    // Test the ordering of buy orders and sell orders
-    #[test]
-    fn test_buyorder_ordering() {
-        let buy_order1 = BuyOrder {
-            order_id: 1,
-            size: 100,
-            price: 100.0,
-            timestamp: 5,
-        };
-        let buy_order2 = BuyOrder {
-            order_id: 2,
-            size: 100,
-            price: 100.0,
-            timestamp: 6,
-        };
-        let buy_order3 = BuyOrder {
-            order_id: 3,
-            size: 100,
-            price: 101.0,
-            timestamp: 7,
-        };
-        let buy_order4 = BuyOrder {
-            order_id: 4,
-            size: 100,
-            price: 99.0,
-            timestamp: 8,
-        };
-        assert_eq!(buy_order1.cmp(&buy_order2), Ordering::Greater);
-        assert_eq!(buy_order1.cmp(&buy_order3), Ordering::Less);
-        assert_eq!(buy_order1.cmp(&buy_order4), Ordering::Greater);
-        assert_eq!(buy_order2.cmp(&buy_order3), Ordering::Less);
-        assert_eq!(buy_order2.cmp(&buy_order4), Ordering::Greater);
-        assert_eq!(buy_order3.cmp(&buy_order4), Ordering::Greater);
-
-        let mut buy_orders = BinaryHeap::new();
-        buy_orders.push(buy_order4);
-        buy_orders.push(buy_order3);
-        buy_orders.push(buy_order2);
-        buy_orders.push(buy_order1);
-        assert_eq!(buy_orders.pop().unwrap().order_id, 3);
-        assert_eq!(buy_orders.pop().unwrap().order_id, 1);
-        assert_eq!(buy_orders.pop().unwrap().order_id, 2);
-        assert_eq!(buy_orders.pop().unwrap().order_id, 4);
+    pub fn is_empty(&self) -> bool {
+        self.order_count == 0
     }
 
-    #[test]
-    fn test_sellorder_ordering() {
-        let sell_order1 = SellOrder {
-            order_id: 1,
-            size: 100,
-            price: 100.0,
-            timestamp: 5,
-        };
-        let sell_order2 = SellOrder {
-            order_id: 2,
-            size: 100,
-            price: 100.0,
-            timestamp: 6,
-        };
-        let sell_order3 = SellOrder {
-            order_id: 3,
-            size: 100,
-            price: 101.0,
-            timestamp: 7,
-        };
-        let sell_order4 = SellOrder {
-            order_id: 4,
-            size: 100,
-            price: 99.0,
-            timestamp: 8,
-        };
-        assert_eq!(sell_order1.cmp(&sell_order2), Ordering::Greater);
-        assert_eq!(sell_order1.cmp(&sell_order3), Ordering::Greater);
-        assert_eq!(sell_order1.cmp(&sell_order4), Ordering::Less);
-        assert_eq!(sell_order2.cmp(&sell_order3), Ordering::Greater);
-        assert_eq!(sell_order2.cmp(&sell_order4), Ordering::Less);
-        assert_eq!(sell_order3.cmp(&sell_order4), Ordering::Less);
-
-        let mut sell_orders = BinaryHeap::new();
-        sell_orders.push(sell_order4);
-        sell_orders.push(sell_order3);
-        sell_orders.push(sell_order2);
-        sell_orders.push(sell_order1);
-        assert_eq!(sell_orders.pop().unwrap().order_id, 4);
-        assert_eq!(sell_orders.pop().unwrap().order_id, 1);
-        assert_eq!(sell_orders.pop().unwrap().order_id, 2);
-        assert_eq!(sell_orders.pop().unwrap().order_id, 3);
+    // Head and tail of the queue of displayed or hidden orders
+    pub fn queue_mut(&mut self, hidden: bool) -> (&mut Option<OrderId>, &mut Option<OrderId>) {
+        match hidden {
+            true => (&mut self.hidden_head, &mut self.hidden_tail),
+            false => (&mut self.head, &mut self.tail),
+        }
     }
 }
@@ -1,5 +1,8 @@
 use super::{
-    common::{Direction, LimitOrMarket, OrderId, Price, Size, TimeInForce, Timestamp},
+    common::{
+        Cash, Direction, InvId, LimitOrMarket, OrderId, PostOnly, Price, SelfTradePrevention, Size,
+        TimeInForce, Timestamp,
+    },
     event::Event,
     portal::OrderResponse,
 };
@@ -8,19 +11,39 @@ use super::{
 pub enum OrderbookRequest {
     NewOrder(NewOrderRequest),
     CancelOrder(CancelOrderRequest),
+    AmendOrder(AmendOrderRequest),
 }
 pub struct NewOrderRequest {
     pub order_id: OrderId,
+    pub inv_id: InvId,
     pub direction: Direction,
     pub size: Size,
     pub price: Price,
     pub timestamp: Timestamp,
     pub limit_or_market: LimitOrMarket,
     pub time_in_force: TimeInForce,
+    pub display_size: Option<Size>, // iceberg orders only, 0 for a hidden order
+    pub post_only: Option<PostOnly>,
+    pub min_quantity: Option<Size>, // IOC orders only
+    pub self_trade_prevention: Option<SelfTradePrevention>,
 }
 pub struct CancelOrderRequest {
     pub order_id: OrderId,
 }
+pub struct AmendOrderRequest {
+    pub order_id: OrderId,
+    pub size: Size,
+    pub price: Price,
+    pub timestamp: Timestamp,
+}
+
+// Price bands of an orderbook: a trade outside them interrupts continuous trading with a call auction
+#[derive(Debug, Clone)]
+pub struct PriceBands {
+    pub static_band: Option<Price>,  // percent from the close price
+    pub dynamic_band: Option<Price>, // percent from the last trade price
+    pub auction_duration: Timestamp, // seconds
+}
 
 #[derive(Debug, PartialEq)]
 pub enum OrderbookLog {
@@ -33,8 +56,44 @@ pub struct OrderFillResponse {
     pub order_id: OrderId,
     pub fill_size: Size,
     pub fill_price: Price,
+    pub liquidity: Liquidity,
+    pub fee: Cash, // charged by the portal, negative for a rebate
+}
+// Whether a fill added liquidity (the resting order), took it (the incoming order), or came from an
+// auction uncross
+#[derive(Debug, PartialEq, Clone)]
+pub enum Liquidity {
+    Maker,
+    Taker,
+    Auction,
+}
+// Open size taken off a live order without a trade, by self-trade prevention
+#[derive(Debug, PartialEq, Clone)]
+pub struct OrderReducedResponse {
+    pub order_id: OrderId,
+    pub reduced_size: Size,
+}
+// A child order was activated by its parent order, e.g. when the entry order of a bracket is done
+#[derive(Debug, PartialEq, Clone)]
+pub struct OrderActivatedResponse {
+    pub order_id: OrderId,
+    pub parent_id: OrderId,
 }
 #[derive(Debug, PartialEq, Clone)]
 pub struct OrderDeadResponse {
     pub order_id: OrderId,
+    pub reason: DeadReason,
+}
+
+// Why an order left the orderbook (or never entered it)
+#[derive(Debug, PartialEq, Clone)]
+pub enum DeadReason {
+    Filled,            // the whole size was executed
+    Cancelled,         // cancelled by the investor, or by the exchange, e.g. on expiry
+    Unfilled,          // the unfilled size of an order that does not rest, e.g. IOC or market
+    FillOrKill,        // FOK: the whole size could not be filled immediately
+    PostOnly,          // post-only: would have taken liquidity
+    MinQuantity,       // the minimum quantity could not be filled immediately
+    InsufficientFunds, // a released stop order could not be reserved at the market price
+    SelfTrade,         // cancelled by self-trade prevention
 }
@@ -1,16 +1,20 @@
 use super::{
     common::{
-        Direction, InvId, LimitOrMarket, OrderId, Price, SeqNum, Size, SubId, TimeInForce,
-        Timestamp,
+        Cash, Contingency, Direction, InvId, LimitOrMarket, OrderId, PostOnly, Price,
+        SelfTradePrevention, SeqNum, Size, SubId, TimeInForce, Timestamp,
     },
     event::Event,
-    orderbook::{OrderDeadResponse, OrderFillResponse},
+    orderbook::{
+        OrderActivatedResponse, OrderDeadResponse, OrderFillResponse, OrderReducedResponse,
+    },
 };
 #[derive(Debug)]
 pub enum PortalRequest {
     EventHistory(SubId),
     NewOrder(InvId, PortalNewOrderRequest),
     CancelOrder(InvId, OrderId),
+    AmendOrder(InvId, PortalAmendOrderRequest),
+    Timer(Timestamp),
 }
 
 #[derive(Debug)]
@@ -22,6 +26,20 @@ pub struct PortalNewOrderRequest {
     pub limit_or_market: LimitOrMarket,
     pub time_in_force: TimeInForce,
     pub timestamp: Timestamp,
+    pub display_size: Option<Size>, // iceberg orders only: size shown in the orderbook
+    pub hidden: bool,               // limit orders only: rests without being shown in the orderbook
+    pub post_only: Option<PostOnly>,
+    pub min_quantity: Option<Size>, // IOC orders only: minimum size to execute, or nothing
+    pub self_trade_prevention: Option<SelfTradePrevention>,
+    pub contingency: Option<Contingency>,
+}
+
+#[derive(Debug)]
+pub struct PortalAmendOrderRequest {
+    pub order_id: OrderId,
+    pub size: Size,
+    pub price: Price,
+    pub timestamp: Timestamp,
 }
 
 pub enum PortalTask {
@@ -30,11 +48,26 @@ pub enum PortalTask {
     OrderAck(InvId, SeqNum, OrderId),    // ack new order request
     OrderReject(InvId, SeqNum, String),  // reject new order request
     CancelReject(InvId, SeqNum, String), // reject cancel order request
+    AmendAck(InvId, SeqNum, OrderId),    // ack amend order request
+    AmendReject(InvId, SeqNum, String),  // reject amend order request
     OrderResponse(InvId, OrderResponse),
+    MarginCall(InvId, MarginCallResponse),
+}
+
+// The equity of a margin account fell below its maintenance margin: it is liquidated at the deadline
+// unless it recovers, the ids of the orders closing its positions are sent then
+#[derive(Debug, PartialEq, Clone)]
+pub struct MarginCallResponse {
+    pub equity: Cash,
+    pub requirement: Cash,
+    pub deadline: Timestamp,
+    pub liquidation_orders: Vec<OrderId>,
 }
 
 #[derive(Debug, PartialEq, Clone)]
 pub enum OrderResponse {
     OrderFill(OrderFillResponse),
     OrderDead(OrderDeadResponse),
+    OrderReduced(OrderReducedResponse),
+    OrderActivated(OrderActivatedResponse),
 }
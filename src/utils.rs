@@ -1,24 +1,41 @@
 // utils: contains helper functions for parsing and wrapping rpc proto types
 
 use crate::server::stock_exchange::{
-    rpc_order_request::{self, CancelOrder, NewOrder},
-    rpc_order_response::{CancelRej, OrderAck, OrderDead, OrderFill, OrderRej, Response},
-    rpc_subscribe_response, RpcOrderRequest, RpcOrderResponse, RpcSubscribeResponse,
+    rpc_order_request::{self, AmendOrder, CancelOrder, NewOrder},
+    rpc_order_response::{
+        AmendAck, AmendRej, CancelRej, LoginRej, MarginCall, OrderAck, OrderActivated, OrderDead,
+        OrderFill, OrderReduced, OrderRej, Response,
+    },
+    rpc_subscribe_response, RpcDecimal, RpcOrderRequest, RpcOrderResponse,
+    RpcSetTickerStateRequest, RpcSetTickerStateResponse, RpcSubscribeResponse,
 };
 use crate::types::{
     account_manager::AccountUpdate,
-    common::{Direction, InvId, LimitOrMarket, OrderId, SeqNum, SubId, TimeInForce},
-    event::Event,
-    orderbook::{OrderDeadResponse, OrderFillResponse},
-    portal::{OrderResponse, PortalNewOrderRequest, PortalRequest},
+    common::{
+        Contingency, Direction, InvId, LimitOrMarket, OrderId, PegReference, PostOnly, Price,
+        SelfTradePrevention, SeqNum, SessionPhase, SubId, Ticker, TickerState, TimeInForce,
+        Timestamp, Trail,
+    },
+    event::{AuctionIndicative, Event, MarketStatus, TickerStatus},
+    fixed::Fixed,
+    orderbook::{
+        DeadReason, Liquidity, OrderActivatedResponse, OrderDeadResponse, OrderFillResponse,
+        OrderReducedResponse,
+    },
+    portal::{
+        MarginCallResponse, OrderResponse, PortalAmendOrderRequest, PortalNewOrderRequest,
+        PortalRequest,
+    },
 };
 use std::time::{SystemTime, UNIX_EPOCH};
 
 pub fn get_inv_id(acc_upd: &AccountUpdate) -> InvId {
     match acc_upd {
-        AccountUpdate::UpdCash(inv_id, _) => inv_id.clone(),
-        AccountUpdate::AddPos(inv_id, ..) => inv_id.clone(),
-        AccountUpdate::MinusPos(inv_id, ..) => inv_id.clone(),
+        AccountUpdate::UpdCash(inv_id, _) => *inv_id,
+        AccountUpdate::AddPos(inv_id, ..) => *inv_id,
+        AccountUpdate::MinusPos(inv_id, ..) => *inv_id,
+        AccountUpdate::AddOpen(inv_id, ..) => *inv_id,
+        AccountUpdate::MinusOpen(inv_id, ..) => *inv_id,
     }
 }
 
@@ -26,6 +43,8 @@ pub fn get_order_id(order_resp: &OrderResponse) -> OrderId {
     match order_resp {
         OrderResponse::OrderFill(order_fill) => order_fill.order_id,
         OrderResponse::OrderDead(order_dead) => order_dead.order_id,
+        OrderResponse::OrderReduced(order_reduced) => order_reduced.order_id,
+        OrderResponse::OrderActivated(order_activated) => order_activated.order_id,
     }
 }
 
@@ -37,11 +56,11 @@ fn get_timestamp() -> u64 {
 
 // Parse: rpc proto type -> portal type
 // Wrap: portal type -> rpc proto type
-fn parse_direction(value: i32) -> Direction {
+fn parse_direction(value: i32) -> Result<Direction, String> {
     match value {
-        0 => Direction::Buy,
-        1 => Direction::Sell,
-        _ => panic!("invalid direction"),
+        0 => Ok(Direction::Buy),
+        1 => Ok(Direction::Sell),
+        _ => Err(format!("invalid direction: {}", value)),
     }
 }
 fn wrap_direction(direction: Direction) -> i32 {
@@ -50,35 +69,186 @@ fn wrap_direction(direction: Direction) -> i32 {
         Direction::Sell => 1,
     }
 }
-fn parse_limit_or_market(value: i32) -> LimitOrMarket {
+fn wrap_ticker_state(state: TickerState) -> i32 {
+    match state {
+        TickerState::Trading => 0,
+        TickerState::VolatilityAuction => 1,
+        TickerState::Halted => 2,
+        TickerState::CancelOnly => 3,
+    }
+}
+fn parse_ticker_state(value: i32) -> Result<TickerState, String> {
     match value {
-        0 => LimitOrMarket::Limit,
-        1 => LimitOrMarket::Market,
-        _ => panic!("invalid limit or market"),
+        0 => Ok(TickerState::Trading),
+        1 => Ok(TickerState::VolatilityAuction),
+        2 => Ok(TickerState::Halted),
+        3 => Ok(TickerState::CancelOnly),
+        _ => Err("Invalid ticker state".to_string()),
+    }
+}
+fn wrap_session_phase(phase: SessionPhase) -> i32 {
+    match phase {
+        SessionPhase::Closed => 0,
+        SessionPhase::PreOpen => 1,
+        SessionPhase::Continuous => 2,
+        SessionPhase::ClosingAuction => 3,
+    }
+}
+fn parse_limit_or_market(
+    value: i32,
+    stop_price: Option<RpcDecimal>,
+    peg_offset: Option<RpcDecimal>,
+    trail: Option<RpcDecimal>,
+    trail_percent: bool,
+    limit_offset: Option<RpcDecimal>,
+) -> Result<LimitOrMarket, String> {
+    let parse_trail = |trail| -> Result<Trail, String> {
+        match trail_percent {
+            true => Ok(Trail::Percent(parse_decimal(trail)?)),
+            false => Ok(Trail::Amount(parse_decimal(trail)?)),
+        }
+    };
+    match value {
+        0 => Ok(LimitOrMarket::Limit),
+        1 => Ok(LimitOrMarket::Market),
+        2 => Ok(LimitOrMarket::StopMarket(parse_decimal(stop_price)?)),
+        3 => Ok(LimitOrMarket::StopLimit(parse_decimal(stop_price)?)),
+        4 => Ok(LimitOrMarket::Peg(
+            PegReference::Primary,
+            parse_decimal(peg_offset)?,
+        )),
+        5 => Ok(LimitOrMarket::Peg(
+            PegReference::Midpoint,
+            parse_decimal(peg_offset)?,
+        )),
+        6 => Ok(LimitOrMarket::TrailingStop(parse_trail(trail)?, None)),
+        7 => Ok(LimitOrMarket::TrailingStop(
+            parse_trail(trail)?,
+            Some(parse_decimal(limit_offset)?),
+        )),
+        _ => Err(format!("invalid limit or market: {}", value)),
+    }
+}
+// A missing decimal is read as zero, e.g. the price of a market order
+pub fn parse_decimal<const DECIMALS: u32>(
+    value: Option<RpcDecimal>,
+) -> Result<Fixed<DECIMALS>, String> {
+    match value {
+        Some(value) => Fixed::from_scaled(value.units, value.scale).ok_or(format!(
+            "invalid decimal: {} units at scale {}",
+            value.units, value.scale
+        )),
+        None => Ok(Fixed::ZERO),
+    }
+}
+pub fn wrap_decimal<const DECIMALS: u32>(value: Fixed<DECIMALS>) -> Option<RpcDecimal> {
+    Some(RpcDecimal {
+        units: value.units(),
+        scale: DECIMALS,
+    })
+}
+fn parse_post_only(value: i32) -> Result<Option<PostOnly>, String> {
+    match value {
+        0 => Ok(None),
+        1 => Ok(Some(PostOnly::Reject)),
+        2 => Ok(Some(PostOnly::Reprice)),
+        _ => Err(format!("invalid post only: {}", value)),
+    }
+}
+fn parse_self_trade_prevention(value: i32) -> Result<Option<SelfTradePrevention>, String> {
+    match value {
+        0 => Ok(None),
+        1 => Ok(Some(SelfTradePrevention::CancelNewest)),
+        2 => Ok(Some(SelfTradePrevention::CancelOldest)),
+        3 => Ok(Some(SelfTradePrevention::CancelBoth)),
+        4 => Ok(Some(SelfTradePrevention::DecrementAndCancel)),
+        _ => Err(format!("invalid self trade prevention: {}", value)),
+    }
+}
+fn parse_contingency(
+    oco_order_id: OrderId,
+    take_profit: Option<RpcDecimal>,
+    stop_loss: Option<RpcDecimal>,
+) -> Result<Option<Contingency>, String> {
+    let take_profit = take_profit
+        .map(|price| parse_decimal(Some(price)))
+        .transpose()?;
+    let stop_loss = stop_loss
+        .map(|price| parse_decimal(Some(price)))
+        .transpose()?;
+    match (oco_order_id, take_profit, stop_loss) {
+        (0, None, None) => Ok(None),
+        (0, take_profit, stop_loss) => Ok(Some(Contingency::Bracket {
+            take_profit,
+            stop_loss,
+        })),
+        (oco_order_id, None, None) => Ok(Some(Contingency::Oco(oco_order_id))),
+        _ => Err("An OCO order cannot be a bracket entry".to_string()),
+    }
+}
+fn wrap_liquidity(liquidity: Liquidity) -> i32 {
+    match liquidity {
+        Liquidity::Maker => 0,
+        Liquidity::Taker => 1,
+        Liquidity::Auction => 2,
+    }
+}
+
+fn wrap_dead_reason(reason: DeadReason) -> i32 {
+    match reason {
+        DeadReason::Filled => 0,
+        DeadReason::Cancelled => 1,
+        DeadReason::Unfilled => 2,
+        DeadReason::FillOrKill => 3,
+        DeadReason::PostOnly => 4,
+        DeadReason::MinQuantity => 5,
+        DeadReason::InsufficientFunds => 6,
+        DeadReason::SelfTrade => 7,
     }
 }
-fn parse_time_in_force(value: i32) -> TimeInForce {
+fn parse_time_in_force(value: i32, expire_time: Timestamp) -> Result<TimeInForce, String> {
     match value {
-        0 => TimeInForce::Day,
-        1 => TimeInForce::IOC,
-        _ => panic!("invalid time in force"),
+        0 => Ok(TimeInForce::Day),
+        1 => Ok(TimeInForce::IOC),
+        2 => Ok(TimeInForce::FOK),
+        3 => Ok(TimeInForce::GTC),
+        4 => Ok(TimeInForce::GTD(expire_time)),
+        _ => Err(format!("invalid time in force: {}", value)),
     }
 }
 
 // Send Order rpc
 
 // parse rpc new order request to portal request
-fn parse_new_order_request(inv_id: InvId, new_order: NewOrder) -> PortalRequest {
+fn parse_new_order_request(inv_id: InvId, new_order: NewOrder) -> Result<PortalRequest, String> {
+    let price: Price = parse_decimal(new_order.price)?;
     let req = PortalNewOrderRequest {
         ticker: new_order.ticker,
-        direction: parse_direction(new_order.direction),
+        direction: parse_direction(new_order.direction)?,
         size: new_order.size,
-        price: new_order.price,
-        limit_or_market: parse_limit_or_market(new_order.limit_or_market),
-        time_in_force: parse_time_in_force(new_order.time_in_force),
+        price,
+        limit_or_market: parse_limit_or_market(
+            new_order.limit_or_market,
+            new_order.stop_price,
+            new_order.peg_offset,
+            new_order.trail,
+            new_order.trail_percent,
+            new_order.limit_offset,
+        )?,
+        time_in_force: parse_time_in_force(new_order.time_in_force, new_order.expire_time)?,
         timestamp: get_timestamp(),
+        display_size: (new_order.display_size > 0).then_some(new_order.display_size),
+        hidden: new_order.hidden,
+        post_only: parse_post_only(new_order.post_only)?,
+        min_quantity: (new_order.min_quantity > 0).then_some(new_order.min_quantity),
+        self_trade_prevention: parse_self_trade_prevention(new_order.self_trade_prevention)?,
+        contingency: parse_contingency(
+            new_order.oco_order_id,
+            new_order.take_profit,
+            new_order.stop_loss,
+        )?,
     };
-    PortalRequest::NewOrder(inv_id, req)
+    Ok(PortalRequest::NewOrder(inv_id, req))
 }
 
 // parse rpc cancel order request to portal request
@@ -86,27 +256,52 @@ fn parse_cancel_order_request(inv_id: InvId, cancel_order: CancelOrder) -> Porta
     PortalRequest::CancelOrder(inv_id, cancel_order.order_id)
 }
 
-// parse RpcOrderRequest to PortalRequest
-pub fn parse_order_request(inv_id: InvId, request: RpcOrderRequest) -> PortalRequest {
-    let request: rpc_order_request::Request = request.request.unwrap();
+// parse rpc amend order request to portal request
+fn parse_amend_order_request(
+    inv_id: InvId,
+    amend_order: AmendOrder,
+) -> Result<PortalRequest, String> {
+    let price: Price = parse_decimal(amend_order.price)?;
+    let req = PortalAmendOrderRequest {
+        order_id: amend_order.order_id,
+        size: amend_order.size,
+        price,
+        timestamp: get_timestamp(),
+    };
+    Ok(PortalRequest::AmendOrder(inv_id, req))
+}
+
+// parse RpcOrderRequest to PortalRequest, Err if the request carries an invalid value or is not an order
+// request, e.g. a second login
+pub fn parse_order_request(
+    inv_id: InvId,
+    request: RpcOrderRequest,
+) -> Result<PortalRequest, String> {
+    let request: rpc_order_request::Request = request.request.ok_or("empty request")?;
     match request {
         rpc_order_request::Request::NewOrder(new_order) => {
             parse_new_order_request(inv_id, new_order)
         }
         rpc_order_request::Request::CancelOrder(cancel_order) => {
-            parse_cancel_order_request(inv_id, cancel_order)
+            Ok(parse_cancel_order_request(inv_id, cancel_order))
+        }
+        rpc_order_request::Request::AmendOrder(amend_order) => {
+            parse_amend_order_request(inv_id, amend_order)
         }
-        _ => panic!("parse_order_request: invalid request"),
+        rpc_order_request::Request::Login(_) => Err("already logged in".to_string()),
+        rpc_order_request::Request::Logout(_) => Err("not an order request".to_string()),
     }
 }
 
-// parse seqnum from rpc request
-pub fn parse_seqnum(request: &RpcOrderRequest) -> SeqNum {
+// parse seqnum from rpc request, Err if the request is empty
+pub fn parse_seqnum(request: &RpcOrderRequest) -> Result<SeqNum, String> {
     match &request.request {
-        Some(rpc_order_request::Request::Login(login)) => login.seqnum.clone(),
-        Some(rpc_order_request::Request::NewOrder(new_order)) => new_order.seqnum.clone(),
-        Some(rpc_order_request::Request::CancelOrder(cancel_order)) => cancel_order.seqnum.clone(),
-        _ => panic!("invalid request"),
+        Some(rpc_order_request::Request::Login(login)) => Ok(login.seqnum),
+        Some(rpc_order_request::Request::NewOrder(new_order)) => Ok(new_order.seqnum),
+        Some(rpc_order_request::Request::CancelOrder(cancel_order)) => Ok(cancel_order.seqnum),
+        Some(rpc_order_request::Request::AmendOrder(amend_order)) => Ok(amend_order.seqnum),
+        Some(rpc_order_request::Request::Logout(logout)) => Ok(logout.seqnum),
+        None => Err("empty request".to_string()),
     }
 }
 
@@ -115,48 +310,115 @@ pub fn wrap_order_response(response: OrderResponse) -> RpcOrderResponse {
     match response {
         OrderResponse::OrderFill(order_fill) => wrap_order_fill_response(order_fill),
         OrderResponse::OrderDead(order_dead) => wrap_order_dead_response(order_dead),
+        OrderResponse::OrderReduced(order_reduced) => wrap_order_reduced_response(order_reduced),
+        OrderResponse::OrderActivated(order_activated) => {
+            wrap_order_activated_response(order_activated)
+        }
+    }
+}
+
+pub fn wrap_login_reject(seqnum: SeqNum, reason: String) -> RpcOrderResponse {
+    RpcOrderResponse {
+        response: Some(Response::LoginRej(LoginRej { seqnum, reason })),
+        out_seqnum: 0,
     }
 }
 
 pub fn wrap_order_reject(seqnum: u64, reason: String) -> RpcOrderResponse {
     RpcOrderResponse {
         response: Some(Response::Rej(OrderRej { seqnum, reason })),
+        out_seqnum: 0,
     }
 }
 
 pub fn wrap_cancel_reject(seqnum: u64, reason: String) -> RpcOrderResponse {
     RpcOrderResponse {
         response: Some(Response::CancelRej(CancelRej { seqnum, reason })),
+        out_seqnum: 0,
     }
 }
 
-pub fn wrap_order_ack(seqnum: SeqNum, order_id: OrderId) -> RpcOrderResponse {
+pub fn wrap_amend_ack(seqnum: SeqNum, order_id: OrderId) -> RpcOrderResponse {
     RpcOrderResponse {
-        response: Some(Response::Ack(OrderAck {
-            seqnum: seqnum,
-            order_id: order_id,
+        response: Some(Response::AmendAck(AmendAck { seqnum, order_id })),
+        out_seqnum: 0,
+    }
+}
+
+pub fn wrap_amend_reject(seqnum: SeqNum, reason: String) -> RpcOrderResponse {
+    RpcOrderResponse {
+        response: Some(Response::AmendRej(AmendRej { seqnum, reason })),
+        out_seqnum: 0,
+    }
+}
+
+pub fn wrap_margin_call(response: MarginCallResponse) -> RpcOrderResponse {
+    RpcOrderResponse {
+        response: Some(Response::MarginCall(MarginCall {
+            equity: wrap_decimal(response.equity),
+            requirement: wrap_decimal(response.requirement),
+            deadline: response.deadline,
+            liquidation_orders: response.liquidation_orders,
         })),
+        out_seqnum: 0,
+    }
+}
+
+pub fn wrap_order_ack(seqnum: SeqNum, order_id: OrderId) -> RpcOrderResponse {
+    RpcOrderResponse {
+        response: Some(Response::Ack(OrderAck { seqnum, order_id })),
+        out_seqnum: 0,
     }
 }
 fn wrap_order_fill_response(response: OrderFillResponse) -> RpcOrderResponse {
     RpcOrderResponse {
         response: Some(Response::Fill(OrderFill {
             order_id: response.order_id,
-            price: response.fill_price,
+            price: wrap_decimal(response.fill_price),
             size: response.fill_size,
+            liquidity: wrap_liquidity(response.liquidity),
+            fee: wrap_decimal(response.fee),
         })),
+        out_seqnum: 0,
     }
 }
+fn wrap_order_reduced_response(response: OrderReducedResponse) -> RpcOrderResponse {
+    RpcOrderResponse {
+        response: Some(Response::Reduced(OrderReduced {
+            order_id: response.order_id,
+            reduced_size: response.reduced_size,
+        })),
+        out_seqnum: 0,
+    }
+}
+
+fn wrap_order_activated_response(response: OrderActivatedResponse) -> RpcOrderResponse {
+    RpcOrderResponse {
+        response: Some(Response::Activated(OrderActivated {
+            order_id: response.order_id,
+            parent_id: response.parent_id,
+        })),
+        out_seqnum: 0,
+    }
+}
+
 fn wrap_order_dead_response(response: OrderDeadResponse) -> RpcOrderResponse {
     RpcOrderResponse {
         response: Some(Response::Dead(OrderDead {
             order_id: response.order_id,
+            reason: wrap_dead_reason(response.reason),
         })),
+        out_seqnum: 0,
     }
 }
 
 // Subscribe rpc
 
+// make a timer request carrying the current time
+pub fn make_timer_request() -> PortalRequest {
+    PortalRequest::Timer(get_timestamp())
+}
+
 // parse rpc subscribe request to portal request
 pub fn parse_subscribe_request(sub_id: SubId) -> PortalRequest {
     PortalRequest::EventHistory(sub_id)
@@ -171,7 +433,7 @@ pub fn wrap_event(event: Event) -> RpcSubscribeResponse {
                     order_id: added.order_id,
                     ticker: added.ticker,
                     direction: wrap_direction(added.direction),
-                    limit_price: added.limit_price,
+                    limit_price: wrap_decimal(added.limit_price),
                     size: added.resting_size,
                 },
             )),
@@ -181,7 +443,7 @@ pub fn wrap_event(event: Event) -> RpcSubscribeResponse {
                 rpc_subscribe_response::OrderExecuted {
                     order_id: executed.order_id,
                     ticker: executed.ticker,
-                    execution_price: executed.execution_price,
+                    execution_price: wrap_decimal(executed.execution_price),
                     execution_size: executed.execution_size,
                 },
             )),
@@ -193,5 +455,164 @@ pub fn wrap_event(event: Event) -> RpcSubscribeResponse {
                 },
             )),
         },
+        Event::OrderModified(modified) => RpcSubscribeResponse {
+            response: Some(rpc_subscribe_response::Response::Modified(
+                rpc_subscribe_response::OrderModified {
+                    order_id: modified.order_id,
+                    ticker: modified.ticker,
+                    direction: wrap_direction(modified.direction),
+                    limit_price: wrap_decimal(modified.limit_price),
+                    size: modified.resting_size,
+                },
+            )),
+        },
+        Event::OrderReplaced(replaced) => RpcSubscribeResponse {
+            response: Some(rpc_subscribe_response::Response::Replaced(
+                rpc_subscribe_response::OrderReplaced {
+                    order_id: replaced.order_id,
+                    ticker: replaced.ticker,
+                    direction: wrap_direction(replaced.direction),
+                    limit_price: wrap_decimal(replaced.limit_price),
+                    size: replaced.resting_size,
+                },
+            )),
+        },
+        Event::TickerStatus(TickerStatus { ticker, state }) => RpcSubscribeResponse {
+            response: Some(rpc_subscribe_response::Response::TickerStatus(
+                rpc_subscribe_response::TickerStatus {
+                    ticker,
+                    state: wrap_ticker_state(state),
+                },
+            )),
+        },
+        Event::MarketStatus(MarketStatus { phase }) => RpcSubscribeResponse {
+            response: Some(rpc_subscribe_response::Response::MarketStatus(
+                rpc_subscribe_response::MarketStatus {
+                    phase: wrap_session_phase(phase),
+                },
+            )),
+        },
+        Event::AuctionIndicative(AuctionIndicative {
+            ticker,
+            indicative_price,
+            matched_size,
+            imbalance_size,
+            imbalance_direction,
+        }) => RpcSubscribeResponse {
+            response: Some(rpc_subscribe_response::Response::AuctionIndicative(
+                rpc_subscribe_response::AuctionIndicative {
+                    ticker,
+                    indicative_price: indicative_price.and_then(wrap_decimal),
+                    matched_size,
+                    imbalance_size,
+                    imbalance_direction: imbalance_direction.map_or(0, wrap_direction),
+                },
+            )),
+        },
+    }
+}
+
+// Admin rpc
+
+// parse rpc set ticker state request to (ticker, state)
+pub fn parse_set_ticker_state_request(
+    request: RpcSetTickerStateRequest,
+) -> Result<(Ticker, TickerState), String> {
+    Ok((request.ticker, parse_ticker_state(request.state)?))
+}
+
+pub fn wrap_set_ticker_state_response(
+    ticker: Ticker,
+    state: TickerState,
+) -> RpcSetTickerStateResponse {
+    RpcSetTickerStateResponse {
+        ticker,
+        state: wrap_ticker_state(state),
+    }
+}
+
+// Config fixtures shared by the tests of the portal and of the server
+#[cfg(test)]
+pub mod test_support {
+    // one stock, a buyer with cash and a seller with shares
+    pub const STOCKS: &str = r#"{"stocks": [
+        {"ticker": "XYZ", "close_price": 10.0, "lot_size": 1, "mpf": 0.01, "name": "XYZ Corp."}
+    ]}"#;
+    pub const INVESTORS: &str = r#"{"investors": [
+        {"inv_id": 1, "account_name": "Alice", "password": "a", "stocks": {}, "cash_amount": 100000.0},
+        {"inv_id": 2, "account_name": "Bob", "password": "b", "stocks": {"XYZ": 10000}, "cash_amount": 0}
+    ]}"#;
+
+    // Write config contents to temporary files named after a test, return the paths of the investor list
+    // and of the stock list
+    pub fn write_config(name: &str, investors: &str, stocks: &str) -> (String, String) {
+        let dir = std::env::temp_dir();
+        let investor_path = dir.join(format!("ses_{}_{}_inv.json", name, std::process::id()));
+        let stock_path = dir.join(format!("ses_{}_{}_stock.json", name, std::process::id()));
+        std::fs::write(&investor_path, investors).unwrap();
+        std::fs::write(&stock_path, stocks).unwrap();
+        (
+            investor_path.to_string_lossy().to_string(),
+            stock_path.to_string_lossy().to_string(),
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn new_order(new_order: NewOrder) -> RpcOrderRequest {
+        RpcOrderRequest {
+            request: Some(rpc_order_request::Request::NewOrder(new_order)),
+        }
+    }
+
+    // Test that an out-of-range enum value from a client is an error, not a panic
+    #[test]
+    fn test_parse_invalid_enum() {
+        let post_only = new_order(NewOrder {
+            post_only: 7,
+            ..Default::default()
+        });
+        assert_eq!(
+            parse_order_request(1, post_only).err(),
+            Some("invalid post only: 7".to_string())
+        );
+        let self_trade_prevention = new_order(NewOrder {
+            self_trade_prevention: 9,
+            ..Default::default()
+        });
+        assert_eq!(
+            parse_order_request(1, self_trade_prevention).err(),
+            Some("invalid self trade prevention: 9".to_string())
+        );
+    }
+
+    // Test that a request that is not an order request is an error, not a panic
+    #[test]
+    fn test_parse_invalid_request() {
+        let empty = RpcOrderRequest { request: None };
+        assert_eq!(parse_seqnum(&empty), Err("empty request".to_string()));
+        assert_eq!(
+            parse_order_request(1, empty).err(),
+            Some("empty request".to_string())
+        );
+        let login = RpcOrderRequest {
+            request: Some(rpc_order_request::Request::Login(Default::default())),
+        };
+        assert_eq!(parse_seqnum(&login), Ok(0));
+        assert_eq!(
+            parse_order_request(1, login).err(),
+            Some("already logged in".to_string())
+        );
+        let direction = new_order(NewOrder {
+            direction: 2,
+            ..Default::default()
+        });
+        assert_eq!(
+            parse_order_request(1, direction).err(),
+            Some("invalid direction: 2".to_string())
+        );
     }
 }